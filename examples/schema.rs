@@ -1,7 +1,7 @@
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 use std::env::current_dir;
 use std::fs::create_dir_all;
-use terra_chess::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
+use terra_chess::msg::{DrawClaimsResponse, ExecuteMsg, InstantiateMsg, MoveResponse, QueryMsg};
 use terra_chess::state::ChessMove;
 
 fn main() {
@@ -14,4 +14,6 @@ fn main() {
     export_schema(&schema_for!(ExecuteMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
     export_schema(&schema_for!(ChessMove), &out_dir);
+    export_schema(&schema_for!(DrawClaimsResponse), &out_dir);
+    export_schema(&schema_for!(MoveResponse), &out_dir);
 }
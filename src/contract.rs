@@ -1,12 +1,18 @@
 #![allow(clippy::many_single_char_names)]
 use cosmwasm_std::entry_point;
-use cosmwasm_std::{to_binary, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult};
+use cosmwasm_std::{
+    to_binary, Addr, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult,
+    Storage,
+};
 use std::result::Result;
 
-use crate::engine::Game as ChessGame;
+use crate::engine::{Color, Game as ChessGame, VictoryStatus, BOARD_SIZE};
 use crate::error::ContractError;
-use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
-use crate::state::{ChessMove, ADMIN, MATCHS};
+use crate::msg::{DrawClaimsResponse, ExecuteMsg, InstantiateMsg, MoveResponse, QueryMsg};
+use crate::state::{
+    AgreedMoves, ChessMove, FinishedMatch, Promotion, ADMIN, AGREED, FINISHED, MATCHS,
+    ONE_STEP_ADMIN, PENDING_ADMIN, START_POSITION,
+};
 use cw0::maybe_addr;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
@@ -18,6 +24,12 @@ pub fn instantiate(
 ) -> Result<Response, ContractError> {
     let api = deps.api;
     ADMIN.set(deps.branch(), maybe_addr(api, msg.admin)?)?;
+    ONE_STEP_ADMIN.save(deps.storage, &msg.one_step_admin_update.unwrap_or(true))?;
+    if let Some(fen) = msg.start_fen {
+        // Both kings are needed to tell check, so make sure the position has them.
+        ChessGame::from_fen(&fen)?.try_check_victory()?;
+        START_POSITION.save(deps.storage, &fen)?;
+    }
     Ok(Response::default())
 }
 
@@ -31,8 +43,13 @@ pub fn execute(
     let api = deps.api;
     match msg {
         ExecuteMsg::UpdateAdmin { admin } => {
+            if !ONE_STEP_ADMIN.may_load(deps.storage)?.unwrap_or(true) {
+                return Err(ContractError::OneStepAdminDisabled {});
+            }
             Ok(ADMIN.execute_update_admin(deps, info, maybe_addr(api, admin)?)?)
         }
+        ExecuteMsg::ProposeAdmin { new_admin } => try_propose_admin(deps, info, new_admin),
+        ExecuteMsg::AcceptAdmin {} => try_accept_admin(deps, info),
         ExecuteMsg::StartMatch {
             opponent,
             first_move,
@@ -41,65 +58,373 @@ pub fn execute(
             host,
             opponent,
             your_move,
-        } => try_make_move(deps, info, host, opponent, your_move),
+            ply,
+        } => try_make_move(deps, info, host, opponent, your_move, ply),
+        ExecuteMsg::PlayMoves {
+            host,
+            opponent,
+            moves,
+            ply,
+        } => try_play_moves(deps, info, host, opponent, moves, ply),
+        ExecuteMsg::AgreeMoves {
+            host,
+            opponent,
+            moves,
+            ply,
+        } => try_agree_moves(deps, info, host, opponent, moves, ply),
+        ExecuteMsg::Rematch { previous_opponent } => try_rematch(deps, info, previous_opponent),
+        ExecuteMsg::ClaimInsufficientMaterialDraw { host, opponent } => {
+            try_claim_insufficient_material_draw(deps, info, host, opponent)
+        }
     }
 }
 
+pub fn try_propose_admin(
+    deps: DepsMut,
+    info: MessageInfo,
+    new_admin: String,
+) -> Result<Response, ContractError> {
+    ADMIN.assert_admin(deps.as_ref(), &info.sender)?;
+    let new_admin_checked = deps.api.addr_validate(&new_admin)?;
+    PENDING_ADMIN.save(deps.storage, &new_admin_checked)?;
+
+    Ok(Response::new())
+}
+
+pub fn try_accept_admin(mut deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let pending = PENDING_ADMIN
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NoPendingAdmin {})?;
+    if info.sender != pending {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    ADMIN.set(deps.branch(), Some(pending))?;
+    PENDING_ADMIN.remove(deps.storage);
+
+    Ok(Response::new())
+}
+
 pub fn try_make_move(
     deps: DepsMut,
-    _info: MessageInfo,
+    info: MessageInfo,
     host: String,
     opponent: String,
     your_move: ChessMove,
+    ply: u32,
 ) -> Result<Response, ContractError> {
+    check_move(&your_move)?;
     let host_checked = deps.api.addr_validate(&host)?;
     let opponent_checked = deps.api.addr_validate(&opponent)?;
-    let mut game = ChessGame::new();
-
-    let mut moves_made = MATCHS.load(deps.storage, (&host_checked, &opponent_checked))?;
+    if info.sender != host_checked && info.sender != opponent_checked {
+        return Err(ContractError::Unauthorized {});
+    }
 
-    for x in &moves_made {
-        let (u, v) = x.original;
-        let (w, z) = x.new;
-        let pos_start = (u as usize, v as usize);
-        let pos_end = (w as usize, z as usize);
-        game.move_piece(pos_start, pos_end);
+    let mut moves_made = load_match(deps.storage, &host_checked, &opponent_checked)?;
+    if moves_made.len() != ply as usize {
+        return Err(ContractError::StaleMove {});
     }
+    let mut game = replay(deps.storage, &moves_made)?;
     // Game state now rebuilt
 
-    let (u, v) = your_move.original;
-    let (w, z) = your_move.new;
-    let pos_start = (u as usize, v as usize);
-    let pos_end = (w as usize, z as usize);
-    let valid_moves = game.valid_moves(pos_start);
-    for i in &valid_moves {
-        let (_a, b) = i.last().unwrap();
-        if b == &pos_end {
-            game.move_piece(pos_start, pos_end);
-            moves_made.push(your_move);
-        };
+    check_turn(
+        &info.sender,
+        &game,
+        &host_checked,
+        &opponent_checked,
+        moves_made.len(),
+    )?;
+    check_piece(&game, &your_move)?;
+    play(&mut game, &your_move)?;
+    moves_made.push(your_move);
+
+    let response = save_progress(deps, &host_checked, &opponent_checked, &game, moves_made)?;
+    Ok(Response::new().set_data(to_binary(&response)?))
+}
+
+pub fn try_play_moves(
+    deps: DepsMut,
+    info: MessageInfo,
+    host: String,
+    opponent: String,
+    moves: Vec<ChessMove>,
+    ply: u32,
+) -> Result<Response, ContractError> {
+    let host_checked = deps.api.addr_validate(&host)?;
+    let opponent_checked = deps.api.addr_validate(&opponent)?;
+    if info.sender != host_checked && info.sender != opponent_checked {
+        return Err(ContractError::Unauthorized {});
     }
 
-    match game.check_victory() {
-        Some(_) => MATCHS.remove(deps.storage, (&host_checked, &opponent_checked)),
-        None => MATCHS.save(
-            deps.storage,
-            (&host_checked, &opponent_checked),
-            &moves_made,
-        )?,
+    let mut moves_made = load_match(deps.storage, &host_checked, &opponent_checked)?;
+    if moves_made.len() != ply as usize {
+        return Err(ContractError::StaleMove {});
+    }
+    let mut game = replay(deps.storage, &moves_made)?;
+
+    // With the other player's agreement the batch can hold their moves too. Each move still has
+    // to be for the side to move, so the turns alternate.
+    let other = if info.sender == host_checked {
+        &opponent_checked
+    } else {
+        &host_checked
     };
+    let agreed = AGREED
+        .may_load(deps.storage, (&host_checked, &opponent_checked))?
+        .filter(|a| &a.player == other && a.ply == ply && a.moves == moves)
+        .is_some();
 
+    for m in moves {
+        check_move(&m)?;
+        if !agreed {
+            check_turn(
+                &info.sender,
+                &game,
+                &host_checked,
+                &opponent_checked,
+                moves_made.len(),
+            )?;
+        }
+        if game.check_victory().is_some() {
+            return Err(ContractError::GameOver {});
+        }
+        check_piece(&game, &m)?;
+        play(&mut game, &m)?;
+        moves_made.push(m);
+    }
+
+    AGREED.remove(deps.storage, (&host_checked, &opponent_checked));
+    let response = save_progress(deps, &host_checked, &opponent_checked, &game, moves_made)?;
+    Ok(Response::new().set_data(to_binary(&response)?))
+}
+
+pub fn try_agree_moves(
+    deps: DepsMut,
+    info: MessageInfo,
+    host: String,
+    opponent: String,
+    moves: Vec<ChessMove>,
+    ply: u32,
+) -> Result<Response, ContractError> {
+    let host_checked = deps.api.addr_validate(&host)?;
+    let opponent_checked = deps.api.addr_validate(&opponent)?;
+    if info.sender != host_checked && info.sender != opponent_checked {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let moves_made = load_match(deps.storage, &host_checked, &opponent_checked)?;
+    if moves_made.len() != ply as usize {
+        return Err(ContractError::StaleMove {});
+    }
+    for m in &moves {
+        check_move(m)?;
+    }
+
+    AGREED.save(
+        deps.storage,
+        (&host_checked, &opponent_checked),
+        &AgreedMoves {
+            player: info.sender,
+            ply,
+            moves,
+        },
+    )?;
+    Ok(Response::new())
+}
+
+pub fn try_claim_insufficient_material_draw(
+    deps: DepsMut,
+    info: MessageInfo,
+    host: String,
+    opponent: String,
+) -> Result<Response, ContractError> {
+    let host_checked = deps.api.addr_validate(&host)?;
+    let opponent_checked = deps.api.addr_validate(&opponent)?;
+    if info.sender != host_checked && info.sender != opponent_checked {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let moves_made = load_match(deps.storage, &host_checked, &opponent_checked)?;
+    let game = replay(deps.storage, &moves_made)?;
+    if !game.insufficient_material() {
+        return Err(ContractError::DrawNotClaimable {});
+    }
+
+    finish_match(
+        deps,
+        &host_checked,
+        &opponent_checked,
+        moves_made,
+        VictoryStatus::Draw,
+        None,
+    )?;
     Ok(Response::new())
 }
 
+/// Loads the moves of a running match, telling a match that has already ended apart from one that
+/// was never started.
+fn load_match(
+    storage: &dyn Storage,
+    host: &Addr,
+    opponent: &Addr,
+) -> Result<Vec<ChessMove>, ContractError> {
+    match MATCHS.may_load(storage, (host, opponent))? {
+        Some(moves) => Ok(moves),
+        None if FINISHED.has(storage, (host, opponent)) => Err(ContractError::GameOver {}),
+        None => Err(ContractError::GameNotFound {}),
+    }
+}
+
+/// Checks that a move is well formed before the game is rebuilt: both squares are on the board, it
+/// goes somewhere and it only names a promotion piece if it ends on the first or last rank.
+fn check_move(m: &ChessMove) -> Result<(), ContractError> {
+    m.validate()?;
+    let last_rank = m.new.1 == 0 || m.new.1 as usize == BOARD_SIZE - 1;
+    if m.original == m.new || (m.promotion.is_some() && !last_rank) {
+        return Err(ContractError::InvalidMove {});
+    }
+    Ok(())
+}
+
+/// Returns the player with the given color. The host makes the first move, so the host plays the
+/// side to move in the start position, which is black if the configured FEN has black to move.
+fn player_with<'p>(game: &ChessGame, color: Color, host: &'p Addr, opponent: &'p Addr) -> &'p Addr {
+    if color == game.color_to_move_after(0) {
+        host
+    } else {
+        opponent
+    }
+}
+
+/// Checks that it is the sender's turn after the given number of moves.
+fn check_turn(
+    sender: &Addr,
+    game: &ChessGame,
+    host: &Addr,
+    opponent: &Addr,
+    moves_made: usize,
+) -> Result<(), ContractError> {
+    let player = player_with(game, game.color_to_move_after(moves_made), host, opponent);
+    if sender != player {
+        return Err(ContractError::NotYourTurn {});
+    }
+    Ok(())
+}
+
+/// Checks that the piece a move starts from belongs to the side to move, so a player can't move
+/// the opponent's pieces.
+fn check_piece(game: &ChessGame, m: &ChessMove) -> Result<(), ContractError> {
+    let from = (m.original.0 as usize, m.original.1 as usize);
+    if !game.can_player_move(from, game.whose_turn()) {
+        return Err(ContractError::NotYourTurn {});
+    }
+    Ok(())
+}
+
+/// Plays a move sent to the contract for the side to move. A castle is sent as the king's move,
+/// like e1 to g1, and a promotion without a piece turns the pawn into a queen.
+fn play(game: &mut ChessGame, m: &ChessMove) -> Result<(), ContractError> {
+    let from = (m.original.0 as usize, m.original.1 as usize);
+    let to = (m.new.0 as usize, m.new.1 as usize);
+    game.play(from, to, m.promotion.map(Promotion::kind))
+        .map_err(|_| ContractError::IllegalMove {})?;
+    Ok(())
+}
+
+/// Rebuilds a game from the moves stored for a match, starting from the position configured at
+/// instantiation.
+fn replay<'a>(storage: &dyn Storage, moves: &[ChessMove]) -> Result<ChessGame<'a>, ContractError> {
+    let mut game = match START_POSITION.may_load(storage)? {
+        Some(fen) => ChessGame::from_fen(&fen)?,
+        None => ChessGame::new(),
+    };
+    game.record_san(false);
+    let moves: Vec<_> = moves
+        .iter()
+        .map(|m| {
+            let (u, v) = m.original;
+            let (w, z) = m.new;
+            (
+                (u as usize, v as usize),
+                (w as usize, z as usize),
+                m.promotion.map(Promotion::kind),
+            )
+        })
+        .collect();
+    Ok(game.rebuild_from_moves_with_history(&moves))
+}
+
+/// Saves the moves of a match, moving it to the finished matches if the game is over.
+fn save_progress(
+    deps: DepsMut,
+    host: &Addr,
+    opponent: &Addr,
+    game: &ChessGame,
+    moves_made: Vec<ChessMove>,
+) -> StdResult<MoveResponse> {
+    match game.check_victory() {
+        Some((status, color)) => {
+            let winner = match status {
+                VictoryStatus::Checkmate => Some(player_with(game, color, host, opponent).clone()),
+                _ => None,
+            };
+            let response = MoveResponse {
+                game_over: true,
+                result: Some(status.to_string()),
+                winner: winner.clone(),
+            };
+            finish_match(deps, host, opponent, moves_made, status, winner)?;
+            Ok(response)
+        }
+        None => {
+            MATCHS.save(deps.storage, (host, opponent), &moves_made)?;
+            Ok(MoveResponse {
+                game_over: false,
+                result: None,
+                winner: None,
+            })
+        }
+    }
+}
+
+/// Moves a match from the running matches to the finished ones.
+fn finish_match(
+    deps: DepsMut,
+    host: &Addr,
+    opponent: &Addr,
+    moves_made: Vec<ChessMove>,
+    status: VictoryStatus,
+    winner: Option<Addr>,
+) -> StdResult<()> {
+    MATCHS.remove(deps.storage, (host, opponent));
+    AGREED.remove(deps.storage, (host, opponent));
+    FINISHED.save(
+        deps.storage,
+        (host, opponent),
+        &FinishedMatch {
+            moves: moves_made,
+            result: status.to_string(),
+            winner,
+        },
+    )
+}
+
 pub fn try_start_match(
     deps: DepsMut,
     info: MessageInfo,
     opponent: String,
     first_move: ChessMove,
 ) -> Result<Response, ContractError> {
+    check_move(&first_move)?;
     let host = info.sender;
     let opponent_checked = deps.api.addr_validate(&opponent)?;
+    if opponent_checked == host {
+        return Err(ContractError::SelfPlay {});
+    }
+
+    // The match has to open with a legal move for the side to move in the start position.
+    let mut game = replay(deps.storage, &[])?;
+    play(&mut game, &first_move)?;
     let moves = vec![first_move];
 
     MATCHS.save(deps.storage, (&host, &opponent_checked), &moves)?;
@@ -107,14 +432,60 @@ pub fn try_start_match(
     Ok(Response::new())
 }
 
+pub fn try_rematch(
+    deps: DepsMut,
+    info: MessageInfo,
+    previous_opponent: String,
+) -> Result<Response, ContractError> {
+    let player = info.sender;
+    let previous_opponent_checked = deps.api.addr_validate(&previous_opponent)?;
+
+    // The host plays the side to move in the start position, so the previous opponent hosts the
+    // rematch to swap colors.
+    let (host, opponent) = if FINISHED.has(deps.storage, (&previous_opponent_checked, &player)) {
+        (player, previous_opponent_checked)
+    } else if FINISHED.has(deps.storage, (&player, &previous_opponent_checked)) {
+        (previous_opponent_checked, player)
+    } else {
+        return Err(ContractError::NoFinishedMatch {});
+    };
+    if MATCHS.has(deps.storage, (&host, &opponent)) || MATCHS.has(deps.storage, (&opponent, &host))
+    {
+        return Err(ContractError::MatchInProgress {});
+    }
+
+    // Only the latest result between the two is kept, so it decides who hosts the next rematch.
+    FINISHED.remove(deps.storage, (&opponent, &host));
+    MATCHS.save(deps.storage, (&host, &opponent), &Vec::new())?;
+
+    Ok(Response::new())
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::GetAdmin {} => to_binary(&ADMIN.query_admin(deps)?),
         QueryMsg::CheckMatch { host, opponent } => to_binary(&query_match(deps, host, opponent)?),
+        QueryMsg::DrawClaims { host, opponent } => {
+            to_binary(&query_draw_claims(deps, host, opponent)?)
+        }
     }
 }
 
+fn query_draw_claims(deps: Deps, host: String, opponent: String) -> StdResult<DrawClaimsResponse> {
+    let host_checked = deps.api.addr_validate(&host)?;
+    let opponent_checked = deps.api.addr_validate(&opponent)?;
+    let moves_made = MATCHS.load(deps.storage, (&host_checked, &opponent_checked))?;
+    let game =
+        replay(deps.storage, &moves_made).map_err(|err| StdError::generic_err(err.to_string()))?;
+
+    Ok(DrawClaimsResponse {
+        fifty_move: game.can_claim_fifty_move(),
+        threefold_repetition: game.three_fold_repetition(),
+        insufficient_material: game.insufficient_material(),
+    })
+}
+
 fn query_match(deps: Deps, host: String, opponent: String) -> StdResult<Vec<String>> {
     let host_checked = deps.api.addr_validate(&host)?;
     let opponent_checked = deps.api.addr_validate(&opponent)?;
@@ -126,13 +497,13 @@ fn query_match(deps: Deps, host: String, opponent: String) -> StdResult<Vec<Stri
         let (w, v) = item.new;
         let line = String::from("Move made from (")
             + &x.to_string()
-            + &",".to_owned()
+            + ","
             + &y.to_string()
-            + &") to (".to_owned()
+            + ") to ("
             + &w.to_string()
-            + &",".to_owned()
+            + ","
             + &v.to_string()
-            + &")".to_owned();
+            + ")";
         string.push(line);
     }
 
@@ -142,9 +513,37 @@ fn query_match(deps: Deps, host: String, opponent: String) -> StdResult<Vec<Stri
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::engine::{MissingKing, WHITE};
+    use crate::error::InvalidCoord;
     use crate::state::ChessMove;
     use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
     use cosmwasm_std::{coins, from_binary};
+    use cw_controllers::AdminError;
+
+    /// Plays the moves one at a time with `PlayMove`, each sent by the player whose turn it is,
+    /// starting after `ply` moves. Returns the response to the last move.
+    fn play_in_turn(
+        mut deps: DepsMut,
+        moves: &[ChessMove],
+        ply: u32,
+    ) -> Result<Response, ContractError> {
+        let mut res = Response::new();
+        for (i, &your_move) in moves.iter().enumerate() {
+            let ply = ply + i as u32;
+            let sender = match ply % 2 {
+                0 => "mario",
+                _ => "bowser",
+            };
+            let msg = ExecuteMsg::PlayMove {
+                host: String::from("mario"),
+                opponent: String::from("bowser"),
+                your_move,
+                ply,
+            };
+            res = execute(deps.branch(), mock_env(), mock_info(sender, &[]), msg)?;
+        }
+        Ok(res)
+    }
 
     #[test]
     fn humble_chess_test() {
@@ -154,11 +553,12 @@ mod tests {
         let opening = ChessMove {
             original: (3, 1),
             new: (3, 3),
+            promotion: None,
         };
         let info = mock_info("mario", &coins(1000, "coins"));
         let opponent = String::from("bowser");
         let msg = ExecuteMsg::StartMatch {
-            opponent: opponent,
+            opponent,
             first_move: opening,
         };
         let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
@@ -168,22 +568,1050 @@ mod tests {
         let mov = ChessMove {
             original: (4, 6),
             new: (4, 4),
+            promotion: None,
         };
         let msg = ExecuteMsg::PlayMove {
-            host: host,
+            host,
             opponent: info.sender.to_string(),
             your_move: mov,
+            ply: 1,
         };
         let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
 
         let info = mock_info("mario", &coins(1000, "coins"));
         let opponent = String::from("bowser");
         let msg = QueryMsg::CheckMatch {
-            opponent: opponent,
+            opponent,
             host: info.sender.to_string(),
         };
         let res = query(deps.as_ref(), mock_env(), msg).unwrap();
         let decoded: Vec<String> = from_binary(&res).unwrap();
         println!("{:?}", decoded);
     }
+
+    #[test]
+    fn two_step_admin_transfer() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = InstantiateMsg {
+            admin: Some(String::from("peach")),
+            one_step_admin_update: Some(false),
+            start_fen: None,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("peach", &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::UpdateAdmin {
+            admin: Some(String::from("toad")),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("peach", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::OneStepAdminDisabled {});
+
+        let msg = ExecuteMsg::AcceptAdmin {};
+        let err = execute(deps.as_mut(), mock_env(), mock_info("toad", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::NoPendingAdmin {});
+
+        let msg = ExecuteMsg::ProposeAdmin {
+            new_admin: String::from("toad"),
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("toad", &[]),
+            msg.clone(),
+        );
+        assert_eq!(
+            err.unwrap_err(),
+            ContractError::Admin(AdminError::NotAdmin {})
+        );
+        execute(deps.as_mut(), mock_env(), mock_info("peach", &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::AcceptAdmin {};
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bowser", &[]),
+            msg.clone(),
+        );
+        assert_eq!(err.unwrap_err(), ContractError::Unauthorized {});
+        assert_eq!(
+            ADMIN.get(deps.as_ref()).unwrap(),
+            Some(Addr::unchecked("peach"))
+        );
+
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("toad", &[]),
+            msg.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            ADMIN.get(deps.as_ref()).unwrap(),
+            Some(Addr::unchecked("toad"))
+        );
+        let err = execute(deps.as_mut(), mock_env(), mock_info("toad", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::NoPendingAdmin {});
+    }
+
+    #[test]
+    fn one_step_admin_update_by_default() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = InstantiateMsg {
+            admin: Some(String::from("peach")),
+            one_step_admin_update: None,
+            start_fen: None,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("peach", &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::UpdateAdmin {
+            admin: Some(String::from("toad")),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("peach", &[]), msg).unwrap();
+        assert_eq!(
+            ADMIN.get(deps.as_ref()).unwrap(),
+            Some(Addr::unchecked("toad"))
+        );
+    }
+
+    #[test]
+    fn rematch_swaps_colors() {
+        let mut deps = mock_dependencies(&[]);
+
+        let msg = ExecuteMsg::Rematch {
+            previous_opponent: String::from("mario"),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("bowser", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::NoFinishedMatch {});
+
+        // Fool's mate, mario hosting as white.
+        let msg = ExecuteMsg::StartMatch {
+            opponent: String::from("bowser"),
+            first_move: ChessMove {
+                original: (5, 1),
+                new: (5, 2),
+                promotion: None,
+            },
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap();
+        for &(ply, player, original, new) in &[
+            (1, "bowser", (4, 6), (4, 4)),
+            (2, "mario", (6, 1), (6, 3)),
+            (3, "bowser", (3, 7), (7, 3)),
+        ] {
+            let msg = ExecuteMsg::PlayMove {
+                host: String::from("mario"),
+                opponent: String::from("bowser"),
+                your_move: ChessMove {
+                    original,
+                    new,
+                    promotion: None,
+                },
+                ply,
+            };
+            execute(deps.as_mut(), mock_env(), mock_info(player, &[]), msg).unwrap();
+        }
+
+        let mario = Addr::unchecked("mario");
+        let bowser = Addr::unchecked("bowser");
+        assert!(!MATCHS.has(&deps.storage, (&mario, &bowser)));
+        let finished = FINISHED.load(&deps.storage, (&mario, &bowser)).unwrap();
+        assert_eq!(finished.result, "checkmate");
+        assert_eq!(finished.winner, Some(bowser.clone()));
+
+        // Bowser was black, so the rematch has bowser hosting as white.
+        let msg = ExecuteMsg::Rematch {
+            previous_opponent: String::from("mario"),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("bowser", &[]), msg).unwrap();
+        assert!(!FINISHED.has(&deps.storage, (&mario, &bowser)));
+        assert_eq!(
+            MATCHS.load(&deps.storage, (&bowser, &mario)).unwrap(),
+            Vec::<ChessMove>::new()
+        );
+
+        let msg = ExecuteMsg::PlayMove {
+            host: String::from("bowser"),
+            opponent: String::from("mario"),
+            your_move: ChessMove {
+                original: (4, 1),
+                new: (4, 3),
+                promotion: None,
+            },
+            ply: 0,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("bowser", &[]), msg).unwrap();
+        assert_eq!(
+            MATCHS.load(&deps.storage, (&bowser, &mario)).unwrap().len(),
+            1
+        );
+
+        // The rematch can only be claimed once.
+        let msg = ExecuteMsg::Rematch {
+            previous_opponent: String::from("bowser"),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::NoFinishedMatch {});
+    }
+
+    #[test]
+    fn rematch_keeps_running_matches() {
+        let mut deps = mock_dependencies(&[]);
+        let mario = Addr::unchecked("mario");
+        let bowser = Addr::unchecked("bowser");
+        FINISHED
+            .save(
+                &mut deps.storage,
+                (&mario, &bowser),
+                &FinishedMatch {
+                    moves: vec![],
+                    result: VictoryStatus::Draw.to_string(),
+                    winner: None,
+                },
+            )
+            .unwrap();
+
+        // A new match with either player hosting blocks the rematch.
+        for &(host, opponent) in &[("mario", "bowser"), ("bowser", "mario")] {
+            let msg = ExecuteMsg::StartMatch {
+                opponent: String::from(opponent),
+                first_move: ChessMove::new((4, 1), (4, 3)).unwrap(),
+            };
+            execute(deps.as_mut(), mock_env(), mock_info(host, &[]), msg).unwrap();
+            let msg = ExecuteMsg::Rematch {
+                previous_opponent: String::from("mario"),
+            };
+            let err =
+                execute(deps.as_mut(), mock_env(), mock_info("bowser", &[]), msg).unwrap_err();
+            assert_eq!(err, ContractError::MatchInProgress {});
+            let host = Addr::unchecked(host);
+            let opponent = Addr::unchecked(opponent);
+            assert_eq!(
+                MATCHS
+                    .load(&deps.storage, (&host, &opponent))
+                    .unwrap()
+                    .len(),
+                1
+            );
+            MATCHS.remove(&mut deps.storage, (&host, &opponent));
+        }
+        assert!(FINISHED.has(&deps.storage, (&mario, &bowser)));
+    }
+
+    #[test]
+    fn off_board_moves_are_rejected() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = ExecuteMsg::StartMatch {
+            opponent: String::from("bowser"),
+            first_move: ChessMove {
+                original: (4, 1),
+                new: (4, 8),
+                promotion: None,
+            },
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::InvalidCoord(InvalidCoord(4, 8)));
+
+        let msg = ExecuteMsg::StartMatch {
+            opponent: String::from("bowser"),
+            first_move: ChessMove {
+                original: (4, 1),
+                new: (4, 3),
+                promotion: None,
+            },
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::PlayMove {
+            host: String::from("mario"),
+            opponent: String::from("bowser"),
+            your_move: ChessMove {
+                original: (200, 6),
+                new: (4, 4),
+                promotion: None,
+            },
+            ply: 1,
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("bowser", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::InvalidCoord(InvalidCoord(200, 6)));
+
+        // A move has to go somewhere.
+        let msg = ExecuteMsg::PlayMove {
+            host: String::from("mario"),
+            opponent: String::from("bowser"),
+            your_move: ChessMove::new((4, 6), (4, 6)).unwrap(),
+            ply: 1,
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("bowser", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::InvalidMove {});
+    }
+
+    #[test]
+    fn stale_moves_are_rejected() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = ExecuteMsg::StartMatch {
+            opponent: String::from("bowser"),
+            first_move: ChessMove {
+                original: (4, 1),
+                new: (4, 3),
+                promotion: None,
+            },
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap();
+
+        let reply = ExecuteMsg::PlayMove {
+            host: String::from("mario"),
+            opponent: String::from("bowser"),
+            your_move: ChessMove {
+                original: (4, 6),
+                new: (4, 4),
+                promotion: None,
+            },
+            ply: 1,
+        };
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bowser", &[]),
+            reply.clone(),
+        )
+        .unwrap();
+
+        // Resubmitting the same message doesn't play the move twice.
+        let err = execute(deps.as_mut(), mock_env(), mock_info("bowser", &[]), reply).unwrap_err();
+        assert_eq!(err, ContractError::StaleMove {});
+
+        let ahead = ExecuteMsg::PlayMove {
+            host: String::from("mario"),
+            opponent: String::from("bowser"),
+            your_move: ChessMove {
+                original: (6, 0),
+                new: (5, 2),
+                promotion: None,
+            },
+            ply: 3,
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), ahead).unwrap_err();
+        assert_eq!(err, ContractError::StaleMove {});
+
+        let mario = Addr::unchecked("mario");
+        let bowser = Addr::unchecked("bowser");
+        assert_eq!(
+            MATCHS.load(&deps.storage, (&mario, &bowser)).unwrap().len(),
+            2
+        );
+    }
+
+    #[test]
+    fn play_moves_batch() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = ExecuteMsg::StartMatch {
+            opponent: String::from("bowser"),
+            first_move: ChessMove::new((4, 1), (4, 3)).unwrap(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap();
+        let mario = Addr::unchecked("mario");
+        let bowser = Addr::unchecked("bowser");
+
+        let batch = |moves: Vec<ChessMove>, ply: u32| ExecuteMsg::PlayMoves {
+            host: String::from("mario"),
+            opponent: String::from("bowser"),
+            moves,
+            ply,
+        };
+        let agree = |moves: Vec<ChessMove>, ply: u32| ExecuteMsg::AgreeMoves {
+            host: String::from("mario"),
+            opponent: String::from("bowser"),
+            moves,
+            ply,
+        };
+        let e5_nf3 = vec![
+            ChessMove::new((4, 6), (4, 4)).unwrap(),
+            ChessMove::new((6, 0), (5, 2)).unwrap(),
+        ];
+
+        // White's reply needs white's agreement, and bowser agreeing to it doesn't count.
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bowser", &[]),
+            batch(e5_nf3.clone(), 1),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::NotYourTurn {});
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bowser", &[]),
+            agree(e5_nf3.clone(), 1),
+        )
+        .unwrap();
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bowser", &[]),
+            batch(e5_nf3.clone(), 1),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::NotYourTurn {});
+
+        // 1... e5 2. Nf3, agreed to by mario and sent by bowser.
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("mario", &[]),
+            agree(e5_nf3.clone(), 1),
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bowser", &[]),
+            batch(e5_nf3.clone(), 1),
+        )
+        .unwrap();
+        let moves = MATCHS.load(&deps.storage, (&mario, &bowser)).unwrap();
+        assert_eq!(moves.len(), 3);
+        assert_eq!(moves[1..], e5_nf3[..]);
+        assert!(!AGREED.has(&deps.storage, (&mario, &bowser)));
+
+        // Nobody outside the match can play, and white can't play black's move.
+        let nc6 = ChessMove::new((1, 7), (2, 5)).unwrap();
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("luigi", &[]),
+            batch(vec![nc6], 3),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("mario", &[]),
+            batch(vec![nc6], 3),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::NotYourTurn {});
+
+        // The batch has to be the agreed one, and a move that isn't legal fails all of it. The
+        // pawn on a2 blocks Ra3.
+        let ra3 = ChessMove::new((0, 0), (0, 2)).unwrap();
+        let nc3 = ChessMove::new((1, 0), (2, 2)).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("mario", &[]),
+            agree(vec![nc6, ra3], 3),
+        )
+        .unwrap();
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bowser", &[]),
+            batch(vec![nc6, nc3], 3),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::NotYourTurn {});
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bowser", &[]),
+            batch(vec![nc6, ra3], 3),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::IllegalMove {});
+        assert_eq!(
+            MATCHS.load(&deps.storage, (&mario, &bowser)).unwrap(),
+            moves
+        );
+
+        // A batch of only the sender's own move needs no agreement.
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bowser", &[]),
+            batch(vec![nc6], 3),
+        )
+        .unwrap();
+        assert_eq!(
+            MATCHS.load(&deps.storage, (&mario, &bowser)).unwrap().len(),
+            4
+        );
+    }
+
+    #[test]
+    fn custom_start_position() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = InstantiateMsg {
+            admin: None,
+            one_step_admin_update: None,
+            start_fen: Some(String::from("not a fen")),
+        };
+        let err = instantiate(deps.as_mut(), mock_env(), mock_info("peach", &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidFen(_)));
+
+        let msg = InstantiateMsg {
+            admin: None,
+            one_step_admin_update: None,
+            start_fen: Some(String::from("6k1/8/8/8/8/8/8/R7 w - - 0 1")),
+        };
+        let err = instantiate(deps.as_mut(), mock_env(), mock_info("peach", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::MissingKing(MissingKing(Color::White)));
+
+        let msg = InstantiateMsg {
+            admin: None,
+            one_step_admin_update: None,
+            start_fen: Some(String::from("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1")),
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("peach", &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::StartMatch {
+            opponent: String::from("bowser"),
+            first_move: ChessMove::new((6, 0), (5, 0)).unwrap(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap();
+
+        // The back rank mate only exists in the configured position.
+        let moves = [
+            ChessMove::new((6, 7), (7, 7)).unwrap(),
+            ChessMove::new((0, 0), (0, 7)).unwrap(),
+        ];
+        play_in_turn(deps.as_mut(), &moves, 1).unwrap();
+
+        let mario = Addr::unchecked("mario");
+        let bowser = Addr::unchecked("bowser");
+        assert!(!MATCHS.has(&deps.storage, (&mario, &bowser)));
+        let finished = FINISHED.load(&deps.storage, (&mario, &bowser)).unwrap();
+        assert_eq!(finished.winner, Some(mario));
+        assert_eq!(finished.moves.len(), 3);
+
+        // Nothing more can be played once the match is over.
+        let msg = ExecuteMsg::PlayMove {
+            host: String::from("mario"),
+            opponent: String::from("bowser"),
+            your_move: ChessMove::new((7, 7), (6, 7)).unwrap(),
+            ply: 3,
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("bowser", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::GameOver {});
+    }
+
+    #[test]
+    fn black_to_move_start_position() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = InstantiateMsg {
+            admin: None,
+            one_step_admin_update: None,
+            start_fen: Some(String::from("r5k1/8/8/8/8/8/1P4PP/7K b - - 0 1")),
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("peach", &[]), msg).unwrap();
+
+        // The host moves first, so mario plays black.
+        let msg = ExecuteMsg::StartMatch {
+            opponent: String::from("bowser"),
+            first_move: ChessMove::new((1, 1), (1, 2)).unwrap(),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::IllegalMove {});
+        let msg = ExecuteMsg::StartMatch {
+            opponent: String::from("bowser"),
+            first_move: ChessMove::new((6, 7), (5, 7)).unwrap(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap();
+
+        // 2. b3 Ra1#, and the checkmate by black goes to mario.
+        let moves = [
+            ChessMove::new((1, 1), (1, 2)).unwrap(),
+            ChessMove::new((0, 7), (0, 0)).unwrap(),
+        ];
+        let res = play_in_turn(deps.as_mut(), &moves, 1).unwrap();
+        let response: MoveResponse = from_binary(&res.data.unwrap()).unwrap();
+        let mario = Addr::unchecked("mario");
+        let bowser = Addr::unchecked("bowser");
+        assert_eq!(response.winner, Some(mario.clone()));
+        let finished = FINISHED.load(&deps.storage, (&mario, &bowser)).unwrap();
+        assert_eq!(finished.result, VictoryStatus::Checkmate.to_string());
+        assert_eq!(finished.winner, Some(mario.clone()));
+
+        // In the rematch bowser hosts, and so plays black.
+        let msg = ExecuteMsg::Rematch {
+            previous_opponent: String::from("mario"),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("bowser", &[]), msg).unwrap();
+        let play = |your_move: ChessMove| ExecuteMsg::PlayMove {
+            host: String::from("bowser"),
+            opponent: String::from("mario"),
+            your_move,
+            ply: 0,
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("mario", &[]),
+            play(ChessMove::new((1, 1), (1, 2)).unwrap()),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::NotYourTurn {});
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bowser", &[]),
+            play(ChessMove::new((6, 7), (5, 7)).unwrap()),
+        )
+        .unwrap();
+        assert_eq!(
+            MATCHS.load(&deps.storage, (&bowser, &mario)).unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn missing_and_self_matches() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = ExecuteMsg::StartMatch {
+            opponent: String::from("mario"),
+            first_move: ChessMove::new((4, 1), (4, 3)).unwrap(),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::SelfPlay {});
+
+        let msg = ExecuteMsg::PlayMove {
+            host: String::from("mario"),
+            opponent: String::from("bowser"),
+            your_move: ChessMove::new((4, 1), (4, 3)).unwrap(),
+            ply: 0,
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::GameNotFound {});
+        let msg = ExecuteMsg::ClaimInsufficientMaterialDraw {
+            host: String::from("mario"),
+            opponent: String::from("bowser"),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("bowser", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::GameNotFound {});
+    }
+
+    #[test]
+    fn first_move_must_be_white() {
+        let mut deps = mock_dependencies(&[]);
+        let mario = Addr::unchecked("mario");
+        let bowser = Addr::unchecked("bowser");
+
+        for &(original, new) in &[((4, 6), (4, 4)), ((4, 1), (4, 4)), ((4, 3), (4, 4))] {
+            let msg = ExecuteMsg::StartMatch {
+                opponent: String::from("bowser"),
+                first_move: ChessMove::new(original, new).unwrap(),
+            };
+            let err = execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap_err();
+            assert_eq!(err, ContractError::IllegalMove {});
+            assert!(!MATCHS.has(&deps.storage, (&mario, &bowser)));
+        }
+
+        let msg = ExecuteMsg::StartMatch {
+            opponent: String::from("bowser"),
+            first_move: ChessMove::new((6, 0), (5, 2)).unwrap(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap();
+        assert!(MATCHS.has(&deps.storage, (&mario, &bowser)));
+    }
+
+    #[test]
+    fn claim_insufficient_material_draw() {
+        let mario = Addr::unchecked("mario");
+        let bowser = Addr::unchecked("bowser");
+        let claim = || ExecuteMsg::ClaimInsufficientMaterialDraw {
+            host: String::from("mario"),
+            opponent: String::from("bowser"),
+        };
+
+        for &(fen, claimable) in &[
+            ("4k3/8/8/8/8/8/8/4K3 w - - 0 1", true),
+            ("4k3/8/8/8/8/8/8/3QK3 w - - 0 1", false),
+        ] {
+            let mut deps = mock_dependencies(&[]);
+            let msg = InstantiateMsg {
+                admin: None,
+                one_step_admin_update: None,
+                start_fen: Some(String::from(fen)),
+            };
+            instantiate(deps.as_mut(), mock_env(), mock_info("peach", &[]), msg).unwrap();
+            let msg = ExecuteMsg::StartMatch {
+                opponent: String::from("bowser"),
+                first_move: ChessMove::new((4, 0), (4, 1)).unwrap(),
+            };
+            execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap();
+
+            let err =
+                execute(deps.as_mut(), mock_env(), mock_info("luigi", &[]), claim()).unwrap_err();
+            assert_eq!(err, ContractError::Unauthorized {});
+
+            let res = execute(deps.as_mut(), mock_env(), mock_info("bowser", &[]), claim());
+            if claimable {
+                res.unwrap();
+                assert!(!MATCHS.has(&deps.storage, (&mario, &bowser)));
+                let finished = FINISHED.load(&deps.storage, (&mario, &bowser)).unwrap();
+                assert_eq!(finished.result, "draw");
+                assert_eq!(finished.winner, None);
+            } else {
+                assert_eq!(res.unwrap_err(), ContractError::DrawNotClaimable {});
+                assert!(MATCHS.has(&deps.storage, (&mario, &bowser)));
+            }
+        }
+    }
+
+    #[test]
+    fn castling() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = ExecuteMsg::StartMatch {
+            opponent: String::from("bowser"),
+            first_move: ChessMove::new((4, 1), (4, 3)).unwrap(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap();
+        let moves = [
+            ChessMove::new((4, 6), (4, 4)).unwrap(),
+            ChessMove::new((6, 0), (5, 2)).unwrap(),
+            ChessMove::new((1, 7), (2, 5)).unwrap(),
+            ChessMove::new((5, 0), (2, 3)).unwrap(),
+            ChessMove::new((6, 7), (5, 5)).unwrap(),
+        ];
+        play_in_turn(deps.as_mut(), &moves, 1).unwrap();
+
+        // The king's two-square move castles.
+        let msg = ExecuteMsg::PlayMove {
+            host: String::from("mario"),
+            opponent: String::from("bowser"),
+            your_move: ChessMove::new((4, 0), (6, 0)).unwrap(),
+            ply: 6,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap();
+
+        let mario = Addr::unchecked("mario");
+        let bowser = Addr::unchecked("bowser");
+        let moves = MATCHS.load(&deps.storage, (&mario, &bowser)).unwrap();
+        assert_eq!(moves.len(), 7);
+        let game = replay(&deps.storage, &moves).unwrap();
+        assert_eq!(game.get_from_square("g1").unwrap(), Some(&WHITE[5]));
+        assert_eq!(game.get_from_square("f1").unwrap(), Some(&WHITE[1]));
+        assert_eq!(game.get_from_square("h1").unwrap(), None);
+        assert_eq!(game.get_from_square("e1").unwrap(), None);
+    }
+
+    #[test]
+    fn en_passant() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = ExecuteMsg::StartMatch {
+            opponent: String::from("bowser"),
+            first_move: ChessMove::new((4, 1), (4, 3)).unwrap(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap();
+        let moves = [
+            ChessMove::new((0, 6), (0, 5)).unwrap(),
+            ChessMove::new((4, 3), (4, 4)).unwrap(),
+            ChessMove::new((3, 6), (3, 4)).unwrap(),
+        ];
+        play_in_turn(deps.as_mut(), &moves, 1).unwrap();
+
+        let msg = ExecuteMsg::PlayMove {
+            host: String::from("mario"),
+            opponent: String::from("bowser"),
+            your_move: ChessMove::new((4, 4), (3, 5)).unwrap(),
+            ply: 4,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap();
+
+        let mario = Addr::unchecked("mario");
+        let bowser = Addr::unchecked("bowser");
+        let moves = MATCHS.load(&deps.storage, (&mario, &bowser)).unwrap();
+        assert_eq!(moves.len(), 5);
+        let game = replay(&deps.storage, &moves).unwrap();
+        assert_eq!(game.get_from_square("d6").unwrap(), Some(&WHITE[0]));
+        assert_eq!(game.get_from_square("d5").unwrap(), None);
+        assert_eq!(game.get_from_square("e5").unwrap(), None);
+    }
+
+    #[test]
+    fn underpromotion() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = InstantiateMsg {
+            admin: None,
+            one_step_admin_update: None,
+            start_fen: Some(String::from("6br/5Ppk/6pp/8/8/8/8/2K5 w - - 0 1")),
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("peach", &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::StartMatch {
+            opponent: String::from("bowser"),
+            first_move: ChessMove::new((2, 0), (3, 0)).unwrap(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap();
+
+        // A promotion piece only goes with a move to the first or last rank.
+        let msg = ExecuteMsg::PlayMoves {
+            host: String::from("mario"),
+            opponent: String::from("bowser"),
+            moves: vec![ChessMove::new((6, 5), (6, 4))
+                .unwrap()
+                .promote_to(Promotion::Queen)],
+            ply: 1,
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("bowser", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::InvalidMove {});
+
+        // A queen on f8 wouldn't give check, but a knight mates.
+        let moves = [
+            ChessMove::new((6, 5), (6, 4)).unwrap(),
+            ChessMove::new((5, 6), (5, 7))
+                .unwrap()
+                .promote_to(Promotion::Knight),
+        ];
+        play_in_turn(deps.as_mut(), &moves, 1).unwrap();
+
+        let mario = Addr::unchecked("mario");
+        let bowser = Addr::unchecked("bowser");
+        assert!(!MATCHS.has(&deps.storage, (&mario, &bowser)));
+        let finished = FINISHED.load(&deps.storage, (&mario, &bowser)).unwrap();
+        assert_eq!(finished.result, VictoryStatus::Checkmate.to_string());
+        assert_eq!(finished.winner, Some(mario));
+        let game = replay(&deps.storage, &finished.moves).unwrap();
+        assert_eq!(game.get_from_square("f8").unwrap(), Some(&WHITE[2]));
+    }
+
+    #[test]
+    fn draw_claims() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = InstantiateMsg {
+            admin: None,
+            one_step_admin_update: None,
+            start_fen: Some(String::from("4k3/8/8/8/8/8/8/R3K3 w - - 99 60")),
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("peach", &[]), msg).unwrap();
+        let msg = ExecuteMsg::StartMatch {
+            opponent: String::from("bowser"),
+            first_move: ChessMove::new((0, 0), (0, 1)).unwrap(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap();
+
+        // The rook move is the hundredth halfmove without a capture or a pawn move.
+        let msg = QueryMsg::DrawClaims {
+            host: String::from("mario"),
+            opponent: String::from("bowser"),
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let claims: DrawClaimsResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            claims,
+            DrawClaimsResponse {
+                fifty_move: true,
+                threefold_repetition: false,
+                insufficient_material: false,
+            }
+        );
+    }
+
+    #[test]
+    fn draw_claims_after_pawn_moves() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = ExecuteMsg::StartMatch {
+            opponent: String::from("bowser"),
+            first_move: ChessMove::new((6, 0), (5, 2)).unwrap(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap();
+        let claims = |deps: Deps| {
+            let msg = QueryMsg::DrawClaims {
+                host: String::from("mario"),
+                opponent: String::from("bowser"),
+            };
+            let res = query(deps, mock_env(), msg).unwrap();
+            from_binary::<DrawClaimsResponse>(&res)
+                .unwrap()
+                .threefold_repetition
+        };
+
+        // The knights go out and back before and after the pawn moves, so the start position and
+        // the position after the pawn moves each come up only twice.
+        let shuffle = [
+            ChessMove::new((6, 0), (5, 2)).unwrap(),
+            ChessMove::new((6, 7), (5, 5)).unwrap(),
+            ChessMove::new((5, 2), (6, 0)).unwrap(),
+            ChessMove::new((5, 5), (6, 7)).unwrap(),
+        ];
+        let mut moves = vec![
+            ChessMove::new((6, 7), (5, 5)).unwrap(),
+            ChessMove::new((5, 2), (6, 0)).unwrap(),
+            ChessMove::new((5, 5), (6, 7)).unwrap(),
+            ChessMove::new((4, 1), (4, 3)).unwrap(),
+            ChessMove::new((4, 6), (4, 4)).unwrap(),
+        ];
+        moves.extend_from_slice(&shuffle);
+        play_in_turn(deps.as_mut(), &moves, 1).unwrap();
+        assert!(!claims(deps.as_ref()));
+
+        play_in_turn(deps.as_mut(), &shuffle, 10).unwrap();
+        assert!(claims(deps.as_ref()));
+    }
+
+    #[test]
+    fn stalemate() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = InstantiateMsg {
+            admin: None,
+            one_step_admin_update: None,
+            start_fen: Some(String::from("k7/8/1K6/8/8/8/8/2Q5 w - - 0 1")),
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("peach", &[]), msg).unwrap();
+        let msg = ExecuteMsg::StartMatch {
+            opponent: String::from("bowser"),
+            first_move: ChessMove::new((2, 0), (2, 1)).unwrap(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap();
+
+        let moves = vec![
+            ChessMove::new((0, 7), (1, 7)).unwrap(),
+            ChessMove::new((2, 1), (2, 2)).unwrap(),
+            ChessMove::new((1, 7), (0, 7)).unwrap(),
+        ];
+        let res = play_in_turn(deps.as_mut(), &moves, 1).unwrap();
+        let response: MoveResponse = from_binary(&res.data.unwrap()).unwrap();
+        assert!(!response.game_over);
+
+        let mario = Addr::unchecked("mario");
+        let bowser = Addr::unchecked("bowser");
+        let moves_made = MATCHS.load(&deps.storage, (&mario, &bowser)).unwrap();
+        let game = replay(&deps.storage, &moves_made).unwrap();
+        assert_eq!(game.side_to_move(), Color::White);
+        assert_eq!(game.to_fen(), "k7/8/1K6/8/8/2Q5/8/8 w - - 4 3");
+        assert!(game.check_victory().is_none());
+
+        let msg = ExecuteMsg::PlayMove {
+            host: String::from("mario"),
+            opponent: String::from("bowser"),
+            your_move: ChessMove::new((2, 2), (2, 6)).unwrap(),
+            ply: 4,
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap();
+        let response: MoveResponse = from_binary(&res.data.unwrap()).unwrap();
+        assert_eq!(
+            response,
+            MoveResponse {
+                game_over: true,
+                result: Some(String::from("stalemate")),
+                winner: None,
+            }
+        );
+
+        assert!(!MATCHS.has(&deps.storage, (&mario, &bowser)));
+        let finished = FINISHED.load(&deps.storage, (&mario, &bowser)).unwrap();
+        assert_eq!(finished.result, "stalemate");
+        assert_eq!(finished.winner, None);
+        let game = replay(&deps.storage, &finished.moves).unwrap();
+        assert_eq!(game.side_to_move(), Color::Black);
+        assert_eq!(game.to_fen(), "k7/2Q5/1K6/8/8/8/8/8 b - - 5 3");
+    }
+
+    #[test]
+    fn turn_order() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = ExecuteMsg::StartMatch {
+            opponent: String::from("bowser"),
+            first_move: ChessMove::new((4, 1), (4, 3)).unwrap(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap();
+
+        let play = |your_move: ChessMove, ply: u32| ExecuteMsg::PlayMove {
+            host: String::from("mario"),
+            opponent: String::from("bowser"),
+            your_move,
+            ply,
+        };
+        let reply = ChessMove::new((4, 6), (4, 4)).unwrap();
+
+        // Only the two players can move, and only on their own turn.
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("luigi", &[]),
+            play(reply, 1),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("mario", &[]),
+            play(reply, 1),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::NotYourTurn {});
+
+        // Black can't move a white piece either.
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bowser", &[]),
+            play(ChessMove::new((3, 1), (3, 3)).unwrap(), 1),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::NotYourTurn {});
+
+        let mario = Addr::unchecked("mario");
+        let bowser = Addr::unchecked("bowser");
+        assert_eq!(
+            MATCHS.load(&deps.storage, (&mario, &bowser)).unwrap().len(),
+            1
+        );
+
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bowser", &[]),
+            play(reply, 1),
+        )
+        .unwrap();
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bowser", &[]),
+            play(ChessMove::new((3, 6), (3, 4)).unwrap(), 2),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::NotYourTurn {});
+        assert_eq!(
+            MATCHS.load(&deps.storage, (&mario, &bowser)).unwrap(),
+            vec![ChessMove::new((4, 1), (4, 3)).unwrap(), reply]
+        );
+    }
+
+    #[test]
+    fn illegal_moves_are_rejected() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = ExecuteMsg::StartMatch {
+            opponent: String::from("bowser"),
+            first_move: ChessMove::new((3, 1), (3, 3)).unwrap(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap();
+        let msg = ExecuteMsg::PlayMove {
+            host: String::from("mario"),
+            opponent: String::from("bowser"),
+            your_move: ChessMove::new((4, 6), (4, 4)).unwrap(),
+            ply: 1,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("bowser", &[]), msg).unwrap();
+
+        let mario = Addr::unchecked("mario");
+        let bowser = Addr::unchecked("bowser");
+        let before = MATCHS.load(&deps.storage, (&mario, &bowser)).unwrap();
+
+        // The bishop on f1 can't jump over the pawn on e2 to reach c4.
+        let msg = ExecuteMsg::PlayMove {
+            host: String::from("mario"),
+            opponent: String::from("bowser"),
+            your_move: ChessMove::new((5, 0), (2, 3)).unwrap(),
+            ply: 2,
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("mario", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::IllegalMove {});
+        assert_eq!(
+            MATCHS.load(&deps.storage, (&mario, &bowser)).unwrap(),
+            before
+        );
+    }
 }
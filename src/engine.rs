@@ -9,6 +9,28 @@
 
 use log::*;
 use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+mod compact;
+mod eval;
+mod fen;
+mod json;
+mod pgn;
+mod search;
+mod svg;
+mod uci;
+
+/// The number of ranks and files on the board.
+pub const BOARD_SIZE: usize = 8;
+
+pub use self::compact::{CompactError, COMPACT_LEN};
+pub use self::eval::{KingSafetyWeights, MaterialImbalance, PieceSquareTables, MATE_SCORE};
+pub use self::fen::{validate_fen, FenError};
+pub use self::json::{CastlingRights, PieceDto, PositionDto};
+pub use self::pgn::{move_number_prefix, AnalysisExport, PgnTags, SanError};
+pub use self::search::{SearchOptions, SearchResult};
+pub use self::uci::{UciError, START_FEN};
 
 /// An array of all the white chess pieces.
 ///
@@ -71,7 +93,8 @@ pub static BLACK: [Piece; 6] = [
 ];
 
 /// The different kinds of chess pieces.
-#[derive(PartialEq, Debug, Clone, Copy)]
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Kind {
     King,
     Queen,
@@ -95,7 +118,8 @@ impl std::fmt::Display for Kind {
 }
 
 /// The different colors of chess pieces.
-#[derive(PartialEq, Debug, Clone, Copy)]
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Color {
     White,
     Black,
@@ -110,6 +134,16 @@ impl std::fmt::Display for Color {
     }
 }
 
+impl Color {
+    /// Returns the other color.
+    pub fn opposite(&self) -> Color {
+        match *self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+}
+
 /// The different types of victories.
 #[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub enum VictoryStatus {
@@ -145,6 +179,182 @@ impl std::fmt::Display for Piece {
     }
 }
 
+impl Piece {
+    /// Returns the piece of the given color and kind from the WHITE or BLACK array.
+    pub fn get(color: Color, kind: Kind) -> &'static Piece {
+        let pieces = match color {
+            Color::White => &WHITE,
+            Color::Black => &BLACK,
+        };
+        match kind {
+            Kind::Pawn => &pieces[0],
+            Kind::Rook => &pieces[1],
+            Kind::Knight => &pieces[2],
+            Kind::Bishop => &pieces[3],
+            Kind::Queen => &pieces[4],
+            Kind::King => &pieces[5],
+        }
+    }
+}
+
+/// A square whose occupant differs between two games, as returned from `board_diff`.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct SquareChange<'a> {
+    pub pos: (usize, usize),
+    /// The piece on the square in the game `board_diff` was called on.
+    pub before: Option<&'a Piece>,
+    /// The piece on the square in the other game.
+    pub after: Option<&'a Piece>,
+}
+
+/// The ways a promotion can be rejected by `move_piece_promote`.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum PromotionError {
+    /// Strict promotion is on and no piece was given for a pawn reaching the last rank.
+    Required,
+    /// Pawns can't be promoted to pawns or kings.
+    InvalidPiece(Kind),
+    /// A promotion piece was given for a move that doesn't promote.
+    NotAPromotion(Kind),
+}
+
+impl std::fmt::Display for PromotionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            PromotionError::Required => write!(f, "a promotion piece must be chosen"),
+            PromotionError::InvalidPiece(kind) => write!(f, "can't promote to a {}", kind),
+            PromotionError::NotAPromotion(kind) => {
+                write!(
+                    f,
+                    "can't promote to a {} without reaching the last rank",
+                    kind
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for PromotionError {}
+
+/// The ways a move can be rejected by `play`.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum PlayError {
+    /// The move isn't legal for the side to move.
+    IllegalMove((usize, usize), (usize, usize)),
+    /// The promotion piece was missing, not allowed or given for a move that doesn't promote.
+    Promotion(PromotionError),
+}
+
+impl std::fmt::Display for PlayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            PlayError::IllegalMove(from, to) => write!(
+                f,
+                "illegal move from ({}, {}) to ({}, {})",
+                from.0, from.1, to.0, to.1
+            ),
+            PlayError::Promotion(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for PlayError {}
+
+impl From<PromotionError> for PlayError {
+    fn from(e: PromotionError) -> Self {
+        PlayError::Promotion(e)
+    }
+}
+
+/// The error returned by `try_in_check` and `try_check_victory` when a king they need isn't on
+/// the board.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct MissingKing(pub Color);
+
+impl std::fmt::Display for MissingKing {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "there is no {} king", self.0)
+    }
+}
+
+impl std::error::Error for MissingKing {}
+
+/// The reasons `castle_kingside` and `castle_queenside` can refuse to castle.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum CastleError {
+    /// The king or the rook has moved, or isn't on its starting square.
+    NoRight,
+    /// There are pieces between the king and the rook.
+    Blocked,
+    /// The king is in check, or would pass through or land on an attacked square.
+    Check,
+}
+
+impl std::fmt::Display for CastleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            CastleError::NoRight => write!(f, "the right to castle has been lost"),
+            CastleError::Blocked => write!(f, "there are pieces between the king and the rook"),
+            CastleError::Check => write!(f, "the king can't castle out of, through or into check"),
+        }
+    }
+}
+
+impl std::error::Error for CastleError {}
+
+/// The reasons `is_legal_position` can reject a position.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum IllegalPosition {
+    /// The color doesn't have exactly one king; the number it has is given.
+    KingCount(Color, usize),
+    /// There is a pawn on the first or eighth rank.
+    PawnOnBackRank((usize, usize)),
+    /// The color that just moved is in check, so the side to move could take its king.
+    OpponentInCheck(Color),
+    /// The color has more pawns and promoted pieces than its eight pawns could account for.
+    TooManyPieces(Color),
+}
+
+impl std::fmt::Display for IllegalPosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            IllegalPosition::KingCount(color, n) => {
+                write!(f, "{} has {} kings instead of one", color, n)
+            }
+            IllegalPosition::PawnOnBackRank(pos) => match pos_to_string(pos) {
+                Ok(square) => write!(f, "there is a pawn on {}", square.to_lowercase()),
+                Err(_) => write!(f, "there is a pawn on the first or eighth rank"),
+            },
+            IllegalPosition::OpponentInCheck(color) => {
+                write!(f, "{} is in check but it isn't its turn", color)
+            }
+            IllegalPosition::TooManyPieces(color) => {
+                write!(f, "{} has more pieces than promotions allow", color)
+            }
+        }
+    }
+}
+
+impl std::error::Error for IllegalPosition {}
+
+/// The error returned when a square isn't written like `e4`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct CoordError(pub String);
+
+impl std::fmt::Display for CoordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "invalid square '{}'", self.0)
+    }
+}
+
+impl std::error::Error for CoordError {}
+
+/// A single move, as returned from `valid_moves` and accepted by `move_pieces`.
+///
+/// Most moves consist of a single (from, to) tuple, but castling and *en passant* are made up of
+/// several steps.
+pub type Move = Vec<((usize, usize), (usize, usize))>;
+
 /// The game struct.
 ///
 /// The coordinates used to access pieces are 0-indexed tuples of (usize, usize),
@@ -158,17 +368,76 @@ pub struct Game<'a> {
     /// The current turn number.
     turn: u32,
     /// The game board. Contains references to the WHITE and BLACK arrays.
-    board: [[Option<&'a Piece>; 8]; 8],
+    board: [[Option<&'a Piece>; BOARD_SIZE]; BOARD_SIZE],
     ignore_kings: bool,
     ignore_check: bool,
+    /// The piece pawns are promoted to when no other piece is asked for.
+    default_promotion: Kind,
+    /// Whether `move_piece_promote` requires the promotion piece to be given.
+    strict_promotion: bool,
+    last: ((usize, usize), (usize, usize)),
+    black_can_castle_right: bool,
+    black_can_castle_left: bool,
+    white_can_castle_right: bool,
+    white_can_castle_left: bool,
+    board_history: Vec<RepetitionKey<'a>>,
+    seventy_five_move_rule: u32,
+    last_color: Color,
+    /// The states to go back to with `undo_last`, the most recent last.
+    undo_stack: Vec<Undo<'a>>,
+    /// The states to go forward to with `redo`, the most recently undone last.
+    redo_stack: Vec<Undo<'a>>,
+    /// The moves made with `move_pieces`, in order, matching `undo_stack`.
+    move_history: Vec<Move>,
+    /// The SAN of the legal moves made with `move_pieces`, in order.
+    san_history: Vec<String>,
+    /// Whether `move_pieces` writes to `san_history`. Off for the copies used to try out moves.
+    record_san: bool,
+    /// The moves generated for the current position, if caching is turned on with `move_cache`.
+    move_cache: Option<RefCell<MoveCache>>,
+}
+
+/// The moves generated for a single position, by square and by whether moves leaving the king
+/// in check were removed.
+#[derive(Clone, Default)]
+struct MoveCache {
+    /// The Zobrist hash of the position the moves belong to.
+    hash: u64,
+    moves: HashMap<((usize, usize), bool), Vec<Move>>,
+    /// How many lookups were answered from the cache instead of generating the moves.
+    hits: u64,
+}
+
+/// What makes two positions the same for the repetition rules: the pieces on the board, the side
+/// to move, the castling rights and whether en passant is possible.
+#[derive(PartialEq, Debug, Clone, Copy)]
+struct RepetitionKey<'a> {
+    board: [[Option<&'a Piece>; BOARD_SIZE]; BOARD_SIZE],
+    side_to_move: Color,
+    /// White right, white left, black right and black left.
+    castling: [bool; 4],
+    en_passant: Option<(usize, usize)>,
+}
+
+/// The parts of a game that `move_pieces` changes, saved so the move can be taken back or
+/// replayed.
+#[derive(Clone)]
+struct Undo<'a> {
+    /// The move that leads from the state in `undo_stack` to the one after it.
+    moves: Move,
+    turn: u32,
+    board: [[Option<&'a Piece>; BOARD_SIZE]; BOARD_SIZE],
     last: ((usize, usize), (usize, usize)),
     black_can_castle_right: bool,
     black_can_castle_left: bool,
     white_can_castle_right: bool,
     white_can_castle_left: bool,
-    board_history: Vec<[[Option<&'a Piece>; 8]; 8]>,
     seventy_five_move_rule: u32,
     last_color: Color,
+    /// The repetition history, if it can't be restored by dropping the last board.
+    board_history: Option<Vec<RepetitionKey<'a>>>,
+    /// The SAN recorded for `moves`, if any.
+    san: Option<String>,
 }
 
 // 168 | /     pub fn new() -> Game<'a> {
@@ -193,32 +462,52 @@ impl<'a> Default for Game<'a> {
     }
 }
 
+/// Two games are equal when they describe the same position, meaning they would give the same
+/// FEN: the same pieces on the same squares, side to move, castling rights, en passant square,
+/// halfmove clock and turn. The move history and the ignore flags aren't compared.
+impl<'a> PartialEq for Game<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.board == other.board
+            && self.last_color == other.last_color
+            && self.white_can_castle_right == other.white_can_castle_right
+            && self.white_can_castle_left == other.white_can_castle_left
+            && self.black_can_castle_right == other.black_can_castle_right
+            && self.black_can_castle_left == other.black_can_castle_left
+            && self.en_passant_square() == other.en_passant_square()
+            && self.seventy_five_move_rule == other.seventy_five_move_rule
+            && self.turn == other.turn
+    }
+}
+
 impl<'a> Game<'a> {
     /// Creates a new game, with all the pieces in the correct starting position.
     ///
     pub fn new() -> Game<'a> {
-        let mut board: [[Option<&'a Piece>; 8]; 8] = [[None; 8]; 8];
+        let mut board: [[Option<&'a Piece>; BOARD_SIZE]; BOARD_SIZE] =
+            [[None; BOARD_SIZE]; BOARD_SIZE];
 
-        for i in 0..8 {
+        for i in 0..BOARD_SIZE {
             board[i][1] = Some(&WHITE[0]);
             board[i][6] = Some(&BLACK[0]);
         }
         for i in 0..3 {
             board[i][0] = Some(&WHITE[1 + i]);
-            board[7 - i][0] = Some(&WHITE[1 + i]);
-            board[i][7] = Some(&BLACK[1 + i]);
-            board[7 - i][7] = Some(&BLACK[1 + i]);
+            board[BOARD_SIZE - 1 - i][0] = Some(&WHITE[1 + i]);
+            board[i][BOARD_SIZE - 1] = Some(&BLACK[1 + i]);
+            board[BOARD_SIZE - 1 - i][BOARD_SIZE - 1] = Some(&BLACK[1 + i]);
         }
         board[4][0] = Some(&WHITE[5]);
         board[3][0] = Some(&WHITE[4]);
-        board[4][7] = Some(&BLACK[5]);
-        board[3][7] = Some(&BLACK[4]);
+        board[4][BOARD_SIZE - 1] = Some(&BLACK[5]);
+        board[3][BOARD_SIZE - 1] = Some(&BLACK[4]);
 
         let mut game = Game {
             turn: 1,
             board,
             ignore_kings: false,
             ignore_check: false,
+            default_promotion: Kind::Queen,
+            strict_promotion: false,
             last: ((0, 0), (0, 0)),
             white_can_castle_right: true,
             black_can_castle_right: true,
@@ -227,6 +516,12 @@ impl<'a> Game<'a> {
             board_history: Vec::new(),
             seventy_five_move_rule: 0,
             last_color: Color::Black,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            move_history: Vec::new(),
+            san_history: Vec::new(),
+            record_san: true,
+            move_cache: None,
         };
         game.save_board();
 
@@ -235,13 +530,14 @@ impl<'a> Game<'a> {
 
     /// Creates a new game with an empty board.
     ///
-
     pub fn new_empty() -> Game<'a> {
         let mut game = Game {
             turn: 1,
-            board: [[None; 8]; 8],
+            board: [[None; BOARD_SIZE]; BOARD_SIZE],
             ignore_kings: false,
             ignore_check: false,
+            default_promotion: Kind::Queen,
+            strict_promotion: false,
             last: ((0, 0), (0, 0)),
             white_can_castle_right: true,
             black_can_castle_right: true,
@@ -250,16 +546,85 @@ impl<'a> Game<'a> {
             board_history: Vec::new(),
             seventy_five_move_rule: 0,
             last_color: Color::Black,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            move_history: Vec::new(),
+            san_history: Vec::new(),
+            record_san: true,
+            move_cache: None,
         };
         game.save_board();
 
         game
     }
 
+    /// Sets the game back to the starting position in place, as if it had been created with
+    /// `new`. The vectors holding the history keep their memory, so a loop playing many games
+    /// doesn't have to allocate them again.
+    ///
+    /// Settings like the ignore flags, the default promotion, SAN recording and the move cache
+    /// are kept.
+    ///
+    pub fn reset(&mut self) {
+        self.set_position(&Game::new());
+    }
+
+    /// Sets the game to the position of the given FEN in place, like `reset` does for the
+    /// starting position. On an error the game is left unchanged.
+    ///
+    pub fn reset_to_fen(&mut self, fen: &str) -> Result<(), FenError> {
+        self.set_position(&Game::from_fen(fen)?);
+        Ok(())
+    }
+
+    /// Returns a copy of the game with the given moves played from the current position, for
+    /// callers that store a move list and rebuild the game from it, like the contract.
+    ///
+    /// Each move is a from square, a to square and the piece a pawn is promoted to, and is played
+    /// with `play`. A move that isn't legal for the side to move, as in lists saved before moves
+    /// were checked, is still made with `move_pieces`. The copy starts with a fresh history, and
+    /// the repetition window is cleared on every capture and pawn move, so only positions that
+    /// can still recur are counted for draw claims.
+    ///
+    pub fn rebuild_from_moves_with_history(
+        &self,
+        moves: &[((usize, usize), (usize, usize), Option<Kind>)],
+    ) -> Game<'a> {
+        let mut game = self.clone();
+        game.set_position(self);
+        for &(from, to, promotion) in moves {
+            if game.play(from, to, promotion).is_err() {
+                game.move_pieces(&[(from, to)]);
+            }
+        }
+        game
+    }
+
+    /// Copies the position of another game and clears the history.
+    fn set_position(&mut self, other: &Game<'a>) {
+        self.clear_move_cache();
+        self.board = other.board;
+        self.turn = other.turn;
+        self.last = other.last;
+        self.last_color = other.last_color;
+        self.white_can_castle_right = other.white_can_castle_right;
+        self.white_can_castle_left = other.white_can_castle_left;
+        self.black_can_castle_right = other.black_can_castle_right;
+        self.black_can_castle_left = other.black_can_castle_left;
+        self.seventy_five_move_rule = other.seventy_five_move_rule;
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.move_history.clear();
+        self.san_history.clear();
+        self.board_history.clear();
+        self.save_board();
+    }
+
     /// Clears the board.
     ///
     pub fn clear(&mut self) {
-        self.board = [[None; 8]; 8];
+        self.clear_move_cache();
+        self.board = [[None; BOARD_SIZE]; BOARD_SIZE];
         self.last = ((0, 0), (0, 0));
     }
 
@@ -278,12 +643,53 @@ impl<'a> Game<'a> {
         self.ignore_check = ignore;
     }
 
+    /// Tells the game whether to record the SAN of the moves made with `move_pieces`, which is on
+    /// by default. Working out the SAN of a move costs about as much as generating the replies to
+    /// it, so callers that never read `history_san` can turn it off.
+    ///
+    pub fn record_san(&mut self, enabled: bool) {
+        self.record_san = enabled;
+    }
+
+    /// Tells the game whether to remember the moves it generates for the current position.
+    ///
+    /// Functions like `check_victory`, `in_check` and `move_to_an` generate the same moves over
+    /// and over, so with the cache on, asking about the same position again is much cheaper. The
+    /// cache costs memory and a hash of the position on every lookup, and is emptied whenever the
+    /// position changes. It is off by default.
+    ///
+    pub fn move_cache(&mut self, enabled: bool) {
+        self.move_cache = if enabled {
+            Some(RefCell::new(MoveCache::default()))
+        } else {
+            None
+        };
+    }
+
+    /// Sets the piece pawns are promoted to when a move doesn't say otherwise, which is a queen
+    /// by default.
+    ///
+    /// Panics if the given kind is a pawn or a king.
+    ///
+    pub fn default_promotion(&mut self, kind: Kind) {
+        if kind == Kind::Pawn || kind == Kind::King {
+            panic!("Pawns can't be promoted to a {}", kind);
+        }
+        self.default_promotion = kind;
+    }
+
+    /// Tells the game whether `move_piece_promote` should refuse to promote a pawn when no piece
+    /// is given, instead of using the default promotion.
+    ///
+    pub fn strict_promotion(&mut self, strict: bool) {
+        self.strict_promotion = strict;
+    }
+
     /// Gets the piece at the given position on the board.
     ///
     /// Returns an Option where Some contains a reference to the piece,
     /// and None means there was no piece at the given position.
     ///
-
     pub fn get_from_pos(&self, pos: (usize, usize)) -> Option<&'a Piece> {
         self.board[pos.0][pos.1]
     }
@@ -293,14 +699,38 @@ impl<'a> Game<'a> {
     /// The piece is passed as an Option, where the Some should contain a
     /// reference to the WHITE or BLACK arrays. Pass None to remove an existing piece.
     ///
-
     pub fn set_at_pos(&mut self, pos: (usize, usize), piece: Option<&'a Piece>) {
+        self.clear_move_cache();
         if let Some(p) = piece {
             self.last_color = p.color;
         }
         self.board[pos.0][pos.1] = piece;
     }
 
+    /// Gets the piece on a square given in algebraic notation, like `e4`.
+    ///
+    /// Returns an error if the square isn't a valid square name.
+    ///
+    pub fn get_from_square(&self, square: &str) -> Result<Option<&'a Piece>, CoordError> {
+        let pos = string_to_pos(square).map_err(|_| CoordError(square.to_string()))?;
+        Ok(self.get_from_pos(pos))
+    }
+
+    /// Sets the piece on a square given in algebraic notation, like `e4`, in the same way as
+    /// `set_at_pos`.
+    ///
+    /// Returns an error, leaving the board unchanged, if the square isn't a valid square name.
+    ///
+    pub fn set_at_square(
+        &mut self,
+        square: &str,
+        piece: Option<&'a Piece>,
+    ) -> Result<(), CoordError> {
+        let pos = string_to_pos(square).map_err(|_| CoordError(square.to_string()))?;
+        self.set_at_pos(pos, piece);
+        Ok(())
+    }
+
     /// Returns the current turn.
     pub fn get_turn(&self) -> u32 {
         self.turn
@@ -311,16 +741,26 @@ impl<'a> Game<'a> {
         self.turn += 1;
     }
 
+    /// Returns the color to move.
+    ///
+    /// The turn counts full moves, as in FEN, so both colors move during a turn and its parity
+    /// can't tell them apart. The side to move is instead the opposite of the color that moved
+    /// last, which `move_pieces`, `undo_last` and `from_fen` keep up to date whether or not
+    /// `next_turn` is called. White moves first in a new game.
+    ///
+    pub fn whose_turn(&self) -> Color {
+        self.side_to_move()
+    }
+
     /// Returns a vector of all pieces of a given color, and their position on the board.
     ///
     /// The pieces are arrenged in the order they are found, starting at A1 through H1, then A2
     /// through H2, until it reaches H8.
     ///
-
     pub fn by_color(&self, color: Color) -> Vec<((usize, usize), &'a Piece)> {
         let mut pieces: Vec<((usize, usize), &'a Piece)> = Vec::new();
-        for y in 0..8 {
-            for x in 0..8 {
+        for y in 0..BOARD_SIZE {
+            for x in 0..BOARD_SIZE {
                 if let Some(piece) = self.board[x][y] {
                     if piece.color == color {
                         pieces.push(((x, y), piece));
@@ -336,11 +776,10 @@ impl<'a> Game<'a> {
     /// The pieces are arrenged in the order they are found, starting at A1 through H1, then A2
     /// through H2, until it reaches H8.
     ///
-
     pub fn by_kind(&self, kind: Kind) -> Vec<((usize, usize), &'a Piece)> {
         let mut pieces: Vec<((usize, usize), &'a Piece)> = Vec::new();
-        for y in 0..8 {
-            for x in 0..8 {
+        for y in 0..BOARD_SIZE {
+            for x in 0..BOARD_SIZE {
                 if let Some(piece) = self.board[x][y] {
                     if piece.kind == kind {
                         pieces.push(((x, y), piece));
@@ -356,11 +795,10 @@ impl<'a> Game<'a> {
     /// The pieces are arrenged in the order they are found, starting at A1 through H1, then A2
     /// through H2, until it reaches H8.
     ///
-
     pub fn by_kind_and_color(&self, kind: Kind, color: Color) -> Vec<((usize, usize), &'a Piece)> {
         let mut pieces: Vec<((usize, usize), &'a Piece)> = Vec::new();
-        for x in 0..8 {
-            for y in 0..8 {
+        for x in 0..BOARD_SIZE {
+            for y in 0..BOARD_SIZE {
                 if let Some(piece) = self.board[x][y] {
                     if piece.kind == kind && piece.color == color {
                         pieces.push(((x, y), piece));
@@ -371,6 +809,51 @@ impl<'a> Game<'a> {
         pieces
     }
 
+    /// Returns the pieces on a rank, from the A file to the H file. Rank 0 is the first rank.
+    ///
+    /// Panics if the rank is off the board.
+    ///
+    pub fn rank(&self, r: usize) -> [Option<&'a Piece>; BOARD_SIZE] {
+        if r >= BOARD_SIZE {
+            panic!("Rank {} is off the board", r);
+        }
+        let mut rank = [None; BOARD_SIZE];
+        for (x, square) in rank.iter_mut().enumerate() {
+            *square = self.board[x][r];
+        }
+        rank
+    }
+
+    /// Returns the pieces on a file, from the first rank to the eighth. File 0 is the A file.
+    ///
+    /// Panics if the file is off the board.
+    ///
+    pub fn file(&self, f: usize) -> [Option<&'a Piece>; BOARD_SIZE] {
+        if f >= BOARD_SIZE {
+            panic!("File {} is off the board", f);
+        }
+        self.board[f]
+    }
+
+    /// Returns the squares that hold different pieces in the two games, from A1 through H1, then
+    /// A2 through H2, until it reaches H8.
+    ///
+    pub fn board_diff(&self, other: &Game<'a>) -> Vec<SquareChange<'a>> {
+        let mut changes = Vec::new();
+        for y in 0..BOARD_SIZE {
+            for x in 0..BOARD_SIZE {
+                if self.board[x][y] != other.board[x][y] {
+                    changes.push(SquareChange {
+                        pos: (x, y),
+                        before: self.board[x][y],
+                        after: other.board[x][y],
+                    });
+                }
+            }
+        }
+        changes
+    }
+
     /// Moves a piece from one position to another.
     ///
     /// The return value is an Option containing a reference to the captured piece (if any), or
@@ -381,13 +864,16 @@ impl<'a> Game<'a> {
     /// bounds. Therefore this should always be used together with valid_moves when playing proper
     /// chess.
     ///
-
+    /// A king moved two squares along its home rank from its starting square castles, taking the
+    /// rook along, as long as the castling right on that side is still there.
+    ///
     pub fn move_piece(&mut self, from: (usize, usize), to: (usize, usize)) -> Option<&'a Piece> {
-        if from.0 > 7 || from.1 > 7 || to.0 > 7 || to.1 > 7 {
+        if !on_board(from) || !on_board(to) {
             return None;
         }
         let mut moving = self.get_from_pos(from);
         let other = self.get_from_pos(to);
+        let mut castling_rook = None;
         match moving {
             Some(p) => {
                 if other.is_some() {
@@ -398,12 +884,29 @@ impl<'a> Game<'a> {
 
                 if p.kind == Kind::Pawn {
                     self.seventy_five_move_rule = 0;
-                    if p.color == Color::White && to.1 == 7 {
-                        moving = Some(&WHITE[4]);
+                    if p.color == Color::White && to.1 == BOARD_SIZE - 1 {
+                        moving = Some(Piece::get(Color::White, self.default_promotion));
                     } else if p.color == Color::Black && to.1 == 0 {
-                        moving = Some(&BLACK[4]);
+                        moving = Some(Piece::get(Color::Black, self.default_promotion));
                     }
                 } else if p.kind == Kind::King {
+                    let (home, left, right) = match p.color {
+                        Color::White => {
+                            (0, self.white_can_castle_left, self.white_can_castle_right)
+                        }
+                        Color::Black => (
+                            BOARD_SIZE - 1,
+                            self.black_can_castle_left,
+                            self.black_can_castle_right,
+                        ),
+                    };
+                    if from == (4, home) && to.1 == home {
+                        castling_rook = match to.0 {
+                            2 if left => Some(((0, home), (3, home))),
+                            6 if right => Some(((BOARD_SIZE - 1, home), (5, home))),
+                            _ => None,
+                        };
+                    }
                     match p.color {
                         Color::White => {
                             self.white_can_castle_left = false;
@@ -435,6 +938,13 @@ impl<'a> Game<'a> {
 
                 self.set_at_pos(to, moving);
                 self.set_at_pos(from, None);
+                if let Some((rook_from, rook_to)) = castling_rook {
+                    let rook = Piece::get(p.color, Kind::Rook);
+                    if self.get_from_pos(rook_from) == Some(rook) {
+                        self.set_at_pos(rook_to, Some(rook));
+                        self.set_at_pos(rook_from, None);
+                    }
+                }
                 self.last = (from, to);
                 other
             }
@@ -442,6 +952,92 @@ impl<'a> Game<'a> {
         }
     }
 
+    /// Moves a piece like `move_piece`, promoting a pawn that reaches the last rank to the given
+    /// kind of piece.
+    ///
+    /// If no kind is given the default promotion is used, unless strict promotion is turned on,
+    /// in which case an error is returned and nothing is moved. Asking for a promotion on a move
+    /// that doesn't promote is also an error.
+    ///
+    pub fn move_piece_promote(
+        &mut self,
+        from: (usize, usize),
+        to: (usize, usize),
+        promotion: Option<Kind>,
+    ) -> Result<Option<&'a Piece>, PromotionError> {
+        self.check_promotion(from, to, promotion)?;
+        let captured = self.move_piece(from, to);
+        if let (Some(kind), Some(p)) = (promotion, self.get_from_pos(to)) {
+            self.set_at_pos(to, Some(Piece::get(p.color, kind)));
+        }
+        Ok(captured)
+    }
+
+    /// Checks the promotion piece asked for when moving the piece on `from` to `to`, in the way
+    /// `move_piece_promote` describes.
+    fn check_promotion(
+        &self,
+        from: (usize, usize),
+        to: (usize, usize),
+        promotion: Option<Kind>,
+    ) -> Result<(), PromotionError> {
+        let promotes = match self.get_from_pos(from) {
+            Some(p) if p.kind == Kind::Pawn => match p.color {
+                Color::White => to.1 == BOARD_SIZE - 1,
+                Color::Black => to.1 == 0,
+            },
+            _ => false,
+        };
+        if let Some(kind) = promotion {
+            if !promotes {
+                return Err(PromotionError::NotAPromotion(kind));
+            }
+            if kind == Kind::Pawn || kind == Kind::King {
+                return Err(PromotionError::InvalidPiece(kind));
+            }
+        } else if promotes && self.strict_promotion {
+            return Err(PromotionError::Required);
+        }
+        Ok(())
+    }
+
+    /// Plays a legal move for the side to move, keeping all of the game state in step: the move
+    /// is made with `move_pieces`, so the history, the halfmove clock and the castling and en
+    /// passant rights are updated, and the turn is advanced after black moves.
+    ///
+    /// A castle is given as the king's move, like e1 to g1. A pawn reaching the last rank turns
+    /// into the given piece, or the default promotion if none is given and strict promotion is
+    /// off. The return value is the captured piece, if any. On an error nothing is changed.
+    ///
+    pub fn play(
+        &mut self,
+        from: (usize, usize),
+        to: (usize, usize),
+        promotion: Option<Kind>,
+    ) -> Result<Option<&'a Piece>, PlayError> {
+        let color = self.side_to_move();
+        if !on_board(from) || !on_board(to) || !self.can_player_move(from, color) {
+            return Err(PlayError::IllegalMove(from, to));
+        }
+        self.check_promotion(from, to, promotion)?;
+        let mv = self
+            .valid_moves(from)
+            .into_iter()
+            .find(|mv| destination(mv) == to)
+            .ok_or(PlayError::IllegalMove(from, to))?;
+
+        // Promote through the default, so the history and the SAN see the right piece.
+        let default = self.default_promotion;
+        self.default_promotion = promotion.unwrap_or(default);
+        let captured = self.move_pieces(&mv);
+        self.default_promotion = default;
+
+        if color == Color::Black {
+            self.next_turn();
+        }
+        Ok(captured)
+    }
+
     /// Executes several moves, as stated in the given array.
     ///
     /// The return value is Some containing the last captured piece (if any), or None if no pieces
@@ -454,85 +1050,523 @@ impl<'a> Game<'a> {
     /// of move_piece in case complex moves where several pieces is moved, like castling, is
     /// nessessary. This function doesn't check whether the moves are legal.
     ///
-
     pub fn move_pieces(&mut self, moves: &[((usize, usize), (usize, usize))]) -> Option<&'a Piece> {
         let mut to: (usize, usize);
         let mut from: (usize, usize);
         let mut captured: Option<&'a Piece> = None;
         let mut tmp: Option<&'a Piece>;
+        let clock = self.seventy_five_move_rule;
+        let mut reset = false;
 
         for v in moves {
             from = v.0;
             to = v.1;
-            if from.0 > 7 || from.1 > 7 || to.0 > 7 || to.1 > 7 {
+            if !on_board(from) || !on_board(to) {
                 return None;
             }
         }
 
+        let mut undo = self.snapshot();
+        undo.moves = moves.to_vec();
+        undo.san = self.notate(moves);
+
         for v in moves {
             from = v.0;
             to = v.1;
             tmp = self.move_piece(from, to);
             if tmp.is_some() {
                 captured = tmp;
-                self.board_history.clear();
             }
-            self.save_board();
+            if self.seventy_five_move_rule == 0 {
+                reset = true;
+            }
+        }
+
+        // The sub-moves of a castle or en passant make up a single halfmove.
+        self.seventy_five_move_rule = if reset { 0 } else { clock + 1 };
+
+        // Positions from before a capture or pawn move can never occur again.
+        if reset {
+            undo.board_history = Some(std::mem::take(&mut self.board_history));
+        }
+        self.save_board();
+
+        // Replaying the next undone move keeps the rest of the redo stack, any other move
+        // starts a new line.
+        if self.redo_stack.last().map(|redo| &redo.moves[..]) == Some(moves) {
+            self.redo_stack.pop();
+        } else {
+            self.redo_stack.clear();
+        }
+        if let Some(san) = &undo.san {
+            self.san_history.push(san.clone());
         }
+        self.move_history.push(moves.to_vec());
+        self.undo_stack.push(undo);
 
         captured
     }
 
-    /// Returns a vector of all the moves the piece at the given position can make.
+    /// Returns the SAN of a move about to be made with `move_pieces`, or None if SAN isn't being
+    /// recorded or the move isn't legal.
+    fn notate(&self, moves: &[((usize, usize), (usize, usize))]) -> Option<String> {
+        if !self.record_san || moves.is_empty() {
+            return None;
+        }
+        // Telling check needs both kings on the board.
+        let king = |color| !self.by_kind_and_color(Kind::King, color).is_empty();
+        let can_check = king(Color::White) && king(Color::Black);
+        if !(can_check || self.ignore_kings || self.ignore_check) {
+            return None;
+        }
+        self.get_from_pos(moves[0].0)?;
+        if !self.valid_moves(moves[0].0).iter().any(|m| m[..] == *moves) {
+            return None;
+        }
+        Some(self.move_to_an(moves, false, false))
+    }
+
+    /// Returns the moves made with `move_pieces` in standard algebraic notation, oldest first.
     ///
-    /// The returned vector contains vectors of moves, as a tuple of the current location and the
-    /// destination. This is done so that more complex moves that involve moving several pieces,
-    /// such as castling, can be carried out. Each of these vectors can be passed to move_pieces to
-    /// be executed.
+    /// The SAN is worked out as each move is made, so this is cheap to call. Moves that weren't
+    /// legal when they were made aren't included, and moves taken back with `undo_last` are
+    /// dropped.
     ///
-    /// If the given position doesn't contain a piece, a vector with size 0 is returned.
+    pub fn history_san(&self) -> &[String] {
+        &self.san_history
+    }
+
+    /// Returns the moves made with `move_pieces`, oldest first, in the same form they were given.
     ///
+    /// Unlike `history_san`, moves that weren't legal are included, so the list always has one
+    /// entry per move `undo_last` can take back.
+    ///
+    pub fn moves_played(&self) -> &[Move] {
+        &self.move_history
+    }
 
-    pub fn valid_moves(&self, pos: (usize, usize)) -> Vec<Vec<((usize, usize), (usize, usize))>> {
-        self.check_valid_moves(pos, true)
+    /// Saves the parts of the game that a move changes, without the repetition history.
+    fn snapshot(&self) -> Undo<'a> {
+        Undo {
+            moves: Vec::new(),
+            turn: self.turn,
+            board: self.board,
+            last: self.last,
+            black_can_castle_right: self.black_can_castle_right,
+            black_can_castle_left: self.black_can_castle_left,
+            white_can_castle_right: self.white_can_castle_right,
+            white_can_castle_left: self.white_can_castle_left,
+            seventy_five_move_rule: self.seventy_five_move_rule,
+            last_color: self.last_color,
+            board_history: None,
+            san: None,
+        }
     }
 
-    fn check_valid_moves(
-        &self,
-        pos: (usize, usize),
-        test_check: bool,
-    ) -> Vec<Vec<((usize, usize), (usize, usize))>> {
-        info!(
-            "check_valid_moves called with args: pos: ({}, {}), test_check: {}",
-            pos.0, pos.1, test_check
-        );
-        let mut result: Vec<Vec<((usize, usize), (usize, usize))>> = self.raw_moves(pos);
+    /// Goes back to a saved state. Without a saved history, the last board in the history is
+    /// dropped.
+    fn restore(&mut self, undo: Undo<'a>) {
+        self.turn = undo.turn;
+        self.board = undo.board;
+        self.last = undo.last;
+        self.black_can_castle_right = undo.black_can_castle_right;
+        self.black_can_castle_left = undo.black_can_castle_left;
+        self.white_can_castle_right = undo.white_can_castle_right;
+        self.white_can_castle_left = undo.white_can_castle_left;
+        self.seventy_five_move_rule = undo.seventy_five_move_rule;
+        self.last_color = undo.last_color;
+        match undo.board_history {
+            Some(history) => self.board_history = history,
+            None => {
+                self.board_history.pop();
+            }
+        }
+    }
 
-        let mut index: Vec<usize> = Vec::new();
-        let mut from: (usize, usize);
-        let mut to: (usize, usize);
-        let mut game: Game;
-        'outer: for i in 0..result.len() {
-            game = self.clone();
-            for j in 0..result[i].len() {
-                from = result[i][j].0;
-                to = result[i][j].1;
-                if from.0 > 7 || from.1 > 7 || to.0 > 7 || to.1 > 7 {
-                    info!(
-                        "from: ({}, {}) to: ({}, {}) excluded, being out of bounds",
-                        from.0, from.1, to.0, to.1
-                    );
-                    index.insert(0, i);
-                    continue 'outer;
-                } else if let Some(piece) = game.get_from_pos(from) {
-                    if let Some(other) = game.get_from_pos(to) {
+    /// Copies the game for trying out moves, leaving out the undo history.
+    fn scratch(&self) -> Game<'a> {
+        Game {
+            board_history: self.board_history.clone(),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            move_history: Vec::new(),
+            san_history: Vec::new(),
+            record_san: false,
+            move_cache: None,
+            ..*self
+        }
+    }
+
+    /// Castles the king of the given color with the rook on the H file, moving both pieces.
+    ///
+    /// Returns an error, leaving the game unchanged, if the castle isn't legal.
+    ///
+    pub fn castle_kingside(&mut self, color: Color) -> Result<(), CastleError> {
+        self.castle(color, true)
+    }
+
+    /// Castles the king of the given color with the rook on the A file, moving both pieces.
+    ///
+    /// Returns an error, leaving the game unchanged, if the castle isn't legal.
+    ///
+    pub fn castle_queenside(&mut self, color: Color) -> Result<(), CastleError> {
+        self.castle(color, false)
+    }
+
+    fn castle(&mut self, color: Color, kingside: bool) -> Result<(), CastleError> {
+        let rank = match color {
+            Color::White => 0,
+            Color::Black => BOARD_SIZE - 1,
+        };
+        let right = match (color, kingside) {
+            (Color::White, true) => self.white_can_castle_right,
+            (Color::White, false) => self.white_can_castle_left,
+            (Color::Black, true) => self.black_can_castle_right,
+            (Color::Black, false) => self.black_can_castle_left,
+        };
+        let king = (4, rank);
+        let rook = if kingside {
+            (BOARD_SIZE - 1, rank)
+        } else {
+            (0, rank)
+        };
+        if !right
+            || self.get_from_pos(king) != Some(Piece::get(color, Kind::King))
+            || self.get_from_pos(rook) != Some(Piece::get(color, Kind::Rook))
+        {
+            return Err(CastleError::NoRight);
+        }
+        if !self.path_is_clear(king, rook) {
+            return Err(CastleError::Blocked);
+        }
+
+        let to = if kingside { (6, rank) } else { (2, rank) };
+        let m = self
+            .valid_moves(king)
+            .into_iter()
+            .find(|m| m.len() == 3 && m[1].1 == to)
+            .ok_or(CastleError::Check)?;
+        self.move_pieces(&m);
+        Ok(())
+    }
+
+    /// Takes back the last move made with `move_pieces`, restoring any captured piece, the
+    /// castling rights, en passant and the halfmove clock.
+    ///
+    /// Returns false if there is no move to take back.
+    ///
+    pub fn undo_last(&mut self) -> bool {
+        let undo = match self.undo_stack.pop() {
+            Some(undo) => undo,
+            None => return false,
+        };
+        let mut redo = self.snapshot();
+        redo.moves = undo.moves.clone();
+        redo.board_history = Some(self.board_history.clone());
+        if undo.san.is_some() {
+            redo.san = self.san_history.pop();
+        }
+        self.move_history.pop();
+        self.redo_stack.push(redo);
+        self.restore(undo);
+        true
+    }
+
+    /// Plays the last move taken back with `undo_last` again.
+    ///
+    /// Returns false if there is no move to replay. Making any move other than the one that was
+    /// taken back discards the moves that could be replayed.
+    ///
+    pub fn redo(&mut self) -> bool {
+        let redo = match self.redo_stack.pop() {
+            Some(redo) => redo,
+            None => return false,
+        };
+        let mut undo = self.snapshot();
+        undo.moves = redo.moves.clone();
+        undo.board_history = Some(self.board_history.clone());
+        if let Some(san) = &redo.san {
+            self.san_history.push(san.clone());
+            undo.san = Some(san.clone());
+        }
+        self.move_history.push(redo.moves.clone());
+        self.undo_stack.push(undo);
+        self.restore(redo);
+        true
+    }
+
+    /// Goes to the position after the given number of moves, counted from the start of the
+    /// history, by taking moves back with `undo_last` or replaying them with `redo`.
+    ///
+    /// Any ply from 0 up to the number of moves made and taken back can be reached, so a viewer
+    /// can jump back and forth through a game. Returns false, leaving the game unchanged, for a
+    /// ply past the end.
+    ///
+    pub fn goto_ply(&mut self, ply: usize) -> bool {
+        if ply > self.undo_stack.len() + self.redo_stack.len() {
+            return false;
+        }
+        while self.undo_stack.len() > ply {
+            self.undo_last();
+        }
+        while self.undo_stack.len() < ply {
+            self.redo();
+        }
+        true
+    }
+
+    /// Returns a vector of all the moves the piece at the given position can make.
+    ///
+    /// The returned vector contains vectors of moves, as a tuple of the current location and the
+    /// destination. This is done so that more complex moves that involve moving several pieces,
+    /// such as castling, can be carried out. Each of these vectors can be passed to move_pieces to
+    /// be executed.
+    ///
+    /// If the given position doesn't contain a piece, a vector with size 0 is returned.
+    ///
+    /// The moves are given for the piece's own color whether or not it is that color's turn,
+    /// which suits puzzles and setting up positions. Use `valid_moves_for_turn` when the turn
+    /// order has to be enforced, like when accepting moves from players.
+    ///
+    pub fn valid_moves(&self, pos: (usize, usize)) -> Vec<Vec<((usize, usize), (usize, usize))>> {
+        self.check_valid_moves(pos, true)
+    }
+
+    /// Returns the moves of `valid_moves`, or an empty vector if the piece at the given position
+    /// doesn't belong to the side to move, as given by `whose_turn`.
+    ///
+    /// This is the function to use when the turn order matters, so a player can never move the
+    /// opponent's pieces or move twice in a row.
+    ///
+    pub fn valid_moves_for_turn(&self, pos: (usize, usize)) -> Vec<Move> {
+        if !on_board(pos) || !self.can_player_move(pos, self.whose_turn()) {
+            return Vec::new();
+        }
+        self.valid_moves(pos)
+    }
+
+    /// Returns the moves of `valid_moves` together with the piece each one promotes to.
+    ///
+    /// A move onto the last rank is listed four times, once for each of a queen, rook, bishop and
+    /// knight, so underpromotions can be offered to the player. Other moves are listed once with
+    /// `None`. A move and its kind can be passed on to `play`.
+    ///
+    pub fn valid_moves_with_promotions(&self, pos: (usize, usize)) -> Vec<(Move, Option<Kind>)> {
+        let promotes = |m: &Move| match self.get_from_pos(pos) {
+            Some(p) if p.kind == Kind::Pawn => {
+                let rank = destination(m).1;
+                rank == 0 || rank == BOARD_SIZE - 1
+            }
+            _ => false,
+        };
+        let mut moves = Vec::new();
+        for m in self.valid_moves(pos) {
+            if promotes(&m) {
+                for &kind in &[Kind::Queen, Kind::Rook, Kind::Bishop, Kind::Knight] {
+                    moves.push((m.clone(), Some(kind)));
+                }
+            } else {
+                moves.push((m, None));
+            }
+        }
+        moves
+    }
+
+    /// Returns the squares the piece at the given position can legally move to as a 64-bit mask,
+    /// which is a compact form for highlighting them in a user interface.
+    ///
+    /// Bit `8 * rank + file` is set for each destination, so A1 is bit 0, H1 is bit 7 and H8 is
+    /// bit 63. A castle sets the bit of the king's destination. Returns 0 for an empty square or a
+    /// position off the board.
+    ///
+    pub fn legal_destinations_bb(&self, pos: (usize, usize)) -> u64 {
+        if !on_board(pos) {
+            return 0;
+        }
+        self.valid_moves(pos).iter().fold(0, |mask, m| {
+            let (file, rank) = destination(m);
+            mask | 1 << (rank * BOARD_SIZE + file)
+        })
+    }
+
+    /// Checks a batch of (from, to) moves, returning whether each one is legal.
+    ///
+    /// The legal destinations of each source square are only generated once, however many
+    /// candidates start from it. A castle is given as the king's move. Like `valid_moves`, this
+    /// doesn't check whose turn it is.
+    ///
+    pub fn filter_legal(&self, candidates: &[((usize, usize), (usize, usize))]) -> Vec<bool> {
+        let mut destinations: HashMap<(usize, usize), u64> = HashMap::new();
+        candidates
+            .iter()
+            .map(|&(from, to)| {
+                let mask = *destinations
+                    .entry(from)
+                    .or_insert_with(|| self.legal_destinations_bb(from));
+                on_board(to) && mask & 1 << (to.1 * BOARD_SIZE + to.0) != 0
+            })
+            .collect()
+    }
+
+    /// Returns the legal destinations of every piece of the given color, grouped by piece.
+    ///
+    /// The pieces come in the order of `by_color`, and pieces that can't move, like pinned or
+    /// boxed in ones, are kept with an empty list. A castle is listed as the king's destination.
+    ///
+    pub fn legal_moves_by_piece(
+        &self,
+        color: Color,
+    ) -> Vec<((usize, usize), &'a Piece, Vec<(usize, usize)>)> {
+        self.by_color(color)
+            .into_iter()
+            .map(|(pos, piece)| {
+                let destinations = self
+                    .valid_moves(pos)
+                    .iter()
+                    .map(|m| destination(m))
+                    .collect();
+                (pos, piece, destinations)
+            })
+            .collect()
+    }
+
+    /// Returns a vector of all the moves the pieces of the given color can make.
+    ///
+    /// The moves are grouped by piece in the order the pieces are returned from `by_color`.
+    ///
+    pub fn all_valid_moves(&self, color: Color) -> Vec<Move> {
+        if self.in_check(color) {
+            return self.evasions(color);
+        }
+        let mut result: Vec<Move> = Vec::new();
+        for (pos, _) in self.by_color(color) {
+            result.append(&mut self.valid_moves(pos));
+        }
+        result
+    }
+
+    /// Checks whether the given color has any legal move.
+    ///
+    /// This stops at the first legal move found, testing the candidates one at a time, so it is
+    /// much cheaper than generating every legal move when all that matters is whether there is
+    /// one, like when looking for checkmate or stalemate.
+    ///
+    pub fn has_legal_move(&self, color: Color) -> bool {
+        // Cached moves are cheaper to look up than to test again.
+        if self.move_cache.is_some() {
+            return self
+                .by_color(color)
+                .into_iter()
+                .any(|(pos, _)| !self.valid_moves(pos).is_empty());
+        }
+        let in_check = self.in_check(color);
+        self.by_color(color).into_iter().any(|(pos, _)| {
+            self.raw_moves(pos)
+                .into_iter()
+                // A king can't castle out of check.
+                .filter(|m| !(in_check && m.len() == 3))
+                .any(|m| !self.filter_moves(vec![m], true).is_empty())
+        })
+    }
+
+    fn check_valid_moves(
+        &self,
+        pos: (usize, usize),
+        test_check: bool,
+    ) -> Vec<Vec<((usize, usize), (usize, usize))>> {
+        info!(
+            "check_valid_moves called with args: pos: ({}, {}), test_check: {}",
+            pos.0, pos.1, test_check
+        );
+        if let Some(cache) = &self.move_cache {
+            let hash = self.zobrist_hash();
+            let mut cache = cache.borrow_mut();
+            if cache.hash != hash {
+                cache.hash = hash;
+                cache.moves.clear();
+            }
+            if let Some(moves) = cache.moves.get(&(pos, test_check)).cloned() {
+                cache.hits += 1;
+                return moves;
+            }
+        }
+
+        let mut result: Vec<Vec<((usize, usize), (usize, usize))>> = self.raw_moves(pos);
+
+        // A king can't castle out of check.
+        if test_check && result.iter().any(|m| m.len() == 3) {
+            if let Some(piece) = self.get_from_pos(pos) {
+                if self.in_check(piece.color) {
+                    result.retain(|m| m.len() != 3);
+                }
+            }
+        }
+
+        let result = self.filter_moves(result, test_check);
+        if let Some(cache) = &self.move_cache {
+            cache
+                .borrow_mut()
+                .moves
+                .insert((pos, test_check), result.clone());
+        }
+        info!("check_valid_moves finished");
+        result
+    }
+
+    /// Empties the move cache, if there is one.
+    fn clear_move_cache(&self) {
+        if let Some(cache) = &self.move_cache {
+            cache.borrow_mut().moves.clear();
+        }
+    }
+
+    /// Returns a Zobrist hash of everything move generation depends on: the pieces, the side to
+    /// move, the castling rights, the en passant square and the ignore flags.
+    fn zobrist_hash(&self) -> u64 {
+        let flags = [
+            self.side_to_move() == Color::Black,
+            self.white_can_castle_right,
+            self.white_can_castle_left,
+            self.black_can_castle_right,
+            self.black_can_castle_left,
+            self.ignore_kings,
+            self.ignore_check,
+        ];
+        hash_position(&self.board, &flags, self.en_passant_square())
+    }
+
+    /// Removes the moves that are out of bounds, capture a friendly piece or, if `test_check` is
+    /// true, leave the king in check.
+    fn filter_moves(
+        &self,
+        mut result: Vec<Vec<((usize, usize), (usize, usize))>>,
+        test_check: bool,
+    ) -> Vec<Vec<((usize, usize), (usize, usize))>> {
+        let mut index: Vec<usize> = Vec::new();
+        let mut from: (usize, usize);
+        let mut to: (usize, usize);
+        let mut game: Game;
+        'outer: for i in 0..result.len() {
+            game = self.board_copy();
+            for j in 0..result[i].len() {
+                from = result[i][j].0;
+                to = result[i][j].1;
+                if !on_board(from) || !on_board(to) {
+                    info!(
+                        "from: ({}, {}) to: ({}, {}) excluded, being out of bounds",
+                        from.0, from.1, to.0, to.1
+                    );
+                    index.insert(0, i);
+                    continue 'outer;
+                } else if let Some(piece) = game.get_from_pos(from) {
+                    if let Some(other) = game.get_from_pos(to) {
                         if other.color == piece.color {
                             info!("from: ({}, {}) to: ({}, {}) excluded because it was targeting a friendly", from.0, from.1, to.0, to.1);
                             index.insert(0, i);
                             continue 'outer;
                         }
                     }
-                    if test_check && game.check_for_check(from, to) {
+                    if test_check && game.leaves_king_in_check(from, to) {
                         info!("from: ({}, {}) to: ({}, {}) at index {} excluded because it would put it in check", from.0, from.1, to.0, to.1, i);
                         index.insert(0, i);
                         continue 'outer;
@@ -547,7 +1581,6 @@ impl<'a> Game<'a> {
             result.remove(v);
         }
 
-        info!("check_valid_moves finished");
         result
     }
 
@@ -570,14 +1603,17 @@ impl<'a> Game<'a> {
                                     moves.push((pos.0, pos.1 + 2));
                                 }
 
-                                if pos.1 < 7 && self.get_from_pos((pos.0, pos.1 + 1)).is_none() {
+                                if pos.1 < BOARD_SIZE - 1
+                                    && self.get_from_pos((pos.0, pos.1 + 1)).is_none()
+                                {
                                     moves.push((pos.0, pos.1 + 1));
                                 }
 
-                                if pos.0 > 0 && pos.1 < 7 {
+                                if pos.0 > 0 && pos.1 < BOARD_SIZE - 1 {
                                     passant = false;
                                     if let Some(other) = self.get_from_pos((pos.0 - 1, pos.1)) {
                                         if other.color != piece.color
+                                            && other.kind == Kind::Pawn
                                             && pos.1 == 4
                                             && (self.last.0).0 == pos.0 - 1
                                             && (self.last.0).1 == pos.1 + 2
@@ -597,10 +1633,11 @@ impl<'a> Game<'a> {
                                         moves.push((pos.0 - 1, pos.1 + 1));
                                     }
                                 }
-                                if pos.0 < 7 && pos.1 < 7 {
+                                if pos.0 < BOARD_SIZE - 1 && pos.1 < BOARD_SIZE - 1 {
                                     passant = false;
                                     if let Some(other) = self.get_from_pos((pos.0 + 1, pos.1)) {
                                         if other.color != piece.color
+                                            && other.kind == Kind::Pawn
                                             && pos.1 == 4
                                             && (self.last.0).0 == pos.0 + 1
                                             && (self.last.0).1 == pos.1 + 2
@@ -637,6 +1674,7 @@ impl<'a> Game<'a> {
                                     passant = false;
                                     if let Some(other) = self.get_from_pos((pos.0 - 1, pos.1)) {
                                         if other.color != piece.color
+                                            && other.kind == Kind::Pawn
                                             && pos.1 == 3
                                             && (self.last.0).0 == pos.0 - 1
                                             && (self.last.0).1 == pos.1 - 2
@@ -656,10 +1694,11 @@ impl<'a> Game<'a> {
                                         moves.push((pos.0 - 1, pos.1 - 1));
                                     }
                                 }
-                                if pos.0 < 7 && pos.1 > 0 {
+                                if pos.0 < BOARD_SIZE - 1 && pos.1 > 0 {
                                     passant = false;
                                     if let Some(other) = self.get_from_pos((pos.0 + 1, pos.1)) {
                                         if other.color != piece.color
+                                            && other.kind == Kind::Pawn
                                             && pos.1 == 3
                                             && (self.last.0).0 == pos.0 + 1
                                             && (self.last.0).1 == pos.1 - 2
@@ -686,7 +1725,7 @@ impl<'a> Game<'a> {
                         let mut x: usize = pos.0;
                         let mut y: usize = pos.1;
                         // Vertically/horisontally
-                        while x < 7 {
+                        while x < BOARD_SIZE - 1 {
                             x += 1;
                             moves.push((x, pos.1));
                             if self.get_from_pos((x, pos.1)).is_some() {
@@ -702,7 +1741,7 @@ impl<'a> Game<'a> {
                             }
                         }
 
-                        while y < 7 {
+                        while y < BOARD_SIZE - 1 {
                             y += 1;
                             moves.push((pos.0, y));
                             if self.get_from_pos((pos.0, y)).is_some() {
@@ -722,7 +1761,7 @@ impl<'a> Game<'a> {
                         let mut x: usize = pos.0;
                         let mut y: usize = pos.1;
                         // Diagonally
-                        while x < 7 && y < 7 {
+                        while x < BOARD_SIZE - 1 && y < BOARD_SIZE - 1 {
                             x += 1;
                             y += 1;
                             moves.push((x, y));
@@ -733,7 +1772,7 @@ impl<'a> Game<'a> {
 
                         x = pos.0;
                         y = pos.1;
-                        while x < 7 && y > 0 {
+                        while x < BOARD_SIZE - 1 && y > 0 {
                             x += 1;
                             y -= 1;
                             moves.push((x, y));
@@ -744,7 +1783,7 @@ impl<'a> Game<'a> {
 
                         x = pos.0;
                         y = pos.1;
-                        while x > 0 && y < 7 {
+                        while x > 0 && y < BOARD_SIZE - 1 {
                             x -= 1;
                             y += 1;
                             moves.push((x, y));
@@ -768,7 +1807,7 @@ impl<'a> Game<'a> {
                         let mut x: usize = pos.0;
                         let mut y: usize = pos.1;
                         // Diagonally
-                        while x < 7 && y < 7 {
+                        while x < BOARD_SIZE - 1 && y < BOARD_SIZE - 1 {
                             x += 1;
                             y += 1;
                             moves.push((x, y));
@@ -779,7 +1818,7 @@ impl<'a> Game<'a> {
 
                         x = pos.0;
                         y = pos.1;
-                        while x < 7 && y > 0 {
+                        while x < BOARD_SIZE - 1 && y > 0 {
                             x += 1;
                             y -= 1;
                             moves.push((x, y));
@@ -790,7 +1829,7 @@ impl<'a> Game<'a> {
 
                         x = pos.0;
                         y = pos.1;
-                        while x > 0 && y < 7 {
+                        while x > 0 && y < BOARD_SIZE - 1 {
                             x -= 1;
                             y += 1;
                             moves.push((x, y));
@@ -812,7 +1851,7 @@ impl<'a> Game<'a> {
 
                         // Vertically/horisontally
                         x = pos.0;
-                        while x < 7 {
+                        while x < BOARD_SIZE - 1 {
                             x += 1;
                             moves.push((x, pos.1));
                             if self.get_from_pos((x, pos.1)).is_some() {
@@ -829,7 +1868,7 @@ impl<'a> Game<'a> {
                         }
 
                         y = pos.1;
-                        while y < 7 {
+                        while y < BOARD_SIZE - 1 {
                             y += 1;
                             moves.push((pos.0, y));
                             if self.get_from_pos((pos.0, y)).is_some() {
@@ -850,15 +1889,15 @@ impl<'a> Game<'a> {
                             if pos.1 >= 2 {
                                 moves.push((pos.0 - 1, pos.1 - 2));
                             }
-                            if pos.1 <= 5 {
+                            if pos.1 + 2 < BOARD_SIZE {
                                 moves.push((pos.0 - 1, pos.1 + 2));
                             }
                         }
-                        if pos.0 <= 6 {
+                        if pos.0 + 1 < BOARD_SIZE {
                             if pos.1 >= 2 {
                                 moves.push((pos.0 + 1, pos.1 - 2));
                             }
-                            if pos.1 <= 5 {
+                            if pos.1 + 2 < BOARD_SIZE {
                                 moves.push((pos.0 + 1, pos.1 + 2));
                             }
                         }
@@ -866,15 +1905,15 @@ impl<'a> Game<'a> {
                             if pos.1 >= 1 {
                                 moves.push((pos.0 - 2, pos.1 - 1));
                             }
-                            if pos.1 <= 6 {
+                            if pos.1 + 1 < BOARD_SIZE {
                                 moves.push((pos.0 - 2, pos.1 + 1));
                             }
                         }
-                        if pos.0 <= 5 {
+                        if pos.0 + 2 < BOARD_SIZE {
                             if pos.1 >= 1 {
                                 moves.push((pos.0 + 2, pos.1 - 1));
                             }
-                            if pos.1 <= 6 {
+                            if pos.1 + 1 < BOARD_SIZE {
                                 moves.push((pos.0 + 2, pos.1 + 1));
                             }
                         }
@@ -885,16 +1924,16 @@ impl<'a> Game<'a> {
                             if pos.1 > 0 {
                                 moves.push((pos.0 - 1, pos.1 - 1));
                             }
-                            if pos.1 < 7 {
+                            if pos.1 < BOARD_SIZE - 1 {
                                 moves.push((pos.0 - 1, pos.1 + 1));
                             }
                         }
-                        if pos.0 < 7 {
+                        if pos.0 < BOARD_SIZE - 1 {
                             moves.push((pos.0 + 1, pos.1));
                             if pos.1 > 0 {
                                 moves.push((pos.0 + 1, pos.1 - 1));
                             }
-                            if pos.1 < 7 {
+                            if pos.1 < BOARD_SIZE - 1 {
                                 moves.push((pos.0 + 1, pos.1 + 1));
                             }
                         }
@@ -902,7 +1941,7 @@ impl<'a> Game<'a> {
                         if pos.1 > 0 {
                             moves.push((pos.0, pos.1 - 1));
                         }
-                        if pos.1 < 7 {
+                        if pos.1 < BOARD_SIZE - 1 {
                             moves.push((pos.0, pos.1 + 1));
                         }
 
@@ -914,10 +1953,10 @@ impl<'a> Game<'a> {
                             Color::White => {
                                 if pos.0 == 4 && pos.1 == 0 {
                                     if self.white_can_castle_left {
-                                        game = self.clone();
+                                        game = self.scratch();
                                         for i in 1..4 {
                                             if i == 3 {
-                                                if game.get_from_pos((1, pos.1)).is_none() {
+                                                if self.path_is_clear(pos, (0, pos.1)) {
                                                     if let Some(rook) =
                                                         game.get_from_pos((0, pos.1))
                                                     {
@@ -945,10 +1984,10 @@ impl<'a> Game<'a> {
                                         }
                                     }
                                     if self.white_can_castle_right {
-                                        game = self.clone();
+                                        game = self.scratch();
                                         for i in 1..4 {
                                             if i == 3 {
-                                                if game.get_from_pos((6, pos.1)).is_none() {
+                                                if self.path_is_clear(pos, (7, pos.1)) {
                                                     if let Some(rook) =
                                                         game.get_from_pos((7, pos.1))
                                                     {
@@ -978,12 +2017,12 @@ impl<'a> Game<'a> {
                                 }
                             }
                             Color::Black => {
-                                if pos.0 == 4 && pos.1 == 7 {
+                                if pos.0 == 4 && pos.1 == BOARD_SIZE - 1 {
                                     if self.black_can_castle_left {
-                                        game = self.clone();
+                                        game = self.scratch();
                                         for i in 1..4 {
                                             if i == 3 {
-                                                if game.get_from_pos((1, pos.1)).is_none() {
+                                                if self.path_is_clear(pos, (0, pos.1)) {
                                                     if let Some(rook) =
                                                         game.get_from_pos((0, pos.1))
                                                     {
@@ -1011,10 +2050,10 @@ impl<'a> Game<'a> {
                                         }
                                     }
                                     if self.black_can_castle_right {
-                                        game = self.clone();
+                                        game = self.scratch();
                                         for i in 1..4 {
                                             if i == 3 {
-                                                if game.get_from_pos((6, pos.1)).is_none() {
+                                                if self.path_is_clear(pos, (7, pos.1)) {
                                                     if let Some(rook) =
                                                         game.get_from_pos((7, pos.1))
                                                     {
@@ -1056,9 +2095,277 @@ impl<'a> Game<'a> {
         result
     }
 
-    /// Sees whether the king of the given color is currently in check or not.
+    /// Sees whether the king of the given color is checkmated.
+    ///
+    pub fn is_checkmate(&self, color: Color) -> bool {
+        self.in_check(color) && self.evasions(color).is_empty()
+    }
+
+    /// Checks whether a move, as returned from `valid_moves`, opens the line between one of the
+    /// mover's rooks, bishops or queens and the enemy king, giving a discovered check.
+    ///
+    /// Only the squares on the lines to the king are looked at, so this is cheaper than playing
+    /// the move and calling `in_check`. A check given by the moving piece itself doesn't count.
+    ///
+    pub fn discovers_check(&self, m: &[((usize, usize), (usize, usize))]) -> bool {
+        let color = match m.first().and_then(|v| self.get_from_pos(v.0)) {
+            Some(piece) => piece.color,
+            None => return false,
+        };
+        let king = match self.by_kind_and_color(Kind::King, color.opposite()).first() {
+            Some(&(pos, _)) => pos,
+            None => return false,
+        };
+
+        // The squares the move changes, and whether they end up occupied.
+        let mut changed: Vec<((usize, usize), bool)> = Vec::new();
+        for &(from, to) in m {
+            changed.retain(|&(pos, _)| pos != from && pos != to);
+            changed.push((from, false));
+            changed.push((to, true));
+        }
+        let occupied_after = |pos: (usize, usize)| match changed.iter().find(|(p, _)| *p == pos) {
+            Some(&(_, occupied)) => occupied,
+            None => self.get_from_pos(pos).is_some(),
+        };
+
+        self.by_color(color).into_iter().any(|(pos, piece)| {
+            let straight = pos.0 == king.0 || pos.1 == king.1;
+            let slides = match piece.kind {
+                Kind::Rook => straight,
+                Kind::Bishop => !straight,
+                Kind::Queen => true,
+                _ => false,
+            };
+            let line = squares_between(pos, king);
+            slides
+                && !changed.iter().any(|&(p, _)| p == pos)
+                && line.iter().any(|&p| changed.contains(&(p, false)))
+                && line.iter().all(|&p| !occupied_after(p))
+        })
+    }
+
+    /// Returns the positions of the pieces giving check to the king of the given color.
+    ///
+    pub fn checkers(&self, color: Color) -> Vec<(usize, usize)> {
+        let king = match self.by_kind_and_color(Kind::King, color).first() {
+            Some(&(pos, _)) => pos,
+            None => return Vec::new(),
+        };
+        self.by_color(color.opposite())
+            .into_iter()
+            .map(|(pos, _)| pos)
+            .filter(|&pos| self.attacked_squares(pos).contains(&king))
+            .collect()
+    }
+
+    /// Returns the moves that get the king of the given color out of check, in the same order as
+    /// `all_valid_moves`. Returns an empty vector if the king isn't in check.
+    ///
+    /// Only king moves, captures of the checking piece and moves onto the squares between a
+    /// sliding checker and the king are tested for legality, which is much cheaper than testing
+    /// every move. In a double check only king moves are tried.
+    ///
+    pub fn evasions(&self, color: Color) -> Vec<Move> {
+        let king = match self.by_kind_and_color(Kind::King, color).first() {
+            Some(&(pos, _)) => pos,
+            None => return Vec::new(),
+        };
+        let checkers = self.checkers(color);
+        if checkers.is_empty() {
+            return Vec::new();
+        }
+        let mut targets = Vec::new();
+        if checkers.len() == 1 {
+            targets.push(checkers[0]);
+            targets.append(&mut squares_between(king, checkers[0]));
+        }
+
+        let mut candidates: Vec<Move> = Vec::new();
+        for (pos, _) in self.by_color(color) {
+            for m in self.raw_moves(pos) {
+                let evades = if pos == king {
+                    m.len() != 3
+                } else {
+                    m.iter().any(|(_, to)| targets.contains(to))
+                };
+                if evades {
+                    candidates.push(m);
+                }
+            }
+        }
+        self.filter_moves(candidates, true)
+    }
+
+    /// Checks whether the check against the king of the given color can be answered by capturing
+    /// the checking piece or putting a piece in the way, rather than by moving the king.
+    ///
+    pub fn can_block_check(&self, color: Color) -> bool {
+        self.evasions(color)
+            .iter()
+            .any(|m| match self.get_from_pos(m[0].0) {
+                Some(piece) => piece.kind != Kind::King,
+                None => false,
+            })
+    }
+
+    /// Returns the empty squares the king of the given color can legally step to, from A1 through
+    /// H1, then A2 through H2, until H8. Castling and captures aren't included, so in a king and
+    /// queen ending this is the box the defending king is confined to.
+    ///
+    pub fn king_escape_squares(&self, color: Color) -> Vec<(usize, usize)> {
+        let king = match self.by_kind_and_color(Kind::King, color).first() {
+            Some(&(pos, _)) => pos,
+            None => return Vec::new(),
+        };
+        let mut squares: Vec<(usize, usize)> = self
+            .valid_moves(king)
+            .iter()
+            .filter(|m| m.len() == 1 && self.get_from_pos(m[0].1).is_none())
+            .map(|m| m[0].1)
+            .collect();
+        squares.sort_by_key(|&(x, y)| (y, x));
+        squares
+    }
+
+    /// Names the pattern of the checkmate on the board, for annotating games. Returns None if
+    /// neither side is checkmated or the pattern isn't recognized.
+    ///
+    /// Smothered mates ("smothered mate") and back-rank mates ("back-rank mate") are recognized.
+    ///
+    pub fn name_checkmate(&self) -> Option<&'static str> {
+        let color = *[Color::White, Color::Black]
+            .iter()
+            .find(|&&color| self.is_checkmate(color))?;
+        let king = self.by_kind_and_color(Kind::King, color).first()?.0;
+        let checkers = self.checkers(color);
+        if checkers.len() != 1 {
+            return None;
+        }
+        let checker = self.get_from_pos(checkers[0])?;
+        let own = |pos: &(usize, usize)| {
+            self.get_from_pos(*pos)
+                .filter(|piece| piece.color == color)
+                .is_some()
+        };
+        let around = self.attacks_from(Kind::King, color, king);
+
+        // A knight mates a king hemmed in on all sides by its own pieces.
+        if checker.kind == Kind::Knight && around.iter().all(own) {
+            return Some("smothered mate");
+        }
+
+        // A rook or queen mates along the back rank, with the king's own pieces blocking its way
+        // forward.
+        let back_rank = match color {
+            Color::White => 0,
+            Color::Black => BOARD_SIZE - 1,
+        };
+        if (checker.kind == Kind::Rook || checker.kind == Kind::Queen)
+            && king.1 == back_rank
+            && checkers[0].1 == back_rank
+            && around.iter().filter(|pos| pos.1 != back_rank).all(own)
+        {
+            return Some("back-rank mate");
+        }
+
+        None
+    }
+
+    /// Checks that every square between the two given squares is empty.
+    fn path_is_clear(&self, from: (usize, usize), to: (usize, usize)) -> bool {
+        squares_between(from, to)
+            .iter()
+            .all(|&pos| self.get_from_pos(pos).is_none())
+    }
+
+    /// Sees whether the player whose turn it is to move is in check.
+    ///
+    pub fn side_to_move_in_check(&self) -> bool {
+        self.in_check(self.side_to_move())
+    }
+
+    /// Returns the color to move, which is the opposite of the color that moved last.
+    pub(crate) fn side_to_move(&self) -> Color {
+        self.last_color.opposite()
+    }
+
+    /// Returns a copy of the game where it is the given color's turn, for analysis questions like
+    /// "what would white do if it were white to move here?". Passing the color already to move
+    /// returns a plain copy. Otherwise en passant is cleared, since it only applies right after
+    /// the pawn push, and the copy has no history.
+    ///
+    /// The copy isn't checked: if the side that moved last gave check, the result has the side
+    /// not to move in check, which can't happen in a real game.
+    ///
+    pub fn with_side_to_move(&self, color: Color) -> Game<'a> {
+        let mut game = self.clone();
+        if color != self.side_to_move() {
+            let mut position = self.scratch();
+            position.last_color = color.opposite();
+            position.last = ((0, 0), (0, 0));
+            game.set_position(&position);
+        }
+        game
+    }
+
+    /// Checks whether the given color may move the piece at the given position: the piece belongs
+    /// to it and it is its turn. This is what has to hold before a move submitted by a player is
+    /// played.
+    ///
+    pub fn can_player_move(&self, pos: (usize, usize), color: Color) -> bool {
+        self.side_to_move() == color
+            && self
+                .get_from_pos(pos)
+                .filter(|p| p.color == color)
+                .is_some()
+    }
+
+    /// Returns whose turn it is after the given number of half-moves have been played from the
+    /// position the game started in.
     ///
+    /// The starting side is taken from before the first move made with `move_pieces`, or is the
+    /// current side to move if no moves have been made, so games set up from a FEN with black to
+    /// move are counted correctly.
+    ///
+    pub fn color_to_move_after(&self, ply_count: usize) -> Color {
+        let first = match self.undo_stack.first() {
+            Some(undo) => undo.last_color.opposite(),
+            None => self.side_to_move(),
+        };
+        match ply_count % 2 {
+            0 => first,
+            _ => first.opposite(),
+        }
+    }
+
+    /// Like `in_check`, but returns an error instead of panicking if the king of the given color
+    /// is missing and missing kings aren't ignored.
+    ///
+    /// This is meant for positions from outside sources, which may be partial or corrupt.
+    ///
+    pub fn try_in_check(&self, color: Color) -> Result<bool, MissingKing> {
+        self.has_king(color)?;
+        Ok(self.in_check(color))
+    }
 
+    /// Checks that the given color has a king, unless kings or check are ignored.
+    fn has_king(&self, color: Color) -> Result<(), MissingKing> {
+        if self.ignore_kings
+            || self.ignore_check
+            || !self.by_kind_and_color(Kind::King, color).is_empty()
+        {
+            Ok(())
+        } else {
+            Err(MissingKing(color))
+        }
+    }
+
+    /// Sees whether the king of the given color is currently in check or not.
+    ///
+    /// Panics if the king is missing, unless missing kings are ignored with `ignore_kings`. Use
+    /// `try_in_check` to get an error instead.
+    ///
     pub fn in_check(&self, color: Color) -> bool {
         info!("in_check called with args: color: {}", color);
         if self.ignore_check {
@@ -1091,72 +2398,242 @@ impl<'a> Game<'a> {
         info!("Not in check");
         false
     }
-    #[allow(clippy::all)]
-    fn check_for_check(&self, from: (usize, usize), to: (usize, usize)) -> bool {
-        info!(
-            "check_for_check called with args: from ({}, {}) to: ({}, {})",
-            from.0, from.1, to.0, to.1
-        );
-        let mut game = self.clone();
-        let color: Color;
-        match game.get_from_pos(from) {
-            Some(piece) => color = piece.color,
-            None => panic!("No piece found at position ({}, {}).", from.0, from.1),
-        }
-        game.move_piece(from, to);
-        game.in_check(color)
-    }
 
-    /// Checks whether the game is won, and returns the victory type and the color of the victor,
-    /// or None if the game isn't won yet. In case of a draw a random color is returned.
+    /// Counts how many pieces of the given color attack each square, indexed the same way as the
+    /// board, so `map[3][3]` is the number of attackers on D4.
     ///
-
-    pub fn check_victory(&self) -> Option<(VictoryStatus, Color)> {
-        if self.seventy_five_move_rule >= 75 {
-            return Some((VictoryStatus::Draw, Color::White));
-        }
-        if self.board_history.len() >= 5 {
-            info!("Checking for five fold repetition");
-            let mut matches = 0;
-            let last = match self.board_history.last() {
-                Some(v) => v,
-                None => panic!(),
-            };
-            'rep: for v in &self.board_history {
-                for x in 0..8 {
-                    for y in 0..8 {
-                        if v[x][y] != last[x][y] {
-                            continue 'rep;
-                        }
-                    }
-                }
-                matches += 1;
-            }
-
-            if matches >= 5 {
-                return Some((VictoryStatus::Draw, Color::White));
+    /// Squares occupied by pieces of the same color count as attacked, as they are defended.
+    ///
+    pub fn control_map(&self, color: Color) -> [[u8; BOARD_SIZE]; BOARD_SIZE] {
+        let mut map = [[0; BOARD_SIZE]; BOARD_SIZE];
+        for (pos, _) in self.by_color(color) {
+            for target in self.attacked_squares(pos) {
+                map[target.0][target.1] += 1;
             }
         }
+        map
+    }
 
-        'outer: for color in vec![Color::Black, Color::White] {
-            let pieces = self.by_color(color);
+    /// Returns the squares the piece at the given position attacks, ignoring pins and whose piece
+    /// is on the target square.
+    fn attacked_squares(&self, pos: (usize, usize)) -> Vec<(usize, usize)> {
+        match self.get_from_pos(pos) {
+            Some(piece) => self.attacks_from(piece.kind, piece.color, pos),
+            None => Vec::new(),
+        }
+    }
 
-            for (pos, _) in pieces {
-                if self.valid_moves(pos).len() > 0 {
-                    continue 'outer;
-                }
+    /// Returns the squares a piece of the given kind and color would attack from the given
+    /// position, whether or not it is actually there. Sliding pieces are stopped by the pieces
+    /// currently on the board.
+    ///
+    /// Like `control_map`, this ignores pins and whose piece is on the target square, and pawns
+    /// only attack diagonally.
+    ///
+    pub fn attacks_from(
+        &self,
+        kind: Kind,
+        color: Color,
+        pos: (usize, usize),
+    ) -> Vec<(usize, usize)> {
+        if !on_board(pos) {
+            return Vec::new();
+        }
+        let forward = match color {
+            Color::White => 1,
+            Color::Black => -1,
+        };
+        let (steps, slide): (&[(isize, isize)], bool) = match kind {
+            Kind::Pawn => (&[(-1, forward), (1, forward)], false),
+            Kind::Knight => (
+                &[
+                    (1, 2),
+                    (2, 1),
+                    (2, -1),
+                    (1, -2),
+                    (-1, -2),
+                    (-2, -1),
+                    (-2, 1),
+                    (-1, 2),
+                ],
+                false,
+            ),
+            Kind::Bishop => (&[(1, 1), (1, -1), (-1, -1), (-1, 1)], true),
+            Kind::Rook => (&[(0, 1), (1, 0), (0, -1), (-1, 0)], true),
+            Kind::Queen | Kind::King => (
+                &[
+                    (0, 1),
+                    (1, 1),
+                    (1, 0),
+                    (1, -1),
+                    (0, -1),
+                    (-1, -1),
+                    (-1, 0),
+                    (-1, 1),
+                ],
+                kind == Kind::Queen,
+            ),
+        };
+
+        let mut result = Vec::new();
+        for &(dx, dy) in steps {
+            let (mut x, mut y) = (pos.0 as isize + dx, pos.1 as isize + dy);
+            while x >= 0 && y >= 0 && on_board((x as usize, y as usize)) {
+                result.push((x as usize, y as usize));
+                if !slide || self.get_from_pos((x as usize, y as usize)).is_some() {
+                    break;
+                }
+                x += dx;
+                y += dy;
             }
+        }
+        result
+    }
 
-            let opposite: Color = if color == Color::White {
-                Color::Black
-            } else {
-                Color::White
-            };
+    /// Checks whether moving the piece on `from` to `to` leaves the king of its color attacked.
+    ///
+    /// Only the board is copied, without any history, and instead of generating every move of
+    /// the opponent the king's square is tested by looking outwards from it for each kind of
+    /// piece. The move is made as a single step, so this is meant for each step of a castle or en
+    /// passant in turn.
+    ///
+    /// Panics if there is no piece on `from`, or if the king is missing and missing kings aren't
+    /// ignored.
+    ///
+    pub fn leaves_king_in_check(&self, from: (usize, usize), to: (usize, usize)) -> bool {
+        if self.ignore_check {
+            return false;
+        }
+        let piece = match self.get_from_pos(from) {
+            Some(piece) => piece,
+            None => panic!("No piece found at position ({}, {}).", from.0, from.1),
+        };
+        let mut game = self.board_copy();
+        game.board[to.0][to.1] = Some(piece);
+        game.board[from.0][from.1] = None;
+
+        let king = (0..BOARD_SIZE)
+            .flat_map(|x| (0..BOARD_SIZE).map(move |y| (x, y)))
+            .find(|&(x, y)| {
+                game.board[x][y]
+                    .filter(|p| p.kind == Kind::King && p.color == piece.color)
+                    .is_some()
+            });
+        let king = match king {
+            Some(king) => king,
+            None if self.ignore_kings => return false,
+            None => panic!("There is no king"),
+        };
+
+        // A piece attacks the king exactly when the same kind of piece on the king's square
+        // would attack it.
+        let other = piece.color.opposite();
+        [
+            Kind::Pawn,
+            Kind::Rook,
+            Kind::Knight,
+            Kind::Bishop,
+            Kind::Queen,
+            Kind::King,
+        ]
+        .iter()
+        .any(|&kind| {
+            game.attacks_from(kind, piece.color, king)
+                .into_iter()
+                .any(|pos| {
+                    game.get_from_pos(pos)
+                        .filter(|p| p.kind == kind && p.color == other)
+                        .is_some()
+                })
+        })
+    }
+
+    /// Copies the pieces, rights and settings of the game with no history, which is all that is
+    /// needed to try out steps on the board.
+    fn board_copy(&self) -> Game<'a> {
+        Game {
+            board_history: Vec::new(),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            move_history: Vec::new(),
+            san_history: Vec::new(),
+            record_san: false,
+            move_cache: None,
+            ..*self
+        }
+    }
+
+    /// Checks that the position could have come up in a game: each side has exactly one king,
+    /// no pawns stand on the first or eighth rank, the side that just moved isn't in check, and
+    /// neither side has more pawns and promoted pieces than its eight pawns allow.
+    ///
+    /// Returns the first problem found. Useful before trusting a position built by hand or read
+    /// from a FEN.
+    ///
+    pub fn is_legal_position(&self) -> Result<(), IllegalPosition> {
+        for &color in [Color::White, Color::Black].iter() {
+            let kings = self.by_kind_and_color(Kind::King, color).len();
+            if kings != 1 {
+                return Err(IllegalPosition::KingCount(color, kings));
+            }
+        }
+
+        if let Some((pos, _)) = self
+            .by_kind(Kind::Pawn)
+            .into_iter()
+            .find(|(pos, _)| pos.1 == 0 || pos.1 == BOARD_SIZE - 1)
+        {
+            return Err(IllegalPosition::PawnOnBackRank(pos));
+        }
+
+        let waiting = self.last_color;
+        if self.in_check(waiting) {
+            return Err(IllegalPosition::OpponentInCheck(waiting));
+        }
+
+        for &color in [Color::White, Color::Black].iter() {
+            let count = |kind| self.by_kind_and_color(kind, color).len();
+            let promoted = count(Kind::Queen).saturating_sub(1)
+                + count(Kind::Rook).saturating_sub(2)
+                + count(Kind::Knight).saturating_sub(2)
+                + count(Kind::Bishop).saturating_sub(2);
+            if count(Kind::Pawn) + promoted > BOARD_SIZE {
+                return Err(IllegalPosition::TooManyPieces(color));
+            }
+        }
+        Ok(())
+    }
+
+    /// Like `check_victory`, but returns an error instead of panicking if a king is missing and
+    /// missing kings aren't ignored.
+    ///
+    pub fn try_check_victory(&self) -> Result<Option<(VictoryStatus, Color)>, MissingKing> {
+        self.has_king(Color::White)?;
+        self.has_king(Color::Black)?;
+        Ok(self.check_victory())
+    }
+
+    /// Checks whether the game is won, and returns the victory type and the color of the victor,
+    /// or None if the game isn't won yet. In case of a draw a random color is returned.
+    ///
+    pub fn check_victory(&self) -> Option<(VictoryStatus, Color)> {
+        if self.must_draw_seventy_five() {
+            return Some((VictoryStatus::Draw, Color::White));
+        }
+        if self.repetitions() >= 5 {
+            info!("Five fold repetition");
+            return Some((VictoryStatus::Draw, Color::White));
+        }
+
+        for &color in &[Color::Black, Color::White] {
+            if self.has_legal_move(color) {
+                continue;
+            }
 
             if self.in_check(color) {
-                return Some((VictoryStatus::Checkmate, opposite));
+                return Some((VictoryStatus::Checkmate, color.opposite()));
             } else if self.last_color != color {
-                return Some((VictoryStatus::Stalemate, opposite));
+                return Some((VictoryStatus::Stalemate, color.opposite()));
             }
         }
 
@@ -1172,7 +2649,9 @@ impl<'a> Game<'a> {
     /// If `unicode` is `true` the pieces are represented by unicode symbols instead of letters.
     /// Only black pieces are used, as they are easier to see.
     ///
-
+    /// A promotion is written with the letter of the default promotion, like "e8=N", as that is
+    /// the piece `move_pieces` turns the pawn into.
+    ///
     pub fn move_to_an(
         &self,
         m: &[((usize, usize), (usize, usize))],
@@ -1237,23 +2716,27 @@ impl<'a> Game<'a> {
                 }
             }
 
-            let mut row = false;
-            let mut col = false;
-            for i in self.by_kind_and_color(piece.kind, piece.color) {
-                let (pos, _) = i;
-                if pos.0 != (m[0].0).0 && pos.1 != (m[0].0).1 {
+            // Another piece of the same kind that can reach the destination makes the move
+            // ambiguous. The file is preferred for disambiguation, then the rank, then both.
+            let mut ambiguous = false;
+            let mut same_file = false;
+            let mut same_rank = false;
+            if piece.kind != Kind::Pawn {
+                for (pos, _) in self.by_kind_and_color(piece.kind, piece.color) {
+                    if pos == m[0].0 {
+                        continue;
+                    }
                     for v in self.valid_moves(pos) {
-                        let (tmp_x, tmp_y) = v.last().unwrap().1;
-                        if tmp_x == dest.0 && tmp_y == dest.1 {
-                            if pos.0 == (m[0].0).0 {
-                                row = true;
-                            } else {
-                                col = true;
-                            }
+                        if v.last().unwrap().1 == dest {
+                            ambiguous = true;
+                            same_file |= pos.0 == (m[0].0).0;
+                            same_rank |= pos.1 == (m[0].0).1;
                         }
                     }
                 }
             }
+            let col = ambiguous && (!same_file || same_rank);
+            let row = ambiguous && same_file;
 
             if col {
                 s.push(match (m[0].0).0 {
@@ -1317,8 +2800,14 @@ impl<'a> Game<'a> {
                     panic!("Only pawns should be able to have moves that consists of two moves.");
                 }
             }
-            if piece.kind == Kind::Pawn && (dest.1 == 7 || dest.1 == 0) {
-                s.push_str("=Q");
+            if piece.kind == Kind::Pawn && (dest.1 == BOARD_SIZE - 1 || dest.1 == 0) {
+                s.push('=');
+                s.push(match self.default_promotion {
+                    Kind::Rook => 'R',
+                    Kind::Knight => 'N',
+                    Kind::Bishop => 'B',
+                    _ => 'Q',
+                });
             }
         }
 
@@ -1326,7 +2815,7 @@ impl<'a> Game<'a> {
             Color::White => Color::Black,
             Color::Black => Color::White,
         };
-        let mut g = self.clone();
+        let mut g = self.scratch();
 
         g.move_pieces(m);
         if let Some(v) = g.check_victory() {
@@ -1348,13 +2837,66 @@ impl<'a> Game<'a> {
         s
     }
 
+    /// Returns the legal moves of the piece at the given position in [algebraic
+    /// notation](https://en.wikipedia.org/wiki/Algebraic_notation_(chess)) (AN).
+    ///
+    /// The moves are in the same order as returned from `valid_moves`, and include check and
+    /// checkmate symbols. This is useful for showing the available moves of a selected piece.
+    ///
+    pub fn legal_sans(&self, pos: (usize, usize)) -> Vec<String> {
+        self.valid_moves(pos)
+            .iter()
+            .map(|m| self.move_to_an(m, false, false))
+            .collect()
+    }
+
+    /// Returns the legal moves of the given color, in the same order as `all_valid_moves`, each
+    /// with whether it gives check and whether it is checkmate.
+    ///
+    /// Each move is played once on a copy of the board, so a move list can show `+` and `#`
+    /// without notating every move. Mate is found with `has_legal_move`, which stops at the
+    /// first reply.
+    ///
+    pub fn legal_moves_annotated(&self, color: Color) -> Vec<(Move, bool, bool)> {
+        let other = color.opposite();
+        self.all_valid_moves(color)
+            .into_iter()
+            .map(|m| {
+                let mut game = self.board_copy();
+                game.move_pieces(&m);
+                let check = game.in_check(other);
+                let mate = check && !game.has_legal_move(other);
+                (m, check, mate)
+            })
+            .collect()
+    }
+
+    /// Maps each legal move of the given color in [algebraic
+    /// notation](https://en.wikipedia.org/wiki/Algebraic_notation_(chess)) (AN) to the moves it
+    /// denotes.
+    ///
+    /// Properly disambiguated notation maps to a single move, so a key with several moves points
+    /// to a notation problem rather than a choice the user has to make. This is useful for
+    /// validating and completing moves typed by a user.
+    ///
+    pub fn legal_san_map(&self, color: Color) -> HashMap<String, Vec<Move>> {
+        let mut map: HashMap<String, Vec<Move>> = HashMap::new();
+        for m in self.all_valid_moves(color) {
+            map.entry(self.move_to_an(&m, false, false))
+                .or_default()
+                .push(m);
+        }
+        map
+    }
+
     /// Turns a string in [algebraic
     /// notation](https://en.wikipedia.org/wiki/Algebraic_notation_(chess)) (AN) into a move that can be passed to `move_pieces`.
     ///
     /// This function supports abbreviated algebraic notation, which means that certain characters
     /// can be removed, as long as it is unambiguous. For example, an 'x' (which signals a capture)
     /// is completely ignored, and can even be added to moves that doesn't end with a capture. The
-    /// same goes for '=Q' (which signals a pawn promotion) and 'e.p.' (which signals *en passant*).
+    /// same goes for '=Q', '=R', '=B' and '=N' (which signal a pawn promotion) and 'e.p.' (which
+    /// signals *en passant*). Use `an_to_move_promote` to also get the promotion piece.
     ///
     /// The pieces can be represented by both letters and unicode symbols.
     ///
@@ -1403,7 +2945,7 @@ impl<'a> Game<'a> {
         if let Kind::Pawn = kind {
             if len >= 6 && &s[len - 4..len] == "e.p." {
                 len -= 4;
-            } else if len >= 4 && &s[len - 2..len] == "=Q" {
+            } else if len >= 4 && ["=Q", "=R", "=B", "=N"].contains(&&s[len - 2..len]) {
                 len -= 2;
             }
 
@@ -1509,9 +3051,33 @@ impl<'a> Game<'a> {
         result
     }
 
-    /// Turns a move tuple into a human readable description.
+    /// Turns a string in algebraic notation into a move like `an_to_move`, along with the piece
+    /// given after an '=' for a pawn promotion, like the knight of "e8=N".
     ///
+    /// The kind is `None` when the promotion piece is left out, and then the default promotion
+    /// applies. The move and kind can be passed to `play`, or the kind set with
+    /// `default_promotion` before calling `move_pieces`.
+    ///
+    pub fn an_to_move_promote(&self, s: &str, color: Color) -> Option<(Move, Option<Kind>)> {
+        let promotion = match s.find('=') {
+            Some(i) => Some(match &s[i..] {
+                "=Q" => Kind::Queen,
+                "=R" => Kind::Rook,
+                "=B" => Kind::Bishop,
+                "=N" => Kind::Knight,
+                _ => return None,
+            }),
+            None => None,
+        };
+        let m = self.an_to_move(s, color)?;
+        if promotion.is_some() && self.check_promotion(m[0].0, m[0].1, promotion).is_err() {
+            return None;
+        }
+        Some((m, promotion))
+    }
 
+    /// Turns a move tuple into a human readable description.
+    ///
     pub fn move_to_string(&self, m: &((usize, usize), (usize, usize))) -> String {
         let mut s = String::new();
         let from = m.0;
@@ -1546,7 +3112,6 @@ impl<'a> Game<'a> {
 
     /// Turns an array of move tuples, like entries returned from valid_moves, into a human readable description.
     ///
-
     pub fn moves_to_string(&self, m: &[((usize, usize), (usize, usize))]) -> String {
         let mut s = String::new();
         let mut first = true;
@@ -1574,56 +3139,12 @@ impl<'a> Game<'a> {
         let mut s = String::new();
         let mut y: usize;
 
-        for y1 in 0..8 {
-            y = 7 - y1;
-            for x in 0..8 {
-                s.push(if let Some(p) = self.get_from_pos((x, y)) {
-                    match p.color {
-                        Color::White => {
-                            if unicode {
-                                match p.kind {
-                                    Kind::Pawn => '\u{2659}',
-                                    Kind::Rook => '\u{2656}',
-                                    Kind::Knight => '\u{2658}',
-                                    Kind::Bishop => '\u{2657}',
-                                    Kind::Queen => '\u{2655}',
-                                    Kind::King => '\u{2654}',
-                                }
-                            } else {
-                                match p.kind {
-                                    Kind::Pawn => 'P',
-                                    Kind::Rook => 'R',
-                                    Kind::Knight => 'N',
-                                    Kind::Bishop => 'B',
-                                    Kind::Queen => 'Q',
-                                    Kind::King => 'K',
-                                }
-                            }
-                        }
-                        Color::Black => {
-                            if unicode {
-                                match p.kind {
-                                    Kind::Pawn => '\u{265f}',
-                                    Kind::Rook => '\u{265c}',
-                                    Kind::Knight => '\u{265e}',
-                                    Kind::Bishop => '\u{265d}',
-                                    Kind::Queen => '\u{265b}',
-                                    Kind::King => '\u{265a}',
-                                }
-                            } else {
-                                match p.kind {
-                                    Kind::Pawn => 'p',
-                                    Kind::Rook => 'r',
-                                    Kind::Knight => 'n',
-                                    Kind::Bishop => 'b',
-                                    Kind::Queen => 'q',
-                                    Kind::King => 'k',
-                                }
-                            }
-                        }
-                    }
-                } else {
-                    ' '
+        for y1 in 0..BOARD_SIZE {
+            y = BOARD_SIZE - 1 - y1;
+            for x in 0..BOARD_SIZE {
+                s.push(match self.get_from_pos((x, y)) {
+                    Some(p) => piece_glyph(p, unicode),
+                    None => ' ',
                 });
             }
 
@@ -1635,44 +3156,336 @@ impl<'a> Game<'a> {
     }
 
     fn save_board(&mut self) {
-        self.board_history.push(self.board);
+        let key = self.repetition_key();
+        self.board_history.push(key);
+    }
+
+    /// Returns what identifies the current position for the repetition rules.
+    ///
+    /// The en passant square only counts when a pawn of the side to move stands next to the pawn
+    /// that was pushed, so it could be taken. Pins are ignored.
+    fn repetition_key(&self) -> RepetitionKey<'a> {
+        let side_to_move = self.side_to_move();
+        let en_passant = self.en_passant_square().filter(|_| {
+            let pushed = self.last.1;
+            [pushed.0.wrapping_sub(1), pushed.0 + 1].iter().any(|&x| {
+                on_board((x, pushed.1))
+                    && self.get_from_pos((x, pushed.1))
+                        == Some(Piece::get(side_to_move, Kind::Pawn))
+            })
+        });
+        RepetitionKey {
+            board: self.board,
+            side_to_move,
+            castling: [
+                self.white_can_castle_right,
+                self.white_can_castle_left,
+                self.black_can_castle_right,
+                self.black_can_castle_left,
+            ],
+            en_passant,
+        }
+    }
+
+    /// Returns a Zobrist hash of what makes positions the same for the repetition rules: the
+    /// pieces, the side to move, the castling rights and en passant, if a pawn could take.
+    ///
+    /// Positions that `three_fold_repetition` counts as the same get the same hash, so callers
+    /// can keep their own list of hashes and count with `count_repetitions` instead of relying on
+    /// the history the game collects.
+    ///
+    pub fn position_hash(&self) -> u64 {
+        let key = self.repetition_key();
+        let [white_right, white_left, black_right, black_left] = key.castling;
+        let flags = [
+            key.side_to_move == Color::Black,
+            white_right,
+            white_left,
+            black_right,
+            black_left,
+        ];
+        hash_position(&key.board, &flags, key.en_passant)
     }
 
     /// Checks whether there has occured a three fold repetition.
-    #[allow(clippy::all)]
     pub fn three_fold_repetition(&self) -> bool {
-        if self.board_history.len() >= 3 {
-            info!("Checking for three fold repetition");
-            let mut matches = 0;
-            let last = match self.board_history.last() {
-                Some(v) => v,
-                None => panic!(),
-            };
-            'rep: for v in &self.board_history {
-                for x in 0..8 {
-                    for y in 0..8 {
-                        if v[x][y] != last[x][y] {
-                            continue 'rep;
-                        }
-                    }
-                }
-                matches += 1;
-            }
+        self.repetitions() >= 3
+    }
 
-            if matches >= 3 {
-                return true;
-            }
+    /// Checks whether two games are in the same position for the repetition rules: the same
+    /// pieces on the same squares, side to move, castling rights and en passant possibilities.
+    /// Unlike `==`, the clocks and the turn number aren't compared, so games that reached the
+    /// position through different moves are the same.
+    ///
+    /// This lets callers that rebuild games from a move list count repeated positions
+    /// themselves.
+    ///
+    pub fn same_position_as(&self, other: &Game) -> bool {
+        self.repetition_key() == other.repetition_key()
+    }
+
+    /// Counts how many times the current position has occured since the last irreversible move.
+    /// Positions only count as the same if the castling rights and en passant possibilities match
+    /// as well as the board.
+    fn repetitions(&self) -> usize {
+        match self.board_history.last() {
+            Some(last) => self.board_history.iter().filter(|v| *v == last).count(),
+            None => 0,
         }
+    }
 
-        false
+    /// Returns the number of halfmoves since the last capture or pawn move.
+    pub fn fifty_move_counter(&self) -> u32 {
+        self.seventy_five_move_rule
     }
 
     /// Checks whether a player can invoke the fifty-move-rule
+    ///
+    /// This is the same as `can_claim_fifty_move`.
     pub fn fifty_move_rule(&self) -> bool {
-        self.seventy_five_move_rule >= 50
+        self.can_claim_fifty_move()
+    }
+
+    /// Checks whether a draw can be claimed under the fifty-move rule: both players have made
+    /// fifty moves, so 100 halfmoves, without a capture or a pawn move.
+    ///
+    pub fn can_claim_fifty_move(&self) -> bool {
+        self.seventy_five_move_rule >= 100
+    }
+
+    /// Checks whether the game is drawn by the seventy-five-move rule: both players have made
+    /// seventy-five moves, so 150 halfmoves, without a capture or a pawn move. Unlike the
+    /// fifty-move rule, this draw doesn't have to be claimed.
+    ///
+    pub fn must_draw_seventy_five(&self) -> bool {
+        self.seventy_five_move_rule >= 150
+    }
+
+    /// Checks whether the side to move can claim a draw right now, by the fifty-move rule, a
+    /// threefold repetition or because neither side can checkmate.
+    ///
+    pub fn can_claim_draw(&self) -> bool {
+        self.can_claim_fifty_move() || self.three_fold_repetition() || self.insufficient_material()
+    }
+
+    /// Checks whether the given color lacks the material to ever checkmate the opponent.
+    ///
+    /// This is what decides a flag-fall: if the player who ran out of time faces an opponent that
+    /// can't possibly deliver mate, the game is drawn instead of lost. A lone knight can only mate
+    /// when the opponent has pieces to block its own king, and bishops can only mate when there
+    /// are bishops on both square colors or the opponent has a pawn or knight to block with.
+    ///
+    pub fn insufficient_material_for(&self, color: Color) -> bool {
+        let own = self.by_color(color);
+        let other = self.by_color(color.opposite());
+        let count = |pieces: &Vec<((usize, usize), &Piece)>, kind: Kind| {
+            pieces.iter().filter(|(_, p)| p.kind == kind).count()
+        };
+
+        if count(&own, Kind::Pawn) > 0
+            || count(&own, Kind::Rook) > 0
+            || count(&own, Kind::Queen) > 0
+        {
+            return false;
+        }
+
+        if count(&own, Kind::Knight) > 0 {
+            return own.len() <= 2
+                && other
+                    .iter()
+                    .all(|(_, p)| p.kind == Kind::King || p.kind == Kind::Queen);
+        }
+
+        if count(&own, Kind::Bishop) > 0 {
+            let bishops = self.by_kind(Kind::Bishop);
+            let same_shade = bishops
+                .iter()
+                .all(|(pos, _)| (pos.0 + pos.1) % 2 == ((bishops[0].0).0 + (bishops[0].0).1) % 2);
+            return same_shade
+                && count(&other, Kind::Pawn) == 0
+                && count(&other, Kind::Knight) == 0;
+        }
+
+        true
+    }
+
+    /// Checks whether neither side has the material to checkmate the other, making the position a
+    /// dead draw.
+    ///
+    pub fn insufficient_material(&self) -> bool {
+        self.insufficient_material_for(Color::White) && self.insufficient_material_for(Color::Black)
+    }
+
+    /// Checks whether making the given move would leave neither side with the material to
+    /// checkmate, without changing the game.
+    ///
+    pub fn would_be_insufficient_material(&self, m: &[((usize, usize), (usize, usize))]) -> bool {
+        let mut game = self.scratch();
+        game.move_pieces(m);
+        game.insufficient_material()
+    }
+}
+
+/// Returns the symbol `board_to_string` uses for a piece: its unicode chess symbol, or the
+/// letter used in algebraic notation, uppercase for white and lowercase for black.
+fn piece_glyph(piece: &Piece, unicode: bool) -> char {
+    if unicode {
+        match (piece.color, piece.kind) {
+            (Color::White, Kind::Pawn) => '\u{2659}',
+            (Color::White, Kind::Rook) => '\u{2656}',
+            (Color::White, Kind::Knight) => '\u{2658}',
+            (Color::White, Kind::Bishop) => '\u{2657}',
+            (Color::White, Kind::Queen) => '\u{2655}',
+            (Color::White, Kind::King) => '\u{2654}',
+            (Color::Black, Kind::Pawn) => '\u{265f}',
+            (Color::Black, Kind::Rook) => '\u{265c}',
+            (Color::Black, Kind::Knight) => '\u{265e}',
+            (Color::Black, Kind::Bishop) => '\u{265d}',
+            (Color::Black, Kind::Queen) => '\u{265b}',
+            (Color::Black, Kind::King) => '\u{265a}',
+        }
+    } else {
+        let c = match piece.kind {
+            Kind::Pawn => 'P',
+            Kind::Rook => 'R',
+            Kind::Knight => 'N',
+            Kind::Bishop => 'B',
+            Kind::Queen => 'Q',
+            Kind::King => 'K',
+        };
+        match piece.color {
+            Color::White => c,
+            Color::Black => c.to_ascii_lowercase(),
+        }
+    }
+}
+
+/// Returns the squares strictly between two squares on the same rank, file or diagonal, ordered
+/// from `a` towards `b`.
+///
+/// Returns an empty vector if the squares aren't aligned, or if they are next to each other.
+///
+pub fn squares_between(a: (usize, usize), b: (usize, usize)) -> Vec<(usize, usize)> {
+    let dx = b.0 as isize - a.0 as isize;
+    let dy = b.1 as isize - a.1 as isize;
+    if (dx == 0 && dy == 0) || (dx != 0 && dy != 0 && dx.abs() != dy.abs()) {
+        return Vec::new();
+    }
+
+    let mut result = Vec::new();
+    let (mut x, mut y) = (a.0 as isize + dx.signum(), a.1 as isize + dy.signum());
+    while (x, y) != (b.0 as isize, b.1 as isize) {
+        result.push((x as usize, y as usize));
+        x += dx.signum();
+        y += dy.signum();
+    }
+    result
+}
+
+/// Checks whether any position occurs at least three times in the given sequence of positions.
+///
+/// This is meant for callers that keep their own move list and rebuild the positions from it,
+/// rather than relying on the history a single `Game` collects as it is played. Positions count
+/// as the same when the pieces, side to move, castling rights and en passant square match.
+///
+pub fn has_threefold_repetition(positions: &[Game]) -> bool {
+    positions.iter().enumerate().any(|(i, game)| {
+        positions[i..]
+            .iter()
+            .filter(|other| game.same_position_as(other))
+            .count()
+            >= 3
+    })
+}
+
+/// Works out the move that leads from one position to the next, for sources that only give the
+/// positions. Each legal move of the side to move in `before` is tried until one gives the board
+/// of `after`, so castling and en passant come out as the full engine moves. A pawn promoted to
+/// something other than the default piece still matches, since `Move` doesn't hold the piece.
+///
+/// Returns None if no single legal move connects the two boards.
+///
+pub fn infer_move<'a>(before: &Game<'a>, after: &Game<'a>) -> Option<Move> {
+    let color = before.side_to_move();
+    before.all_valid_moves(color).into_iter().find(|mv| {
+        let mut game = before.scratch();
+        game.move_pieces(mv);
+        match game.board_diff(after)[..] {
+            [] => true,
+            [SquareChange {
+                pos,
+                after: Some(piece),
+                ..
+            }] => {
+                pos == destination(mv)
+                    && piece.color == color
+                    && piece.kind != Kind::King
+                    && before.get_from_pos(mv[0].0).map(|p| p.kind) == Some(Kind::Pawn)
+            }
+            _ => false,
+        }
+    })
+}
+
+/// Counts how many times a position occurs in a list of hashes from `position_hash`, for callers
+/// that track repetitions themselves. Include the current position in `history` to get the
+/// same count the repetition rules use.
+///
+pub fn count_repetitions(history: &[u64], current: u64) -> usize {
+    history.iter().filter(|&&hash| hash == current).count()
+}
+
+/// Returns the square the moving piece ends up on, which for castling is the king's square rather
+/// than the rook's.
+pub(crate) fn destination(mv: &[((usize, usize), (usize, usize))]) -> (usize, usize) {
+    if mv.len() == 3 {
+        mv[1].1
+    } else {
+        mv[mv.len() - 1].1
     }
 }
 
+/// Returns the random number a Zobrist hash uses for the given piece, square or flag. The numbers
+/// come from SplitMix64, so they are the same on every run without storing a table.
+fn zobrist_key(index: u64) -> u64 {
+    let mut z = index.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Returns the Zobrist hash of the pieces on `board`, the `flags` that are set and the file of the
+/// en passant square, if there is one.
+fn hash_position(
+    board: &[[Option<&Piece>; BOARD_SIZE]; BOARD_SIZE],
+    flags: &[bool],
+    en_passant: Option<(usize, usize)>,
+) -> u64 {
+    let mut hash = 0;
+    for x in 0..BOARD_SIZE {
+        for y in 0..BOARD_SIZE {
+            if let Some(piece) = board[x][y] {
+                let index = (piece.color as usize * 6 + piece.kind as usize) * 64 + y * 8 + x;
+                hash ^= zobrist_key(index as u64);
+            }
+        }
+    }
+    for (i, &flag) in flags.iter().enumerate() {
+        if flag {
+            hash ^= zobrist_key(768 + i as u64);
+        }
+    }
+    if let Some((file, _)) = en_passant {
+        hash ^= zobrist_key(768 + flags.len() as u64 + file as u64);
+    }
+    hash
+}
+
+/// Checks whether a position lies within the bounds of the board.
+fn on_board(pos: (usize, usize)) -> bool {
+    pos.0 < BOARD_SIZE && pos.1 < BOARD_SIZE
+}
+
 /// Turns a position on the board from a string, like B3, to a tuple, like (1, 2).
 ///
 /// Returns a Result containing the tuple, or an error if the given string was too long, or wasn't
@@ -1686,7 +3499,6 @@ pub fn string_to_pos(string: &str) -> Result<(usize, usize), i32> {
 
     let bytes = string.as_bytes();
     let x: u8;
-    let y: u8;
     if bytes[0] >= 65 && bytes[0] <= 72 {
         x = bytes[0] - 65;
     } else if bytes[0] >= 97 && bytes[0] <= 104 {
@@ -1695,11 +3507,11 @@ pub fn string_to_pos(string: &str) -> Result<(usize, usize), i32> {
         return Err(2);
     }
 
-    if bytes[1] >= 49 && bytes[1] <= 56 {
-        y = bytes[1] - 49;
+    let y: u8 = if bytes[1] >= 49 && bytes[1] <= 56 {
+        bytes[1] - 49
     } else {
         return Err(2);
-    }
+    };
 
     Ok((x as usize, y as usize))
 }
@@ -1709,7 +3521,7 @@ pub fn string_to_pos(string: &str) -> Result<(usize, usize), i32> {
 /// Returns a Result containing the string, or an error if the given tuple was out of bounds.
 ///
 pub fn pos_to_string(pos: (usize, usize)) -> Result<String, i32> {
-    if pos.0 > 7 || pos.1 > 7 {
+    if !on_board(pos) {
         return Err(1);
     }
 
@@ -1730,76 +3542,1637 @@ pub fn pos_to_string(pos: (usize, usize)) -> Result<String, i32> {
     }
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-
-//     #[test]
-//     fn test_string_to_pos() {
-//         assert_eq!(string_to_pos("A1"), Ok((0, 0)));
-//         assert_eq!(string_to_pos("C6"), Ok((2, 5)));
-//         assert_eq!(string_to_pos("c6"), Ok((2, 5)));
-//         assert_eq!(string_to_pos("H8"), Ok((7, 7)));
-
-//         assert_eq!(string_to_pos("C9"), Err(2));
-//         assert_eq!(string_to_pos("I5"), Err(2));
-//         assert_eq!(string_to_pos("I59"), Err(1));
-//         assert_eq!(string_to_pos("C5 "), Err(1));
-//         assert_eq!(string_to_pos("5C"), Err(2));
-//     }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-//     #[test]
-//     fn test_pos_to_string() {
-//         assert_eq!(pos_to_string((0,0)), Ok("A1".to_string()));
-//         assert_eq!(pos_to_string((7,7)), Ok("H8".to_string()));
-//         assert_eq!(pos_to_string((3,5)), Ok("D6".to_string()));
+    fn play(game: &mut Game, moves: &[(&str, &str)]) {
+        for (from, to) in moves {
+            game.move_piece(string_to_pos(from).unwrap(), string_to_pos(to).unwrap());
+        }
+    }
 
-//         assert_eq!(pos_to_string((8,8)), Err(1));
-//         assert_eq!(pos_to_string((20,1)), Err(1));
-//         assert_eq!(pos_to_string((2,9)), Err(1));
-//     }
+    fn has_move(game: &Game, from: &str, to: &str) -> bool {
+        let to = string_to_pos(to).unwrap();
+        game.valid_moves(string_to_pos(from).unwrap())
+            .iter()
+            .any(|m| m.last().unwrap().1 == to)
+    }
 
-//     #[test]
-//     fn test_raw_moves() {
-//         let mut game = Game::new_empty();
-//         game.set_at_pos((3,3), Some(&WHITE[1]));
-//         let moves = game.raw_moves((3,3));
-//         assert_eq!(moves.len(), 14);
-//     }
+    #[test]
+    fn test_string_to_pos() {
+        assert_eq!(string_to_pos("A1"), Ok((0, 0)));
+        assert_eq!(string_to_pos("C6"), Ok((2, 5)));
+        assert_eq!(string_to_pos("c6"), Ok((2, 5)));
+        assert_eq!(string_to_pos("H8"), Ok((7, 7)));
+
+        assert_eq!(string_to_pos("C9"), Err(2));
+        assert_eq!(string_to_pos("I5"), Err(2));
+        assert_eq!(string_to_pos("I59"), Err(1));
+        assert_eq!(string_to_pos("C5 "), Err(1));
+        assert_eq!(string_to_pos("5C"), Err(2));
+    }
 
-//     #[test]
-//     fn test_check_for_check() {
-//         let mut game = Game::new_empty();
-//         game.set_at_pos((1, 2), Some(&WHITE[4]));
-//         game.set_at_pos((0, 0), Some(&BLACK[5]));
-//         game.set_at_pos((6, 7), Some(&WHITE[5]));
+    #[test]
+    fn test_pos_to_string() {
+        assert_eq!(pos_to_string((0, 0)), Ok("A1".to_string()));
+        assert_eq!(pos_to_string((7, 7)), Ok("H8".to_string()));
+        assert_eq!(pos_to_string((3, 5)), Ok("D6".to_string()));
 
-//         assert!(game.check_for_check((0,0), (1,0)));
-//     }
+        assert_eq!(pos_to_string((8, 8)), Err(1));
+        assert_eq!(pos_to_string((20, 1)), Err(1));
+        assert_eq!(pos_to_string((2, 9)), Err(1));
+    }
 
-//     #[test]
-//     fn test_print() {
-//         let game = Game::new();
-//         let mut board = game.board_to_string(false);
-//         assert_eq!(board,
-//                    "rnbqkbnr\
-//                   \npppppppp\
-//                   \n        \
-//                   \n        \
-//                   \n        \
-//                   \n        \
-//                   \nPPPPPPPP\
-//                   \nRNBQKBNR");
-
-//         board = game.board_to_string(true);
-//         assert_eq!(board,
-//                    "♜♞♝♛♚♝♞♜\
-//                   \n♟♟♟♟♟♟♟♟\
-//                   \n        \
-//                   \n        \
-//                   \n        \
-//                   \n        \
-//                   \n♙♙♙♙♙♙♙♙\
-//                   \n♖♘♗♕♔♗♘♖");
-//     }
-// }
+    #[test]
+    fn test_raw_moves() {
+        let mut game = Game::new_empty();
+        game.set_at_pos((3, 3), Some(&WHITE[1]));
+        let moves = game.raw_moves((3, 3));
+        assert_eq!(moves.len(), 14);
+    }
+
+    #[test]
+    fn test_check_for_check() {
+        let mut game = Game::new_empty();
+        game.set_at_pos((1, 2), Some(&WHITE[4]));
+        game.set_at_pos((0, 0), Some(&BLACK[5]));
+        game.set_at_pos((6, 7), Some(&WHITE[5]));
+
+        assert!(game.leaves_king_in_check((0, 0), (1, 0)));
+    }
+
+    #[test]
+    fn leaves_king_in_check() {
+        // Stepping next to the other king, moving a pinned piece and blocking a check.
+        let game = Game::from_fen("4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1").unwrap();
+        assert!(game.leaves_king_in_check((4, 1), (3, 2)));
+        assert!(!game.leaves_king_in_check((4, 1), (4, 5)));
+        assert!(!game.leaves_king_in_check((4, 0), (3, 0)));
+        let game = Game::from_fen("8/8/8/8/8/3k4/8/3K4 w - - 0 1").unwrap();
+        assert!(game.leaves_king_in_check((3, 0), (3, 1)));
+        let game = Game::from_fen("4k3/8/8/8/8/5p2/8/4K3 w - - 0 1").unwrap();
+        assert!(game.leaves_king_in_check((4, 0), (4, 1)));
+        assert!(!game.leaves_king_in_check((4, 0), (5, 1)));
+
+        // The same legal moves as checking each step on a full copy of the game.
+        for fen in &[
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1",
+            "4k3/8/8/2KpP2r/8/8/8/8 w - d6 0 2",
+            "4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1",
+        ] {
+            let game = Game::from_fen(fen).unwrap();
+            for &color in &[Color::White, Color::Black] {
+                for (pos, _) in game.by_color(color) {
+                    let expected: Vec<Move> = game
+                        .raw_moves(pos)
+                        .into_iter()
+                        .filter(|m| {
+                            let mut copy = game.scratch();
+                            m.iter().all(|&(from, to)| {
+                                if copy.get_from_pos(to).filter(|p| p.color == color).is_some() {
+                                    return false;
+                                }
+                                let mut after = copy.scratch();
+                                after.move_piece(from, to);
+                                copy.move_piece(from, to);
+                                !after.in_check(color)
+                            })
+                        })
+                        .filter(|m| m.len() != 3 || !game.in_check(color))
+                        .collect();
+                    assert_eq!(game.valid_moves(pos), expected, "{} {:?}", fen, pos);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_print() {
+        let game = Game::new();
+        let mut board = game.board_to_string(false);
+        assert_eq!(
+            board,
+            "rnbqkbnr\
+            \npppppppp\
+            \n        \
+            \n        \
+            \n        \
+            \n        \
+            \nPPPPPPPP\
+            \nRNBQKBNR"
+        );
+
+        board = game.board_to_string(true);
+        assert_eq!(
+            board,
+            "♜♞♝♛♚♝♞♜\
+            \n♟♟♟♟♟♟♟♟\
+            \n        \
+            \n        \
+            \n        \
+            \n        \
+            \n♙♙♙♙♙♙♙♙\
+            \n♖♘♗♕♔♗♘♖"
+        );
+    }
+
+    #[test]
+    fn en_passant_only_available_for_one_ply() {
+        let mut game = Game::new();
+        play(&mut game, &[("e2", "e4"), ("a7", "a6"), ("e4", "e5")]);
+        assert!(!has_move(&game, "e5", "d6"));
+
+        play(&mut game, &[("d7", "d5")]);
+        assert!(has_move(&game, "e5", "d6"));
+
+        play(&mut game, &[("h2", "h3"), ("a6", "a5")]);
+        assert!(!has_move(&game, "e5", "d6"));
+    }
+
+    #[test]
+    fn insufficient_material() {
+        let mut game = Game::new_empty();
+        game.set_at_pos((4, 0), Some(&WHITE[5]));
+        game.set_at_pos((2, 0), Some(&WHITE[3]));
+        game.set_at_pos((4, 7), Some(&BLACK[5]));
+
+        // White flags with king and bishop against a lone king: black can't mate, so it's a draw.
+        assert!(game.insufficient_material_for(Color::Black));
+        assert!(game.insufficient_material_for(Color::White));
+        assert!(game.insufficient_material());
+
+        // A knight can mate when the opponent has a pawn to block its own king with.
+        game.set_at_pos((2, 0), Some(&WHITE[2]));
+        game.set_at_pos((0, 6), Some(&BLACK[0]));
+        assert!(!game.insufficient_material_for(Color::White));
+        assert!(!game.insufficient_material_for(Color::Black));
+
+        // Bishops on opposite shades can mate.
+        game.set_at_pos((0, 6), None);
+        game.set_at_pos((2, 0), Some(&WHITE[3]));
+        game.set_at_pos((5, 0), Some(&WHITE[3]));
+        assert!(!game.insufficient_material_for(Color::White));
+
+        assert!(!Game::new().insufficient_material());
+    }
+
+    #[test]
+    fn castling_counts_as_one_halfmove() {
+        let mut game = Game::new_empty();
+        game.set_at_pos((4, 0), Some(&WHITE[5]));
+        game.set_at_pos((7, 0), Some(&WHITE[1]));
+        game.set_at_pos((0, 0), Some(&WHITE[1]));
+        game.set_at_pos((4, 7), Some(&BLACK[5]));
+        game.set_at_pos((0, 7), Some(&BLACK[1]));
+
+        let castle = |game: &Game, from: (usize, usize), to: (usize, usize)| {
+            game.valid_moves(from)
+                .into_iter()
+                .find(|m| m.len() == 3 && m[1].1 == to)
+                .unwrap()
+        };
+
+        let m = castle(&game, (4, 0), (6, 0));
+        game.move_pieces(&m);
+        assert_eq!(game.seventy_five_move_rule, 1);
+
+        let m = castle(&game, (4, 7), (2, 7));
+        game.move_pieces(&m);
+        assert_eq!(game.seventy_five_move_rule, 2);
+
+        game.move_pieces(&[((0, 0), (0, 3))]);
+        assert_eq!(game.seventy_five_move_rule, 3);
+    }
+
+    #[test]
+    fn legal_sans() {
+        let mut game = Game::new();
+        assert_eq!(game.legal_sans((6, 0)), vec!["Nf3", "Nh3"]);
+
+        play(
+            &mut game,
+            &[("g1", "f3"), ("e7", "e5"), ("d2", "d4"), ("d8", "h4")],
+        );
+        assert!(game.legal_sans((1, 0)).contains(&"Nbd2".to_string()));
+        assert!(game.legal_sans((5, 2)).contains(&"Nfd2".to_string()));
+        assert!(game.legal_sans((5, 2)).contains(&"Nxh4".to_string()));
+        assert!(game.legal_sans((3, 3)).contains(&"dxe5".to_string()));
+
+        let mut game = Game::new_empty();
+        game.set_at_pos((4, 0), Some(&WHITE[5]));
+        game.set_at_pos((4, 7), Some(&BLACK[5]));
+        game.set_at_pos((0, 0), Some(&WHITE[1]));
+        game.set_at_pos((0, 4), Some(&WHITE[1]));
+        assert!(game.legal_sans((0, 0)).contains(&"R1a3".to_string()));
+        assert!(game.legal_sans((0, 4)).contains(&"R5a3".to_string()));
+        assert!(game.legal_sans((0, 4)).contains(&"Ra8+".to_string()));
+    }
+
+    #[test]
+    fn repetition_window() {
+        let mut game = Game::new();
+        let shuffle = [
+            ((6, 0), (5, 2)),
+            ((6, 7), (5, 5)),
+            ((5, 2), (6, 0)),
+            ((5, 5), (6, 7)),
+        ];
+        for m in &shuffle {
+            game.move_pieces(&[*m]);
+        }
+        assert_eq!(game.repetitions(), 2);
+        assert!(!game.three_fold_repetition());
+
+        game.move_pieces(&[((4, 1), (4, 3))]);
+        game.move_pieces(&[((4, 6), (4, 4))]);
+        assert_eq!(game.board_history.len(), 1);
+        assert_eq!(game.repetitions(), 1);
+
+        for _ in 0..2 {
+            for m in &shuffle {
+                game.move_pieces(&[*m]);
+            }
+        }
+        assert!(game.three_fold_repetition());
+        assert_eq!(game.board_history.len(), 9);
+    }
+
+    #[test]
+    fn rebuild_from_moves_with_history() {
+        let shuffle = [
+            ((6, 0), (5, 2), None),
+            ((6, 7), (5, 5), None),
+            ((5, 2), (6, 0), None),
+            ((5, 5), (6, 7), None),
+        ];
+        let twice = [&shuffle[..], &shuffle[..]].concat();
+        assert!(Game::new()
+            .rebuild_from_moves_with_history(&twice)
+            .three_fold_repetition());
+
+        // Three times the same knights, but the pawns moved in between.
+        let pawns = [((4, 1), (4, 3), None), ((4, 6), (4, 4), None)];
+        let moves = [&shuffle[..], &pawns[..], &shuffle[..]].concat();
+        let game = Game::new().rebuild_from_moves_with_history(&moves);
+        assert!(!game.three_fold_repetition());
+        assert_eq!(game.board_history.len(), 5);
+        assert_eq!(game.moves_played().len(), moves.len());
+
+        // The history of the game it starts from doesn't count either.
+        let game = Game::new().rebuild_from_moves_with_history(&twice);
+        let rebuilt = game.rebuild_from_moves_with_history(&shuffle);
+        assert_eq!(rebuilt.repetitions(), 2);
+        assert!(!rebuilt.three_fold_repetition());
+
+        // Promotions keep their piece, and a move that isn't legal is still made.
+        let game = Game::from_fen("7k/4P3/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        let rebuilt = game.rebuild_from_moves_with_history(&[
+            ((4, 6), (4, 7), Some(Kind::Knight)),
+            ((7, 7), (7, 5), None),
+        ]);
+        assert_eq!(rebuilt.get_from_pos((4, 7)), Some(&WHITE[2]));
+        assert_eq!(
+            rebuilt.get_from_pos((7, 5)).map(|p| p.kind),
+            Some(Kind::King)
+        );
+    }
+
+    #[test]
+    fn repetition_needs_same_rights() {
+        // The kings step off and back twice, but the first time round they still had the right
+        // to castle, so the starting position is a different one.
+        let mut game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let shuffle = [
+            ((4, 0), (5, 0)),
+            ((4, 7), (5, 7)),
+            ((5, 0), (4, 0)),
+            ((5, 7), (4, 7)),
+        ];
+        for _ in 0..2 {
+            for m in &shuffle {
+                game.move_pieces(&[*m]);
+            }
+        }
+        assert_eq!(game.repetitions(), 2);
+        assert!(!game.three_fold_repetition());
+        for m in &shuffle {
+            game.move_pieces(&[*m]);
+        }
+        assert!(game.three_fold_repetition());
+
+        // After 1. e4 no black pawn can take en passant, so returning to that position counts.
+        let mut game = Game::from_uci_position("startpos moves e2e4").unwrap();
+        for m in &[
+            "g8f6", "g1f3", "f6g8", "f3g1", "g8f6", "g1f3", "f6g8", "f3g1",
+        ] {
+            game.apply_uci(m).unwrap();
+        }
+        assert!(game.three_fold_repetition());
+
+        // With a black pawn on d4 it could, so it doesn't.
+        let mut game = Game::from_fen("4k3/8/8/8/3p4/8/4P3/4K3 w - - 0 1").unwrap();
+        game.apply_uci("e2e4").unwrap();
+        for m in &[
+            "e8d8", "e1d1", "d8e8", "d1e1", "e8d8", "e1d1", "d8e8", "d1e1",
+        ] {
+            game.apply_uci(m).unwrap();
+        }
+        assert_eq!(game.repetitions(), 2);
+    }
+
+    #[test]
+    fn side_to_move_in_check() {
+        let mut game = Game::from_uci_position("startpos moves f2f3 e7e5 g2g4").unwrap();
+        assert!(!game.side_to_move_in_check());
+        game.apply_uci("d8h4").unwrap();
+        assert!(game.side_to_move_in_check());
+        assert!(game.in_check(Color::White));
+        assert!(!game.in_check(Color::Black));
+    }
+
+    #[test]
+    fn goto_ply() {
+        let moves = [
+            "e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "g8f6", "d2d3", "f8c5", "e1g1", "e8g8",
+        ];
+        let mut game =
+            Game::from_uci_position(&format!("startpos moves {}", moves.join(" "))).unwrap();
+        let end = game.to_fen();
+
+        assert!(game.goto_ply(5));
+        let middle =
+            Game::from_uci_position(&format!("startpos moves {}", moves[..5].join(" "))).unwrap();
+        assert_eq!(game.to_fen(), middle.to_fen());
+        assert_eq!(game.moves_played().len(), 5);
+        assert_eq!(game.history_san(), middle.history_san());
+
+        assert!(game.goto_ply(0));
+        assert_eq!(game.to_fen(), START_FEN);
+        assert!(game.goto_ply(10));
+        assert_eq!(game.to_fen(), end);
+        assert!(game.goto_ply(3));
+        assert!(!game.goto_ply(11));
+        assert_eq!(game.moves_played().len(), 3);
+        assert!(game.goto_ply(7));
+        assert_eq!(
+            game.to_fen(),
+            Game::from_uci_position(&format!("startpos moves {}", moves[..7].join(" ")))
+                .unwrap()
+                .to_fen()
+        );
+    }
+
+    #[test]
+    fn undo_last() {
+        let mut game = Game::new();
+        assert!(!game.undo_last());
+
+        game.apply_uci("g1f3").unwrap();
+        game.apply_uci("d7d5").unwrap();
+        let before_capture = game.clone();
+        game.apply_uci("f3e5").unwrap();
+        game.apply_uci("d8d6").unwrap();
+        game.apply_uci("e5f7").unwrap();
+        assert_eq!(game.get_from_pos((5, 6)), Some(&WHITE[2]));
+
+        // The knight took a pawn, so the clock and the repetition history were reset.
+        assert!(game.undo_last());
+        assert_eq!(game.get_from_pos((5, 6)), Some(&BLACK[0]));
+        assert_eq!(game.fifty_move_counter(), 2);
+        assert!(game.undo_last());
+        assert!(game.undo_last());
+        assert!(game == before_capture);
+        assert_eq!(game.board_history.len(), before_capture.board_history.len());
+
+        assert!(game.undo_last());
+        assert!(game.undo_last());
+        assert!(game == Game::new());
+        assert_eq!(game.board_history.len(), 1);
+        assert!(!game.undo_last());
+    }
+
+    #[test]
+    fn redo() {
+        let mut game = Game::new();
+        assert!(!game.redo());
+        for m in &["e2e4", "e7e5", "g1f3"] {
+            game.apply_uci(m).unwrap();
+        }
+        let end = game.clone();
+
+        assert!(game.undo_last());
+        assert!(game.undo_last());
+        assert!(game.redo());
+        assert!(game.redo());
+        assert!(!game.redo());
+        assert!(game == end);
+        assert_eq!(game.board_history, end.board_history);
+
+        // Playing the undone move by hand keeps the rest of the line.
+        assert!(game.undo_last());
+        assert!(game.undo_last());
+        game.apply_uci("e7e5").unwrap();
+        assert!(game.redo());
+        assert!(game == end);
+
+        // A different move throws the undone moves away.
+        assert!(game.undo_last());
+        assert!(game.undo_last());
+        game.apply_uci("c7c5").unwrap();
+        assert!(!game.redo());
+        assert!(game.undo_last());
+        assert!(game.undo_last());
+        assert!(game == Game::new());
+    }
+
+    #[test]
+    fn position_hash() {
+        let shuffle = [
+            ((6, 0), (5, 2)),
+            ((6, 7), (5, 5)),
+            ((5, 2), (6, 0)),
+            ((5, 5), (6, 7)),
+        ];
+        let mut game = Game::new();
+        let mut history = vec![game.position_hash()];
+        for m in shuffle.iter().chain(shuffle.iter()) {
+            game.move_pieces(&[*m]);
+            history.push(game.position_hash());
+        }
+        assert_eq!(history[0], history[4]);
+        assert_eq!(history[4], history[8]);
+        assert_ne!(history[0], history[2]);
+        assert_eq!(super::count_repetitions(&history, game.position_hash()), 3);
+        assert_eq!(super::count_repetitions(&history, history[1]), 2);
+        assert_eq!(super::count_repetitions(&history[1..], history[0]), 2);
+        assert_eq!(super::count_repetitions(&[], history[0]), 0);
+        assert!(game.three_fold_repetition());
+
+        // The same board with the other side to move, or without castling rights, differs.
+        let mut other = Game::new();
+        other.move_pieces(&[((6, 0), (5, 2))]);
+        other.move_pieces(&[((5, 2), (6, 0))]);
+        assert_ne!(other.position_hash(), Game::new().position_hash());
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_ne!(
+            Game::from_fen(fen).unwrap().position_hash(),
+            Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w Kkq - 0 1")
+                .unwrap()
+                .position_hash()
+        );
+
+        // An en passant square nobody can use doesn't count.
+        assert_eq!(
+            Game::from_fen("4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1")
+                .unwrap()
+                .position_hash(),
+            Game::from_fen("4k3/8/8/8/4P3/8/8/4K3 b - - 0 1")
+                .unwrap()
+                .position_hash()
+        );
+    }
+
+    #[test]
+    fn same_position_as() {
+        let a = Game::from_uci_position("startpos moves g1f3 g8f6 b1c3").unwrap();
+        let b = Game::from_uci_position("startpos moves b1c3 g8f6 g1f3").unwrap();
+        assert!(a.same_position_as(&b));
+        let c =
+            Game::from_uci_position("startpos moves b1c3 g8f6 g1f3 b8c6 c3b1 c6b8 b1c3").unwrap();
+        assert!(a.same_position_as(&c));
+        assert!(a.to_fen() != c.to_fen());
+        assert!(!a.same_position_as(&Game::new()));
+
+        // Black can take en passant in one but not the other.
+        let a = Game::from_fen("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1").unwrap();
+        let b = Game::from_fen("4k3/8/8/8/3pP3/8/8/4K3 b - - 0 1").unwrap();
+        assert!(!a.same_position_as(&b));
+    }
+
+    #[test]
+    fn has_threefold_repetition() {
+        let shuffle = [
+            ((6, 0), (5, 2)),
+            ((6, 7), (5, 5)),
+            ((5, 2), (6, 0)),
+            ((5, 5), (6, 7)),
+        ];
+        let mut game = Game::new();
+        let mut positions = vec![game.clone()];
+        for m in shuffle.iter().chain(shuffle.iter()) {
+            game.move_pieces(&[*m]);
+            positions.push(game.clone());
+        }
+        assert!(super::has_threefold_repetition(&positions));
+        assert!(!super::has_threefold_repetition(&positions[..8]));
+        assert!(!super::has_threefold_repetition(&positions[1..]));
+
+        // The same board with the other side to move doesn't count.
+        let mut other = Game::new();
+        other.move_pieces(&[((6, 0), (5, 2))]);
+        other.move_pieces(&[((5, 2), (6, 0))]);
+        let mixed = [Game::new(), other.clone(), Game::new(), other];
+        assert!(!super::has_threefold_repetition(&mixed));
+    }
+
+    #[test]
+    fn squares_between() {
+        assert_eq!(
+            super::squares_between((0, 0), (4, 0)),
+            vec![(1, 0), (2, 0), (3, 0)]
+        );
+        assert_eq!(super::squares_between((3, 6), (3, 3)), vec![(3, 5), (3, 4)]);
+        assert_eq!(
+            super::squares_between((7, 0), (3, 4)),
+            vec![(6, 1), (5, 2), (4, 3)]
+        );
+        assert!(super::squares_between((0, 0), (1, 1)).is_empty());
+        assert!(super::squares_between((2, 2), (2, 2)).is_empty());
+        assert!(super::squares_between((0, 0), (1, 2)).is_empty());
+        assert!(super::squares_between((0, 0), (7, 6)).is_empty());
+    }
+
+    #[test]
+    fn castling_path_must_be_empty() {
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/8/R1n1K2R w KQ - 0 1").unwrap();
+        let castles = |game: &Game| {
+            game.valid_moves((4, 0))
+                .iter()
+                .filter(|m| m.len() == 3)
+                .map(|m| m[1].1)
+                .collect::<Vec<_>>()
+        };
+        // The knight on c1 blocks the queenside castle even though b1 and d1 are empty.
+        assert_eq!(castles(&game), vec![(6, 0)]);
+        game.set_at_pos((2, 0), None);
+        assert_eq!(castles(&game).len(), 2);
+    }
+
+    #[test]
+    fn evasions() {
+        for fen in &[
+            // The king can take the queen.
+            "4k3/8/8/8/8/8/4q3/4K3 w - - 0 1",
+            // Fool's mate, nothing helps.
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+            // The bishop check can be blocked by four different pieces.
+            "rnbqk1nr/pppp1ppp/8/4p3/1b1P4/8/PPP1PPPP/RNBQKBNR w KQkq - 1 3",
+            // A double check only leaves king moves.
+            "4k3/8/8/8/8/5n2/8/r3K3 w - - 0 1",
+            // No castling out of a knight check.
+            "4k3/8/8/8/8/3n4/8/4K2R w K - 0 1",
+            // The checking pawn can be taken en passant.
+            "8/8/8/3pP3/4K3/8/8/7k w - d6 0 1",
+        ] {
+            let game = Game::from_fen(fen).unwrap();
+            let color = game.side_to_move();
+            assert!(game.in_check(color), "{}", fen);
+            let expected: Vec<Move> = game
+                .by_color(color)
+                .into_iter()
+                .flat_map(|(pos, _)| game.valid_moves(pos))
+                .collect();
+            assert_eq!(game.evasions(color), expected, "{}", fen);
+        }
+
+        let game = Game::from_fen("rnbqk1nr/pppp1ppp/8/4p3/1b1P4/8/PPP1PPPP/RNBQKBNR w KQkq - 1 3")
+            .unwrap();
+        assert_eq!(game.evasions(Color::White).len(), 5);
+        assert!(game.can_block_check(Color::White));
+        assert_eq!(game.checkers(Color::White), vec![(1, 3)]);
+
+        let game = Game::from_fen("4k3/8/8/8/8/3n4/8/4K2R w K - 0 1").unwrap();
+        assert!(!game.can_block_check(Color::White));
+        assert!(game.valid_moves((4, 0)).iter().all(|m| m.len() == 1));
+
+        let game = Game::from_fen("8/8/8/3pP3/4K3/8/8/7k w - d6 0 1").unwrap();
+        assert!(game
+            .evasions(Color::White)
+            .contains(&vec![((4, 4), (3, 4)), ((3, 4), (3, 5))]));
+
+        assert!(Game::new().evasions(Color::White).is_empty());
+    }
+
+    #[test]
+    fn would_be_insufficient_material() {
+        let game = Game::from_fen("4k3/8/8/8/8/2r5/8/1N2K3 w - - 0 1").unwrap();
+        assert!(game.would_be_insufficient_material(&[((1, 0), (2, 2))]));
+        assert!(!game.would_be_insufficient_material(&[((1, 0), (3, 1))]));
+        // The game itself is left alone.
+        assert!(!game.insufficient_material());
+        assert_eq!(game.get_from_pos((2, 2)), Some(&BLACK[1]));
+    }
+
+    #[test]
+    fn promotion_settings() {
+        let mut game = Game::from_fen("4k3/1P6/8/8/8/8/6p1/4K3 w - - 0 1").unwrap();
+
+        game.strict_promotion(true);
+        assert_eq!(
+            game.move_piece_promote((1, 6), (1, 7), None),
+            Err(PromotionError::Required)
+        );
+        assert_eq!(game.get_from_pos((1, 6)), Some(&WHITE[0]));
+        assert_eq!(
+            game.move_piece_promote((1, 6), (1, 7), Some(Kind::King)),
+            Err(PromotionError::InvalidPiece(Kind::King))
+        );
+        assert_eq!(
+            game.move_piece_promote((4, 0), (3, 0), Some(Kind::Rook)),
+            Err(PromotionError::NotAPromotion(Kind::Rook))
+        );
+        // Moves that don't promote are unaffected by strict mode.
+        assert_eq!(game.move_piece_promote((4, 7), (3, 7), None), Ok(None));
+        assert_eq!(
+            game.move_piece_promote((1, 6), (1, 7), Some(Kind::Bishop)),
+            Ok(None)
+        );
+        assert_eq!(game.get_from_pos((1, 7)), Some(&WHITE[3]));
+
+        game.strict_promotion(false);
+        game.default_promotion(Kind::Knight);
+        assert_eq!(game.move_piece_promote((6, 1), (6, 0), None), Ok(None));
+        assert_eq!(game.get_from_pos((6, 0)), Some(&BLACK[2]));
+    }
+
+    #[test]
+    fn underpromotion() {
+        // e8=N forks nothing here, but checks the king on d6 where a queen wouldn't.
+        let mut game = Game::from_fen("8/4P3/3k4/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let push = vec![((4, 6), (4, 7))];
+        assert_eq!(
+            game.valid_moves_with_promotions((4, 6)),
+            vec![
+                (push.clone(), Some(Kind::Queen)),
+                (push.clone(), Some(Kind::Rook)),
+                (push.clone(), Some(Kind::Bishop)),
+                (push.clone(), Some(Kind::Knight)),
+            ]
+        );
+        assert!(game
+            .valid_moves_with_promotions((4, 0))
+            .iter()
+            .all(|(_, kind)| kind.is_none()));
+
+        assert_eq!(game.move_to_an(&push, false, false), "e8=Q");
+        assert_eq!(
+            game.an_to_move_promote("e8=N", Color::White),
+            Some((push.clone(), Some(Kind::Knight)))
+        );
+        assert_eq!(
+            game.an_to_move_promote("e8", Color::White),
+            Some((push.clone(), None))
+        );
+        assert_eq!(game.an_to_move("e8=R", Color::White), Some(push.clone()));
+        assert_eq!(game.an_to_move_promote("e8=K", Color::White), None);
+        assert_eq!(game.an_to_move_promote("Ke2=N", Color::White), None);
+
+        let mut knight = game.clone();
+        knight.default_promotion(Kind::Knight);
+        assert_eq!(knight.move_to_an(&push, false, false), "e8=N+");
+
+        game.play((4, 6), (4, 7), Some(Kind::Knight)).unwrap();
+        assert_eq!(game.get_from_pos((4, 7)), Some(&WHITE[2]));
+        assert_eq!(game.history_san(), ["e8=N+"]);
+    }
+
+    #[test]
+    fn board_diff() {
+        let start = Game::new();
+        let mut game = Game::new();
+        assert!(start.board_diff(&game).is_empty());
+
+        game.move_pieces(&[((4, 1), (4, 3))]);
+        assert_eq!(
+            start.board_diff(&game),
+            vec![
+                SquareChange {
+                    pos: (4, 1),
+                    before: Some(&WHITE[0]),
+                    after: None,
+                },
+                SquareChange {
+                    pos: (4, 3),
+                    before: None,
+                    after: Some(&WHITE[0]),
+                },
+            ]
+        );
+
+        // Castling touches four squares.
+        let castled =
+            Game::from_uci_position("fen 4k3/8/8/8/8/8/8/4K2R w K - 0 1 moves e1g1").unwrap();
+        let before = Game::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        assert_eq!(before.board_diff(&castled).len(), 4);
+    }
+
+    #[test]
+    fn legal_san_map() {
+        let map = Game::new().legal_san_map(Color::White);
+        assert_eq!(map.len(), 20);
+        assert!(map.values().all(|moves| moves.len() == 1));
+        assert_eq!(map["Nf3"], vec![vec![((6, 0), (5, 2))]]);
+        assert_eq!(map["e4"], vec![vec![((4, 1), (4, 3))]]);
+
+        // Both knights can reach d2, so their moves are told apart by file.
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1").unwrap();
+        let map = game.legal_san_map(Color::White);
+        assert!(map.contains_key("Nbd2") && map.contains_key("Nfd2"));
+        assert!(!map.contains_key("Nd2"));
+    }
+
+    #[test]
+    fn history_san() {
+        let line = [
+            "e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "g8f6", "e1g1", "f6e4", "f1e1", "e4d6", "f3e5",
+            "c6e5", "e1e5", "f8e7", "b5f1", "e8g8",
+        ];
+        let mut game = Game::new();
+        let mut fresh = Game::new();
+        let mut expected = Vec::new();
+        for m in line.iter() {
+            let from = string_to_pos(&m[0..2]).unwrap();
+            let to = string_to_pos(&m[2..4]).unwrap();
+            let mv = fresh
+                .valid_moves(from)
+                .into_iter()
+                .find(|mv| mv[mv.len().min(2) - 1].1 == to)
+                .unwrap();
+            expected.push(fresh.move_to_an(&mv, false, false));
+            fresh.move_pieces(&mv);
+            game.move_pieces(&mv);
+        }
+        assert_eq!(game.history_san(), &expected[..]);
+        assert_eq!(game.history_san()[6], "0-0");
+        assert_eq!(game.history_san()[12], "Rxe5+");
+
+        assert!(game.undo_last());
+        assert_eq!(game.history_san().len(), line.len() - 1);
+        assert!(game.redo());
+        assert_eq!(game.history_san(), &expected[..]);
+
+        // Moves that break the rules aren't recorded.
+        game.move_pieces(&[((0, 1), (0, 4))]);
+        assert_eq!(game.history_san().len(), line.len());
+    }
+
+    #[test]
+    fn attacks_from() {
+        let mut game = Game::new_empty();
+        game.set_at_pos((4, 3), Some(&WHITE[0]));
+
+        // From c2 the diagonal towards h7 is cut off at e4, while b1 and b3 are open.
+        let mut squares = game.attacks_from(Kind::Bishop, Color::White, (2, 1));
+        squares.sort();
+        assert_eq!(
+            squares,
+            vec![(0, 3), (1, 0), (1, 2), (3, 0), (3, 2), (4, 3)]
+        );
+        // The square itself doesn't need to be empty or hold that piece.
+        assert_eq!(
+            game.attacks_from(Kind::Bishop, Color::Black, (4, 3)).len(),
+            13
+        );
+        assert_eq!(
+            game.attacks_from(Kind::Pawn, Color::Black, (4, 3)),
+            vec![(3, 2), (5, 2)]
+        );
+        assert!(game
+            .attacks_from(Kind::Knight, Color::White, (8, 0))
+            .is_empty());
+    }
+
+    #[test]
+    fn missing_king() {
+        let mut game = Game::new_empty();
+        game.set_at_pos((4, 7), Some(&BLACK[5]));
+        game.set_at_pos((0, 1), Some(&WHITE[0]));
+
+        assert_eq!(
+            game.try_in_check(Color::White),
+            Err(MissingKing(Color::White))
+        );
+        assert_eq!(game.try_in_check(Color::Black), Ok(false));
+        assert_eq!(game.try_check_victory(), Err(MissingKing(Color::White)));
+        assert_eq!(
+            MissingKing(Color::White).to_string(),
+            "there is no white king"
+        );
+
+        game.ignore_kings(true);
+        assert_eq!(game.try_in_check(Color::White), Ok(false));
+        assert!(!game.in_check(Color::White));
+        assert!(game.try_check_victory().unwrap().is_none());
+    }
+
+    #[test]
+    fn square_accessors() {
+        let mut game = Game::new();
+        assert_eq!(game.get_from_square("e2"), Ok(Some(&WHITE[0])));
+        assert_eq!(game.get_from_square("E8"), Ok(Some(&BLACK[5])));
+        assert_eq!(game.get_from_square("e4"), Ok(None));
+        assert_eq!(
+            game.get_from_square("z9"),
+            Err(CoordError("z9".to_string()))
+        );
+        assert!(game.get_from_square("e10").is_err());
+
+        game.set_at_square("e4", Some(&BLACK[2])).unwrap();
+        assert_eq!(game.get_from_pos((4, 3)), Some(&BLACK[2]));
+        game.set_at_square("e2", None).unwrap();
+        assert_eq!(game.get_from_pos((4, 1)), None);
+        assert!(game.set_at_square("i1", Some(&WHITE[0])).is_err());
+    }
+
+    #[test]
+    fn name_checkmate() {
+        let game = Game::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+        assert_eq!(game.name_checkmate(), Some("back-rank mate"));
+
+        let game = Game::from_fen("6rk/5Npp/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+        assert_eq!(game.name_checkmate(), Some("smothered mate"));
+
+        let game = Game::from_fen("6k1/8/8/8/8/8/5PPP/r5K1 w - - 0 1").unwrap();
+        assert_eq!(game.name_checkmate(), Some("back-rank mate"));
+
+        // Scholar's mate is neither.
+        let game =
+            Game::from_fen("r1bqkb1r/pppp1Qpp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 4")
+                .unwrap();
+        assert!(game.is_checkmate(Color::Black));
+        assert_eq!(game.name_checkmate(), None);
+
+        // Check, but not mate.
+        let game = Game::from_fen("R5k1/5pp1/7p/8/8/8/8/6K1 b - - 0 1").unwrap();
+        assert_eq!(game.name_checkmate(), None);
+        assert_eq!(Game::new().name_checkmate(), None);
+    }
+
+    #[test]
+    fn legal_destinations_bb() {
+        let game = Game::new();
+        // a3 is bit 16 and c3 bit 18.
+        assert_eq!(game.legal_destinations_bb((1, 0)), 1 << 16 | 1 << 18);
+        assert_eq!(game.legal_destinations_bb((4, 1)), 1 << 20 | 1 << 28);
+        assert_eq!(game.legal_destinations_bb((0, 0)), 0);
+        assert_eq!(game.legal_destinations_bb((4, 4)), 0);
+        assert_eq!(game.legal_destinations_bb((8, 0)), 0);
+
+        // Castling shows up as the king's destination, g1.
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        assert_ne!(game.legal_destinations_bb((4, 0)) & 1 << 6, 0);
+    }
+
+    #[test]
+    fn filter_legal() {
+        let game = Game::new();
+        assert_eq!(
+            game.filter_legal(&[
+                ((4, 1), (4, 3)),
+                ((4, 1), (4, 4)),
+                ((6, 0), (5, 2)),
+                ((6, 0), (6, 2)),
+                ((4, 6), (4, 4)),
+                ((4, 4), (4, 5)),
+                ((4, 1), (4, 2)),
+                ((4, 1), (9, 9)),
+            ]),
+            vec![true, false, true, false, true, false, true, false]
+        );
+        assert!(game.filter_legal(&[]).is_empty());
+    }
+
+    #[test]
+    fn move_cache() {
+        let fen = "r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/2N2N2/PPPP1PPP/R1BQK2R w KQkq - 4 4";
+        let plain = Game::from_fen(fen).unwrap();
+        let mut cached = Game::from_fen(fen).unwrap();
+        cached.move_cache(true);
+
+        let cache = |game: &Game| {
+            let cache = game.move_cache.as_ref().unwrap().borrow();
+            (cache.moves.len(), cache.hits)
+        };
+
+        // The first call fills the cache, the rest only look moves up.
+        assert_eq!(cached.check_victory(), plain.check_victory());
+        let (entries, hits) = cache(&cached);
+        assert!(entries > 0);
+        for _ in 0..5 {
+            assert_eq!(cached.check_victory(), plain.check_victory());
+        }
+        let (entries_after, hits_after) = cache(&cached);
+        assert_eq!(entries_after, entries);
+        assert!(hits_after > hits);
+        for color in &[Color::White, Color::Black] {
+            assert_eq!(
+                cached.all_valid_moves(*color),
+                plain.all_valid_moves(*color)
+            );
+        }
+
+        // Moving clears the cache, so the answers stay the same as without it.
+        let mut plain = plain;
+        for m in &["e1g1", "e8g8", "f3e5", "c6e5"] {
+            plain.apply_uci(m).unwrap();
+            cached.apply_uci(m).unwrap();
+            for color in &[Color::White, Color::Black] {
+                assert_eq!(
+                    cached.all_valid_moves(*color),
+                    plain.all_valid_moves(*color)
+                );
+            }
+        }
+        assert_eq!(cached.to_fen(), plain.to_fen());
+        cached.move_cache(false);
+        assert_eq!(cached.valid_moves((3, 0)), plain.valid_moves((3, 0)));
+    }
+
+    #[test]
+    fn castle() {
+        let mut game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(game.castle_kingside(Color::White), Ok(()));
+        assert_eq!(game.get_from_pos((6, 0)), Some(&WHITE[5]));
+        assert_eq!(game.get_from_pos((5, 0)), Some(&WHITE[1]));
+        assert_eq!(game.get_from_pos((7, 0)), None);
+        assert_eq!(game.castle_queenside(Color::Black), Ok(()));
+        assert_eq!(game.get_from_pos((2, 7)), Some(&BLACK[5]));
+        assert_eq!(game.get_from_pos((3, 7)), Some(&BLACK[1]));
+        assert_eq!(game.history_san(), &["0-0", "0-0-0"]);
+        assert_eq!(
+            game.castle_queenside(Color::White),
+            Err(CastleError::NoRight)
+        );
+
+        assert_eq!(
+            Game::new().castle_kingside(Color::White),
+            Err(CastleError::Blocked)
+        );
+        let mut game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w Qkq - 0 1").unwrap();
+        assert_eq!(
+            game.castle_kingside(Color::White),
+            Err(CastleError::NoRight)
+        );
+
+        // The rook on f8 covers f1, which the king would pass through.
+        let before = Game::from_fen("4kr2/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        let mut game = before.clone();
+        assert_eq!(game.castle_kingside(Color::White), Err(CastleError::Check));
+        assert!(game == before);
+        assert_eq!(game.castle_queenside(Color::White), Ok(()));
+
+        // No castling out of check.
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/4r3/R3K2R w KQ - 0 1").unwrap();
+        assert_eq!(game.castle_kingside(Color::White), Err(CastleError::Check));
+        assert_eq!(game.castle_queenside(Color::White), Err(CastleError::Check));
+    }
+
+    #[test]
+    fn with_side_to_move() {
+        let mut game = Game::new();
+        game.move_pieces(&[((4, 1), (4, 3))]);
+        assert_eq!(
+            game.with_side_to_move(Color::Black).to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+
+        let swapped = game.with_side_to_move(Color::White);
+        assert_eq!(swapped.side_to_move(), Color::White);
+        assert_eq!(swapped.en_passant_square(), None);
+        assert_eq!(
+            swapped.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1"
+        );
+        assert!(swapped.moves_played().is_empty());
+        assert_eq!(game.side_to_move(), Color::Black);
+    }
+
+    #[test]
+    fn can_player_move() {
+        let mut game = Game::new();
+        assert!(game.can_player_move((4, 1), Color::White));
+        assert!(!game.can_player_move((4, 6), Color::White));
+        assert!(!game.can_player_move((4, 6), Color::Black));
+        assert!(!game.can_player_move((4, 3), Color::White));
+
+        game.move_pieces(&[((4, 1), (4, 3))]);
+        assert!(game.can_player_move((4, 6), Color::Black));
+        assert!(!game.can_player_move((3, 1), Color::White));
+    }
+
+    #[test]
+    fn color_to_move_after() {
+        let mut game = Game::new();
+        assert_eq!(game.color_to_move_after(0), Color::White);
+        assert_eq!(game.color_to_move_after(1), Color::Black);
+        assert_eq!(game.color_to_move_after(6), Color::White);
+        // Moves already made don't change where the count starts.
+        game.move_pieces(&[((4, 1), (4, 3))]);
+        assert_eq!(game.color_to_move_after(1), Color::Black);
+
+        let mut game = Game::from_fen("4k3/4p3/8/8/8/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(game.color_to_move_after(0), Color::Black);
+        assert_eq!(game.color_to_move_after(1), Color::White);
+        game.move_pieces(&[((4, 6), (4, 4))]);
+        assert_eq!(game.color_to_move_after(2), Color::Black);
+    }
+
+    #[test]
+    fn legal_moves_by_piece() {
+        let game = Game::new();
+        let groups = game.legal_moves_by_piece(Color::White);
+        assert_eq!(groups.len(), 16);
+        assert_eq!(groups.iter().map(|(_, _, d)| d.len()).sum::<usize>(), 20);
+        assert_eq!(groups[0], ((0, 0), &WHITE[1], vec![]));
+        assert_eq!(groups[1], ((1, 0), &WHITE[2], vec![(0, 2), (2, 2)]));
+    }
+
+    #[test]
+    fn is_legal_position() {
+        assert_eq!(Game::new().is_legal_position(), Ok(()));
+
+        let game = Game::from_fen("3Pk3/8/8/8/8/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(
+            game.is_legal_position(),
+            Err(IllegalPosition::PawnOnBackRank((3, 7)))
+        );
+
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/8 w - - 0 1").unwrap();
+        assert_eq!(
+            game.is_legal_position(),
+            Err(IllegalPosition::KingCount(Color::White, 0))
+        );
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/K3K3 w - - 0 1").unwrap();
+        assert_eq!(
+            game.is_legal_position(),
+            Err(IllegalPosition::KingCount(Color::White, 2))
+        );
+
+        // White to move could take the king on e8.
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/4RK2 w - - 0 1").unwrap();
+        assert_eq!(
+            game.is_legal_position(),
+            Err(IllegalPosition::OpponentInCheck(Color::Black))
+        );
+
+        // Two extra queens with all eight pawns still on the board.
+        let game = Game::from_fen("4k3/8/8/8/8/8/PPPPPPPP/QQQ1K3 w - - 0 1").unwrap();
+        assert_eq!(
+            game.is_legal_position(),
+            Err(IllegalPosition::TooManyPieces(Color::White))
+        );
+        let game = Game::from_fen("4k3/8/8/8/8/8/PPPPPP2/QQQ1K3 w - - 0 1").unwrap();
+        assert_eq!(game.is_legal_position(), Ok(()));
+    }
+
+    #[test]
+    fn reset() {
+        let mut game = Game::new();
+        game.record_san(false);
+        game.apply_san_line("1. e4 e5 2. Nf3 Nc6 3. Bb5").unwrap();
+        game.reset();
+        assert!(game == Game::new());
+        assert!(!game.undo_last());
+        assert_eq!(game.repetitions(), 1);
+        assert!(!game.record_san);
+
+        let fen = "r3k2r/ppp2ppp/8/3pP3/8/8/PPP2PPP/R3K2R w Kq d6 0 12";
+        game.reset_to_fen(fen).unwrap();
+        assert_eq!(game.to_fen(), fen);
+        assert!(game.reset_to_fen("not a fen").is_err());
+        assert_eq!(game.to_fen(), fen);
+    }
+
+    #[test]
+    fn discovers_check() {
+        // The knight on e4 blocks the rook on e1 from the king on e8.
+        let game = Game::from_fen("4k3/8/8/8/4N3/8/8/4RK2 w - - 0 1").unwrap();
+        let discovery = vec![((4, 3), (2, 4))];
+        assert!(game.discovers_check(&discovery));
+        assert_eq!(game.move_to_an(&discovery, false, false), "Nc5+");
+        // Staying on the file keeps the line closed.
+        assert!(!game.discovers_check(&[((4, 3), (4, 5))]));
+
+        // A direct check isn't a discovered one.
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/R4K2 w - - 0 1").unwrap();
+        assert!(!game.discovers_check(&[((0, 0), (0, 7))]));
+
+        // The e5 pawn blocks the bishop on a1, and taking en passant moves it off the diagonal.
+        let game = Game::from_fen("7k/8/8/3pP3/8/8/8/B5K1 w - d6 0 1").unwrap();
+        let capture = game
+            .valid_moves((4, 4))
+            .into_iter()
+            .find(|m| m.len() == 2)
+            .unwrap();
+        assert!(game.discovers_check(&capture));
+        assert!(!game.discovers_check(&[((6, 0), (6, 1))]));
+    }
+
+    #[test]
+    fn fifty_and_seventy_five_move_rules() {
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 99 60").unwrap();
+        assert!(!game.can_claim_fifty_move() && !game.fifty_move_rule());
+        game.move_pieces(&[((4, 0), (3, 0))]);
+        assert!(game.can_claim_fifty_move() && game.fifty_move_rule());
+        assert!(!game.must_draw_seventy_five());
+        assert!(game.check_victory().is_none());
+
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 149 90").unwrap();
+        assert!(!game.must_draw_seventy_five());
+        game.move_pieces(&[((4, 0), (3, 0))]);
+        assert!(game.must_draw_seventy_five());
+        assert_eq!(
+            game.check_victory(),
+            Some((VictoryStatus::Draw, Color::White))
+        );
+    }
+
+    #[test]
+    fn knight_shuffles_reach_fifty_and_seventy_five_moves() {
+        let shuffle = [
+            ((6, 0), (5, 2)),
+            ((6, 7), (5, 5)),
+            ((5, 2), (6, 0)),
+            ((5, 5), (6, 7)),
+        ];
+        let mut game = Game::new();
+        for ply in 1..=150 {
+            game.move_pieces(&[shuffle[(ply - 1) % shuffle.len()]]);
+            assert_eq!(game.fifty_move_counter(), ply as u32);
+            assert_eq!(game.fifty_move_rule(), ply >= 100, "ply {}", ply);
+            assert_eq!(game.must_draw_seventy_five(), ply >= 150, "ply {}", ply);
+        }
+
+        // A pawn move resets the clock, a knight move doesn't.
+        game.move_pieces(&[((4, 1), (4, 3))]);
+        assert_eq!(game.fifty_move_counter(), 0);
+        game.move_pieces(&[((5, 5), (6, 7))]);
+        assert_eq!(game.fifty_move_counter(), 1);
+
+        // The shuffles repeat positions, which draws by itself, so the automatic draw is checked
+        // from a position without history: one quiet knight move takes the clock to 150.
+        let mut game = Game::from_fen("4k3/4p3/8/8/8/8/4P3/1N2K3 w - - 149 80").unwrap();
+        assert!(game.check_victory().is_none());
+        game.move_pieces(&[((1, 0), (2, 2))]);
+        assert_eq!(game.fifty_move_counter(), 150);
+        assert_eq!(
+            game.check_victory(),
+            Some((VictoryStatus::Draw, Color::White))
+        );
+    }
+
+    /// Counts the lines of play `depth` plies deep, as in perft. Promotions count once, as
+    /// `valid_moves` returns a single move that promotes to the default piece.
+    fn count_legal_games_to_depth(game: &Game, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        game.all_valid_moves(game.side_to_move())
+            .iter()
+            .map(|m| {
+                let mut next = game.scratch();
+                next.move_pieces(m);
+                count_legal_games_to_depth(&next, depth - 1)
+            })
+            .sum()
+    }
+
+    #[test]
+    fn rule_coverage() {
+        for &(fen, depth, expected) in [
+            // e5-e6 and exd6 e.p., plus five king moves.
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", 1, 7),
+            // The same for black: d4-d3 and dxe3 e.p., plus five king moves.
+            ("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1", 1, 7),
+            // exd6 e.p. would clear the fifth rank between the king on a5 and the rook on h5, so
+            // only e5-e6 and five king moves are left.
+            ("8/8/8/K2pP2r/8/8/8/7k w - d6 0 1", 1, 6),
+            // a7-a8 promotes as a single move, plus three king moves.
+            ("8/P6k/8/8/8/8/8/K7 w - - 0 1", 1, 4),
+            // Both castles, five more king moves, ten moves for the a1 rook and nine for h1.
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", 1, 26),
+            // The rook on f8 covers f1, so only the queenside castle and three king moves are
+            // left, with ten moves for the a1 rook and nine for h1.
+            ("4kr2/8/8/8/8/8/8/R3K2R w KQ - 0 1", 1, 23),
+            // The rook on g8 covers g1, so the king can't castle into check: five king moves
+            // and nine rook moves.
+            ("4k1r1/8/8/8/8/8/8/4K2R w K - 0 1", 1, 14),
+            // In check from a1, the king can't castle or stay on the first rank: d2, e2 and f2.
+            ("4k3/8/8/8/8/8/8/r3K2R w K - 0 1", 1, 3),
+            // The bishop on e2 is pinned and can't move, leaving four king moves.
+            ("4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1", 1, 4),
+            // A pinned rook can still move along the pin: e3 to e6, Rxe7 and four king moves.
+            ("4k3/4r3/8/8/8/8/4R3/4K3 w - - 0 1", 1, 9),
+            // The standard perft results for the start position.
+            (START_FEN, 2, 400),
+            (START_FEN, 3, 8902),
+            // "Kiwipete", which packs castling, en passant and pins into one position.
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                2,
+                2039,
+            ),
+        ]
+        .iter()
+        {
+            let game = Game::from_fen(fen).unwrap();
+            assert_eq!(
+                count_legal_games_to_depth(&game, depth),
+                expected,
+                "{} at depth {}",
+                fen,
+                depth
+            );
+        }
+
+        // Each of the four promotion pieces can be chosen.
+        for &kind in [Kind::Queen, Kind::Rook, Kind::Bishop, Kind::Knight].iter() {
+            let mut game = Game::from_fen("8/P6k/8/8/8/8/8/K7 w - - 0 1").unwrap();
+            game.move_piece_promote((0, 6), (0, 7), Some(kind)).unwrap();
+            assert_eq!(
+                game.get_from_pos((0, 7)),
+                Some(Piece::get(Color::White, kind))
+            );
+        }
+    }
+
+    #[test]
+    fn moves_played() {
+        let mut game = Game::new();
+        assert!(game.moves_played().is_empty());
+
+        let moves: Vec<Move> = vec![
+            vec![((4, 1), (4, 3))],
+            vec![((4, 6), (4, 4))],
+            vec![((6, 0), (5, 2))],
+        ];
+        for m in &moves {
+            game.move_pieces(m);
+        }
+        assert_eq!(game.moves_played(), &moves[..]);
+
+        game.undo_last();
+        assert_eq!(game.moves_played(), &moves[..2]);
+        game.redo();
+        assert_eq!(game.moves_played(), &moves[..]);
+
+        game.reset();
+        assert!(game.moves_played().is_empty());
+    }
+
+    #[test]
+    fn infer_move() {
+        let infer = |before: &str, after: &str| {
+            super::infer_move(
+                &Game::from_fen(before).unwrap(),
+                &Game::from_fen(after).unwrap(),
+            )
+        };
+
+        let castle = infer(
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+            "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 1 1",
+        )
+        .unwrap();
+        assert_eq!(castle.len(), 3);
+        assert_eq!(destination(&castle), (6, 0));
+        let castle = infer(
+            "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1",
+            "2kr3r/8/8/8/8/8/8/R3K2R w KQ - 1 2",
+        )
+        .unwrap();
+        assert_eq!(destination(&castle), (2, 7));
+
+        let en_passant = infer(
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2",
+            "4k3/8/3P4/8/8/8/8/4K3 b - - 0 2",
+        )
+        .unwrap();
+        assert_eq!(en_passant.len(), 2);
+        assert_eq!(en_passant[0].0, (4, 4));
+        assert_eq!(destination(&en_passant), (3, 5));
+
+        let capture = infer(
+            "4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 2",
+            "4k3/8/8/3P4/8/8/8/4K3 b - - 0 2",
+        );
+        assert_eq!(capture, Some(vec![((4, 3), (3, 4))]));
+        let promotion = infer(
+            "8/1P2k3/8/8/8/8/8/4K3 w - - 0 1",
+            "1N6/4k3/8/8/8/8/8/4K3 b - - 0 1",
+        );
+        assert_eq!(promotion, Some(vec![((1, 6), (1, 7))]));
+
+        // Two moves apart, and a king that can't move like that.
+        assert_eq!(
+            infer(
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2",
+            ),
+            None
+        );
+        assert_eq!(
+            infer(
+                "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+                "4k3/8/8/8/8/8/8/K7 b - - 1 1"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn king_escape_squares() {
+        let game = Game::from_fen("k7/8/8/8/8/8/8/1R4K1 b - - 0 1").unwrap();
+        assert_eq!(game.king_escape_squares(Color::Black), vec![(0, 6)]);
+        assert_eq!(
+            game.king_escape_squares(Color::White),
+            vec![(5, 0), (7, 0), (5, 1), (6, 1), (7, 1)]
+        );
+
+        // Taking the pawn is the king's only move, and it isn't an escape square.
+        let game = Game::from_fen("k7/P7/8/8/8/8/8/1R4K1 b - - 0 1").unwrap();
+        assert!(game.king_escape_squares(Color::Black).is_empty());
+        assert!(Game::new().king_escape_squares(Color::White).is_empty());
+    }
+
+    #[test]
+    fn rank_and_file() {
+        let game = Game::new();
+        let back_rank: Vec<Kind> = game.rank(0).iter().map(|p| p.unwrap().kind).collect();
+        assert_eq!(
+            back_rank,
+            vec![
+                Kind::Rook,
+                Kind::Knight,
+                Kind::Bishop,
+                Kind::Queen,
+                Kind::King,
+                Kind::Bishop,
+                Kind::Knight,
+                Kind::Rook
+            ]
+        );
+        assert!(game
+            .rank(0)
+            .iter()
+            .all(|p| p.unwrap().color == Color::White));
+        assert!(game.rank(4).iter().all(|p| p.is_none()));
+
+        assert_eq!(
+            game.file(4),
+            [
+                Some(&WHITE[5]),
+                Some(&WHITE[0]),
+                None,
+                None,
+                None,
+                None,
+                Some(&BLACK[0]),
+                Some(&BLACK[5])
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Rank 8 is off the board")]
+    fn rank_off_the_board() {
+        Game::new().rank(BOARD_SIZE);
+    }
+
+    #[test]
+    fn has_legal_move() {
+        for fen in &[
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            // Stalemate, and a position where black's only moves are with the king.
+            "k7/8/1Q6/8/8/8/8/K7 b - - 0 1",
+            "k7/8/2Q5/8/8/8/8/K7 b - - 0 1",
+            // Every black pawn is blocked and the king has one square left, or none.
+            "7k/4Q3/8/p1p1p1p1/P1P1P1P1/8/8/K7 b - - 0 1",
+            "7k/5Q2/6K1/p1p1p1p1/P1P1P1P1/8/8/8 b - - 0 1",
+            // Checkmate, and a check that can only be answered by a block.
+            "6k1/5ppp/8/8/8/8/8/R5K1 b - - 0 1",
+            "R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1",
+            "R5k1/4nppp/8/8/8/8/8/6K1 b - - 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+            // En passant is possible, and a bishop is pinned to its king.
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2",
+            "4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1",
+        ] {
+            let mut game = Game::from_fen(fen).unwrap();
+            for &cache in &[false, true] {
+                game.move_cache(cache);
+                for &color in &[Color::White, Color::Black] {
+                    assert_eq!(
+                        game.has_legal_move(color),
+                        !game.all_valid_moves(color).is_empty(),
+                        "{} {:?}",
+                        fen,
+                        color
+                    );
+                }
+            }
+        }
+
+        let victory = |fen| Game::from_fen(fen).unwrap().check_victory();
+        assert_eq!(
+            victory("k7/8/1Q6/8/8/8/8/K7 b - - 0 1"),
+            Some((VictoryStatus::Stalemate, Color::White))
+        );
+        assert_eq!(victory("7k/4Q3/8/p1p1p1p1/P1P1P1P1/8/8/K7 b - - 0 1"), None);
+        assert_eq!(
+            victory("7k/5Q2/6K1/p1p1p1p1/P1P1P1P1/8/8/8 b - - 0 1"),
+            Some((VictoryStatus::Stalemate, Color::White))
+        );
+        assert_eq!(
+            victory("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1"),
+            Some((VictoryStatus::Checkmate, Color::White))
+        );
+        assert_eq!(victory("R5k1/4nppp/8/8/8/8/8/6K1 b - - 0 1"), None);
+    }
+
+    #[test]
+    fn play_keeps_state_in_step() {
+        let mut game = Game::new();
+        assert_eq!(game.play((4, 1), (4, 3), None), Ok(None));
+        assert_eq!(
+            game.play((3, 1), (3, 3), None),
+            Err(PlayError::IllegalMove((3, 1), (3, 3)))
+        );
+        assert_eq!(
+            game.play((4, 6), (4, 3), None),
+            Err(PlayError::IllegalMove((4, 6), (4, 3)))
+        );
+        game.play((4, 6), (4, 4), None).unwrap();
+        assert_eq!(game.get_turn(), 2);
+        assert_eq!(game.side_to_move(), Color::White);
+        assert_eq!(game.history_san(), &["e4", "e5"]);
+
+        let mut game = Game::from_fen("r3k3/1P6/8/8/8/8/8/4K3 w q - 0 40").unwrap();
+        assert_eq!(
+            game.play((4, 0), (3, 0), Some(Kind::Queen)),
+            Err(PlayError::Promotion(PromotionError::NotAPromotion(
+                Kind::Queen
+            )))
+        );
+        assert_eq!(
+            game.play((1, 6), (0, 7), Some(Kind::Knight)),
+            Ok(Some(&BLACK[1]))
+        );
+        assert_eq!(game.get_from_pos((0, 7)), Some(&WHITE[2]));
+        assert_eq!(game.fifty_move_counter(), 0);
+        assert_eq!(game.default_promotion, Kind::Queen);
+
+        // The clock, the castling rights and the turn count all move on.
+        game.play((4, 7), (4, 6), None).unwrap();
+        assert_eq!(game.to_fen(), "N7/4k3/8/8/8/8/8/4K3 w - - 1 41");
+        assert!(game.undo_last());
+        assert_eq!(game.fifty_move_counter(), 0);
+    }
+
+    #[test]
+    fn legal_moves_annotated() {
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/1B6/R5K1 w - - 0 1").unwrap();
+        let moves = game.legal_moves_annotated(Color::White);
+        assert_eq!(moves.len(), game.all_valid_moves(Color::White).len());
+        let flags = |m: Move| {
+            moves
+                .iter()
+                .find(|(n, _, _)| *n == m)
+                .map(|&(_, c, k)| (c, k))
+        };
+        assert_eq!(flags(vec![((0, 0), (0, 7))]), Some((true, true)));
+        assert_eq!(flags(vec![((0, 0), (0, 6))]), Some((false, false)));
+        assert_eq!(flags(vec![((1, 1), (5, 5))]), Some((false, false)));
+        let checks = moves.iter().filter(|(_, check, _)| *check).count();
+        assert_eq!(checks, 1);
+
+        // The flags agree with the notation.
+        let game =
+            Game::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4")
+                .unwrap();
+        for (m, check, mate) in game.legal_moves_annotated(Color::White) {
+            let san = game.move_to_an(&m, false, false);
+            assert_eq!(check, san.ends_with('+') || mate, "{}", san);
+            assert_eq!(mate, san == "Qxf7", "{}", san);
+        }
+    }
+
+    #[test]
+    fn move_piece_castles() {
+        let mut game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        game.move_piece((4, 0), (6, 0));
+        assert_eq!(game.get_from_pos((6, 0)), Some(&WHITE[5]));
+        assert_eq!(game.get_from_pos((5, 0)), Some(&WHITE[1]));
+        assert_eq!(game.get_from_pos((7, 0)), None);
+        assert!(!game.white_can_castle_right && !game.white_can_castle_left);
+
+        game.move_piece((4, 7), (2, 7));
+        assert_eq!(game.get_from_pos((2, 7)), Some(&BLACK[5]));
+        assert_eq!(game.get_from_pos((3, 7)), Some(&BLACK[1]));
+        assert_eq!(game.get_from_pos((0, 7)), None);
+        assert!(!game.black_can_castle_right && !game.black_can_castle_left);
+        assert_eq!(game.to_fen(), "2kr3r/8/8/8/8/8/8/R4RK1 w - - 2 1");
+
+        // Without the right the king just moves.
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/8/R3K2R w Q - 0 1").unwrap();
+        game.move_piece((4, 0), (6, 0));
+        assert_eq!(game.get_from_pos((7, 0)), Some(&WHITE[1]));
+        assert_eq!(game.get_from_pos((5, 0)), None);
+    }
+
+    #[test]
+    fn whose_turn() {
+        let mut game = Game::new();
+        assert_eq!(game.whose_turn(), Color::White);
+        game.move_pieces(&[((4, 1), (4, 3))]);
+        assert_eq!(game.whose_turn(), Color::Black);
+        // The turn number doesn't change who moves.
+        game.next_turn();
+        assert_eq!(game.whose_turn(), Color::Black);
+        game.move_pieces(&[((4, 6), (4, 4))]);
+        assert_eq!(game.whose_turn(), Color::White);
+        game.undo_last();
+        assert_eq!(game.whose_turn(), Color::Black);
+
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/4K3 b - - 0 12").unwrap();
+        assert_eq!(game.whose_turn(), Color::Black);
+    }
+
+    #[test]
+    fn valid_moves_for_turn() {
+        let mut game = Game::new();
+        assert_eq!(game.valid_moves_for_turn((4, 1)), game.valid_moves((4, 1)));
+        assert!(game.valid_moves_for_turn((4, 6)).is_empty());
+        assert!(!game.valid_moves((4, 6)).is_empty());
+        assert!(game.valid_moves_for_turn((4, 3)).is_empty());
+        assert!(game.valid_moves_for_turn((8, 0)).is_empty());
+
+        game.move_pieces(&[((4, 1), (4, 3))]);
+        assert!(game.valid_moves_for_turn((3, 1)).is_empty());
+        assert_eq!(game.valid_moves_for_turn((4, 6)).len(), 2);
+    }
+
+    #[test]
+    fn control_map() {
+        let game = Game::new();
+        let white = game.control_map(Color::White);
+        // Nothing reaches D4 from the starting position.
+        assert_eq!(white[3][3], 0);
+        // C3 is covered by the B and D pawns and the B1 knight.
+        assert_eq!(white[2][2], 3);
+        assert_eq!(white[3][2], 2);
+        // D2 is defended by the knight, bishop, queen and king.
+        assert_eq!(white[3][1], 4);
+
+        let black = game.control_map(Color::Black);
+        assert_eq!(black[2][5], 3);
+        assert_eq!(black[3][3], 0);
+    }
+
+    #[test]
+    fn en_passant_resets_fifty_move_counter() {
+        let mut game = Game::new();
+        game.move_pieces(&[((4, 1), (4, 3))]);
+        game.move_pieces(&[((1, 7), (2, 5))]);
+        game.move_pieces(&[((4, 3), (4, 4))]);
+        game.move_pieces(&[((2, 5), (1, 7))]);
+        game.move_pieces(&[((6, 0), (5, 2))]);
+        assert_eq!(game.fifty_move_counter(), 2);
+
+        // The double push resets the counter, so set it up again before capturing.
+        game.move_pieces(&[((3, 6), (3, 4))]);
+        game.seventy_five_move_rule = 7;
+
+        // The first step of en passant moves onto the captured pawn, the second onto the empty
+        // target square.
+        let passant = game
+            .valid_moves((4, 4))
+            .into_iter()
+            .find(|m| m.len() == 2)
+            .unwrap();
+        assert_eq!(passant, vec![((4, 4), (3, 4)), ((3, 4), (3, 5))]);
+        assert_eq!(game.move_pieces(&passant), Some(&BLACK[0]));
+        assert_eq!(game.fifty_move_counter(), 0);
+        assert_eq!(game.get_from_pos((3, 4)), None);
+        assert_eq!(game.get_from_pos((3, 5)), Some(&WHITE[0]));
+
+        game.move_pieces(&[((1, 7), (2, 5))]);
+        assert_eq!(game.fifty_move_counter(), 1);
+    }
+
+    #[test]
+    fn en_passant_requires_a_pawn() {
+        let mut game = Game::new_empty();
+        game.set_at_pos((4, 0), Some(&WHITE[5]));
+        game.set_at_pos((4, 7), Some(&BLACK[5]));
+        game.set_at_pos((4, 4), Some(&WHITE[0]));
+        game.set_at_pos((3, 6), Some(&BLACK[1]));
+        play(&mut game, &[("d7", "d5")]);
+
+        assert!(!has_move(&game, "e5", "d6"));
+    }
+}
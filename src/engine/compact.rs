@@ -0,0 +1,228 @@
+use super::{Color, Game, Kind, Piece, BOARD_SIZE};
+
+/// The number of bytes in a compact position: 32 for the board and 6 for the rest of the state.
+pub const COMPACT_LEN: usize = 38;
+
+const BLACK_TO_MOVE: u8 = 1;
+const WHITE_KINGSIDE: u8 = 1 << 1;
+const WHITE_QUEENSIDE: u8 = 1 << 2;
+const BLACK_KINGSIDE: u8 = 1 << 3;
+const BLACK_QUEENSIDE: u8 = 1 << 4;
+const EN_PASSANT: u8 = 1 << 5;
+
+const KINDS: [Kind; 6] = [
+    Kind::Pawn,
+    Kind::Rook,
+    Kind::Knight,
+    Kind::Bishop,
+    Kind::Queen,
+    Kind::King,
+];
+
+/// The ways a compact position can fail to decode.
+#[derive(PartialEq, Debug, Clone)]
+pub enum CompactError {
+    /// The input wasn't exactly `COMPACT_LEN` bytes long.
+    Length(usize),
+    /// A square held a code above 12.
+    InvalidPiece(u8),
+    /// The flags used bits that have no meaning.
+    InvalidFlags(u8),
+    /// The en passant file wasn't between 0 and 7.
+    InvalidEnPassant(u8),
+    /// The fullmove number was 0.
+    InvalidFullmoveNumber,
+}
+
+impl std::fmt::Display for CompactError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CompactError::Length(n) => write!(f, "expected {} bytes, found {}", COMPACT_LEN, n),
+            CompactError::InvalidPiece(code) => write!(f, "invalid piece code {}", code),
+            CompactError::InvalidFlags(flags) => write!(f, "invalid flags {:#04x}", flags),
+            CompactError::InvalidEnPassant(file) => write!(f, "invalid en passant file {}", file),
+            CompactError::InvalidFullmoveNumber => write!(f, "the fullmove number can't be 0"),
+        }
+    }
+}
+
+impl std::error::Error for CompactError {}
+
+impl<'a> Game<'a> {
+    /// Encodes the position in `COMPACT_LEN` bytes, small enough to store a snapshot of a game
+    /// instead of its moves.
+    ///
+    /// The first 32 bytes hold the board, four bits per square from A1 through H1, then A2
+    /// through H2, until H8, with the even square in the low bits. A square is 0 when empty,
+    /// 1 to 6 for a white pawn, rook, knight, bishop, queen or king and 7 to 12 for the black
+    /// ones. Then follow a byte of flags for the side to move, the castling rights and whether
+    /// there is an en passant square, a byte with the en passant file, and the halfmove clock and
+    /// fullmove number as big endian 16-bit numbers, capped at 65535.
+    ///
+    pub fn to_compact(&self) -> Vec<u8> {
+        let mut bytes = vec![0; COMPACT_LEN];
+        for y in 0..BOARD_SIZE {
+            for x in 0..BOARD_SIZE {
+                let code = self.board[x][y].map_or(0, piece_code);
+                let square = y * BOARD_SIZE + x;
+                bytes[square / 2] |= code << (4 * (square % 2));
+            }
+        }
+
+        let mut flags = 0;
+        for &(set, flag) in &[
+            (self.side_to_move() == Color::Black, BLACK_TO_MOVE),
+            (self.white_can_castle_right, WHITE_KINGSIDE),
+            (self.white_can_castle_left, WHITE_QUEENSIDE),
+            (self.black_can_castle_right, BLACK_KINGSIDE),
+            (self.black_can_castle_left, BLACK_QUEENSIDE),
+        ] {
+            if set {
+                flags |= flag;
+            }
+        }
+        if let Some((file, _)) = self.en_passant_square() {
+            flags |= EN_PASSANT;
+            bytes[33] = file as u8;
+        }
+        bytes[32] = flags;
+
+        let clock = self.seventy_five_move_rule.min(u16::MAX as u32) as u16;
+        let turn = self.turn.min(u16::MAX as u32) as u16;
+        bytes[34..36].copy_from_slice(&clock.to_be_bytes());
+        bytes[36..38].copy_from_slice(&turn.to_be_bytes());
+        bytes
+    }
+
+    /// Creates a game from a position encoded with `to_compact`.
+    ///
+    pub fn from_compact(bytes: &[u8]) -> Result<Game<'a>, CompactError> {
+        if bytes.len() != COMPACT_LEN {
+            return Err(CompactError::Length(bytes.len()));
+        }
+
+        let mut game = Game::new_empty();
+        for y in 0..BOARD_SIZE {
+            for x in 0..BOARD_SIZE {
+                let square = y * BOARD_SIZE + x;
+                let code = bytes[square / 2] >> (4 * (square % 2)) & 0x0f;
+                game.board[x][y] = piece_from_code(code)?;
+            }
+        }
+
+        let flags = bytes[32];
+        if flags >= EN_PASSANT << 1 {
+            return Err(CompactError::InvalidFlags(flags));
+        }
+        let color = if flags & BLACK_TO_MOVE != 0 {
+            Color::Black
+        } else {
+            Color::White
+        };
+        game.last_color = color.opposite();
+        game.white_can_castle_right = flags & WHITE_KINGSIDE != 0;
+        game.white_can_castle_left = flags & WHITE_QUEENSIDE != 0;
+        game.black_can_castle_right = flags & BLACK_KINGSIDE != 0;
+        game.black_can_castle_left = flags & BLACK_QUEENSIDE != 0;
+        if flags & EN_PASSANT != 0 {
+            let file = bytes[33] as usize;
+            if file >= BOARD_SIZE {
+                return Err(CompactError::InvalidEnPassant(bytes[33]));
+            }
+            // The push the side to move can capture was made by the other color.
+            game.last = match color {
+                Color::White => ((file, 6), (file, 4)),
+                Color::Black => ((file, 1), (file, 3)),
+            };
+        }
+
+        game.seventy_five_move_rule = u16::from_be_bytes([bytes[34], bytes[35]]) as u32;
+        game.turn = u16::from_be_bytes([bytes[36], bytes[37]]) as u32;
+        if game.turn == 0 {
+            return Err(CompactError::InvalidFullmoveNumber);
+        }
+
+        game.board_history.clear();
+        game.save_board();
+        Ok(game)
+    }
+}
+
+fn piece_code(piece: &Piece) -> u8 {
+    let kind = KINDS.iter().position(|&k| k == piece.kind).unwrap() as u8;
+    match piece.color {
+        Color::White => 1 + kind,
+        Color::Black => 7 + kind,
+    }
+}
+
+fn piece_from_code(code: u8) -> Result<Option<&'static Piece>, CompactError> {
+    match code {
+        0 => Ok(None),
+        1..=6 => Ok(Some(Piece::get(Color::White, KINDS[code as usize - 1]))),
+        7..=12 => Ok(Some(Piece::get(Color::Black, KINDS[code as usize - 7]))),
+        _ => Err(CompactError::InvalidPiece(code)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for fen in &[
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/ppp2ppp/8/3pP3/8/8/PPP2PPP/R3K2R w Kq d6 0 12",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "8/8/8/8/8/8/8/k6K b - - 42 60",
+        ] {
+            let game = Game::from_fen(fen).unwrap();
+            let bytes = game.to_compact();
+            assert_eq!(bytes.len(), COMPACT_LEN);
+            let decoded = Game::from_compact(&bytes).unwrap();
+            assert!(decoded == game);
+            assert_eq!(decoded.to_fen(), *fen);
+        }
+
+        // A1 is the low half of the first byte, B1 the high half.
+        let bytes = Game::new().to_compact();
+        assert_eq!(bytes[0], 2 | 3 << 4);
+        assert_eq!(bytes[32], 0b11110);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Game::from_compact(&[0; 10]).err(),
+            Some(CompactError::Length(10))
+        );
+
+        let good = Game::new().to_compact();
+        let mut bytes = good.clone();
+        bytes[0] = 0x0d;
+        assert_eq!(
+            Game::from_compact(&bytes).err(),
+            Some(CompactError::InvalidPiece(13))
+        );
+        let mut bytes = good.clone();
+        bytes[32] |= 1 << 6;
+        assert_eq!(
+            Game::from_compact(&bytes).err(),
+            Some(CompactError::InvalidFlags(0b1011110))
+        );
+        let mut bytes = good.clone();
+        bytes[32] |= EN_PASSANT;
+        bytes[33] = 8;
+        assert_eq!(
+            Game::from_compact(&bytes).err(),
+            Some(CompactError::InvalidEnPassant(8))
+        );
+        let mut bytes = good;
+        bytes[37] = 0;
+        assert_eq!(
+            Game::from_compact(&bytes).err(),
+            Some(CompactError::InvalidFullmoveNumber)
+        );
+    }
+}
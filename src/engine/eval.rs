@@ -0,0 +1,682 @@
+use super::{Color, Game, Kind, VictoryStatus, BOARD_SIZE};
+
+/// The score `eval` gives a checkmate on the board.
+///
+/// Searchers should subtract the number of plies to the mate, so that quicker mates score higher
+/// and slower ones lower, like `MATE_SCORE - ply`.
+pub const MATE_SCORE: i32 = 100_000;
+
+const DOUBLED_PAWN: i32 = -10;
+const ISOLATED_PAWN: i32 = -15;
+const PASSED_PAWN: i32 = 20;
+const MOBILITY: i32 = 2;
+
+impl Kind {
+    /// Returns the material value of the piece kind in centipawns.
+    ///
+    /// The king is given a value of 0, as it can never be traded off.
+    pub fn value(&self) -> i32 {
+        match *self {
+            Kind::Pawn => 100,
+            Kind::Knight => 320,
+            Kind::Bishop => 330,
+            Kind::Rook => 500,
+            Kind::Queen => 900,
+            Kind::King => 0,
+        }
+    }
+}
+
+/// Piece-square tables used by the positional evaluation.
+///
+/// Each table is given from white's point of view and is written the way a board is printed,
+/// with the eighth rank first and the A file to the left. Black's pieces use the same tables
+/// mirrored vertically, so `pawn[1]` is the seventh rank for white and the second rank for black.
+///
+/// The defaults are the well known "simplified evaluation function" tables. All fields are
+/// public, so the tables can be tuned and passed to `positional_score_with`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct PieceSquareTables {
+    pub pawn: [[i32; BOARD_SIZE]; BOARD_SIZE],
+    pub knight: [[i32; BOARD_SIZE]; BOARD_SIZE],
+    pub bishop: [[i32; BOARD_SIZE]; BOARD_SIZE],
+    pub rook: [[i32; BOARD_SIZE]; BOARD_SIZE],
+    pub queen: [[i32; BOARD_SIZE]; BOARD_SIZE],
+    /// The king table used in the middlegame.
+    pub king: [[i32; BOARD_SIZE]; BOARD_SIZE],
+    /// The king table used once `is_endgame` returns true.
+    pub king_endgame: [[i32; BOARD_SIZE]; BOARD_SIZE],
+}
+
+impl Default for PieceSquareTables {
+    fn default() -> Self {
+        PieceSquareTables {
+            pawn: [
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [50, 50, 50, 50, 50, 50, 50, 50],
+                [10, 10, 20, 30, 30, 20, 10, 10],
+                [5, 5, 10, 25, 25, 10, 5, 5],
+                [0, 0, 0, 20, 20, 0, 0, 0],
+                [5, -5, -10, 0, 0, -10, -5, 5],
+                [5, 10, 10, -20, -20, 10, 10, 5],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+            ],
+            knight: [
+                [-50, -40, -30, -30, -30, -30, -40, -50],
+                [-40, -20, 0, 0, 0, 0, -20, -40],
+                [-30, 0, 10, 15, 15, 10, 0, -30],
+                [-30, 5, 15, 20, 20, 15, 5, -30],
+                [-30, 0, 15, 20, 20, 15, 0, -30],
+                [-30, 5, 10, 15, 15, 10, 5, -30],
+                [-40, -20, 0, 5, 5, 0, -20, -40],
+                [-50, -40, -30, -30, -30, -30, -40, -50],
+            ],
+            bishop: [
+                [-20, -10, -10, -10, -10, -10, -10, -20],
+                [-10, 0, 0, 0, 0, 0, 0, -10],
+                [-10, 0, 5, 10, 10, 5, 0, -10],
+                [-10, 5, 5, 10, 10, 5, 5, -10],
+                [-10, 0, 10, 10, 10, 10, 0, -10],
+                [-10, 10, 10, 10, 10, 10, 10, -10],
+                [-10, 5, 0, 0, 0, 0, 5, -10],
+                [-20, -10, -10, -10, -10, -10, -10, -20],
+            ],
+            rook: [
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [5, 10, 10, 10, 10, 10, 10, 5],
+                [-5, 0, 0, 0, 0, 0, 0, -5],
+                [-5, 0, 0, 0, 0, 0, 0, -5],
+                [-5, 0, 0, 0, 0, 0, 0, -5],
+                [-5, 0, 0, 0, 0, 0, 0, -5],
+                [-5, 0, 0, 0, 0, 0, 0, -5],
+                [0, 0, 0, 5, 5, 0, 0, 0],
+            ],
+            queen: [
+                [-20, -10, -10, -5, -5, -10, -10, -20],
+                [-10, 0, 0, 0, 0, 0, 0, -10],
+                [-10, 0, 5, 5, 5, 5, 0, -10],
+                [-5, 0, 5, 5, 5, 5, 0, -5],
+                [0, 0, 5, 5, 5, 5, 0, -5],
+                [-10, 5, 5, 5, 5, 5, 0, -10],
+                [-10, 0, 5, 0, 0, 0, 0, -10],
+                [-20, -10, -10, -5, -5, -10, -10, -20],
+            ],
+            king: [
+                [-30, -40, -40, -50, -50, -40, -40, -30],
+                [-30, -40, -40, -50, -50, -40, -40, -30],
+                [-30, -40, -40, -50, -50, -40, -40, -30],
+                [-30, -40, -40, -50, -50, -40, -40, -30],
+                [-20, -30, -30, -40, -40, -30, -30, -20],
+                [-10, -20, -20, -20, -20, -20, -20, -10],
+                [20, 20, 0, 0, 0, 0, 20, 20],
+                [20, 30, 10, 0, 0, 10, 30, 20],
+            ],
+            king_endgame: [
+                [-50, -40, -30, -20, -20, -30, -40, -50],
+                [-30, -20, -10, 0, 0, -10, -20, -30],
+                [-30, -10, 20, 30, 30, 20, -10, -30],
+                [-30, -10, 30, 40, 40, 30, -10, -30],
+                [-30, -10, 30, 40, 40, 30, -10, -30],
+                [-30, -10, 20, 30, 30, 20, -10, -30],
+                [-30, -30, 0, 0, 0, 0, -30, -30],
+                [-50, -30, -30, -30, -30, -30, -30, -50],
+            ],
+        }
+    }
+}
+
+impl PieceSquareTables {
+    /// Returns the table entry for a piece of the given kind and color at the given position.
+    pub fn value(&self, kind: Kind, color: Color, pos: (usize, usize), endgame: bool) -> i32 {
+        let table = match kind {
+            Kind::Pawn => &self.pawn,
+            Kind::Knight => &self.knight,
+            Kind::Bishop => &self.bishop,
+            Kind::Rook => &self.rook,
+            Kind::Queen => &self.queen,
+            Kind::King => {
+                if endgame {
+                    &self.king_endgame
+                } else {
+                    &self.king
+                }
+            }
+        };
+        let row = match color {
+            Color::White => BOARD_SIZE - 1 - pos.1,
+            Color::Black => pos.1,
+        };
+        table[row][pos.0]
+    }
+}
+
+/// Weights used by `king_safety`, in centipawns.
+///
+/// The shelter is made up of the friendly pawns on the king's file and the two files next to it.
+#[derive(PartialEq, Debug, Clone)]
+pub struct KingSafetyWeights {
+    /// Bonus for a shelter pawn directly in front of the king.
+    pub pawn_close: i32,
+    /// Bonus for a shelter pawn two ranks in front of the king.
+    pub pawn_far: i32,
+    /// Penalty for a shelter file with no friendly pawn within two ranks of the king.
+    pub pawn_missing: i32,
+    /// Penalty for a shelter file with no friendly pawns at all.
+    pub half_open_file: i32,
+    /// Penalty for a shelter file with no pawns of either color.
+    pub open_file: i32,
+}
+
+impl Default for KingSafetyWeights {
+    fn default() -> Self {
+        KingSafetyWeights {
+            pawn_close: 10,
+            pawn_far: 5,
+            pawn_missing: -20,
+            half_open_file: -10,
+            open_file: -25,
+        }
+    }
+}
+
+/// How the material of the two sides differs, as returned from `imbalance`.
+///
+/// The counts are white's pieces of each kind minus black's, so `pawns: -2` means black has two
+/// pawns more.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct MaterialImbalance {
+    pub pawns: i32,
+    pub knights: i32,
+    pub bishops: i32,
+    pub rooks: i32,
+    pub queens: i32,
+    /// Whether white has bishops on both light and dark squares.
+    pub white_bishop_pair: bool,
+    /// Whether black has bishops on both light and dark squares.
+    pub black_bishop_pair: bool,
+}
+
+impl<'a> Game<'a> {
+    /// Returns the total material value of the pieces of the given color, in centipawns.
+    ///
+    pub fn material(&self, color: Color) -> i32 {
+        self.by_color(color)
+            .iter()
+            .map(|(_, piece)| piece.kind.value())
+            .sum()
+    }
+
+    /// Scores the position from the point of view of the given color by material alone, in
+    /// centipawns. This is much cheaper than `eval`, for callers like `quick_best_move` that have
+    /// to count every operation.
+    ///
+    pub fn material_only_eval(&self, color: Color) -> i32 {
+        self.material(color) - self.material(color.opposite())
+    }
+
+    /// Breaks the material balance down by piece kind, and tells which sides have the bishop
+    /// pair.
+    ///
+    pub fn imbalance(&self) -> MaterialImbalance {
+        let difference = |kind| {
+            self.by_kind_and_color(kind, Color::White).len() as i32
+                - self.by_kind_and_color(kind, Color::Black).len() as i32
+        };
+        let bishop_pair = |color| {
+            let light: Vec<bool> = self
+                .by_kind_and_color(Kind::Bishop, color)
+                .iter()
+                .map(|(pos, _)| (pos.0 + pos.1) % 2 == 1)
+                .collect();
+            light.contains(&true) && light.contains(&false)
+        };
+        MaterialImbalance {
+            pawns: difference(Kind::Pawn),
+            knights: difference(Kind::Knight),
+            bishops: difference(Kind::Bishop),
+            rooks: difference(Kind::Rook),
+            queens: difference(Kind::Queen),
+            white_bishop_pair: bishop_pair(Color::White),
+            black_bishop_pair: bishop_pair(Color::Black),
+        }
+    }
+
+    /// Checks whether the position should be evaluated as an endgame.
+    ///
+    /// This is the case when neither side has a queen, or every side that has a queen has no
+    /// rooks and at most one minor piece left.
+    ///
+    pub fn is_endgame(&self) -> bool {
+        [Color::White, Color::Black].iter().all(|&color| {
+            let pieces = self.by_color(color);
+            let count = |kinds: &[Kind]| {
+                pieces
+                    .iter()
+                    .filter(|(_, piece)| kinds.contains(&piece.kind))
+                    .count()
+            };
+            count(&[Kind::Queen]) == 0
+                || (count(&[Kind::Rook]) == 0 && count(&[Kind::Knight, Kind::Bishop]) <= 1)
+        })
+    }
+
+    /// Returns the sum of the default piece-square table entries for the pieces of the given color.
+    ///
+    pub fn positional_score(&self, color: Color) -> i32 {
+        self.positional_score_with(color, &PieceSquareTables::default())
+    }
+
+    /// Returns the sum of the given piece-square table entries for the pieces of the given color.
+    ///
+    pub fn positional_score_with(&self, color: Color, tables: &PieceSquareTables) -> i32 {
+        let endgame = self.is_endgame();
+        self.by_color(color)
+            .iter()
+            .map(|&(pos, piece)| tables.value(piece.kind, color, pos, endgame))
+            .sum()
+    }
+
+    /// Returns the files, as 0-indexed columns, that have no pawns of either color on them.
+    ///
+    pub fn open_files(&self) -> Vec<usize> {
+        let pawns = self.by_kind(Kind::Pawn);
+        (0..BOARD_SIZE)
+            .filter(|&file| pawns.iter().all(|(pos, _)| pos.0 != file))
+            .collect()
+    }
+
+    /// Returns the files, as 0-indexed columns, that have no pawns of the given color on them.
+    ///
+    pub fn half_open_files(&self, color: Color) -> Vec<usize> {
+        let pawns = self.by_kind_and_color(Kind::Pawn, color);
+        (0..BOARD_SIZE)
+            .filter(|&file| pawns.iter().all(|(pos, _)| pos.0 != file))
+            .collect()
+    }
+
+    /// Returns the squares of the pawns of the given color that share their file with another pawn
+    /// of the same color.
+    ///
+    pub fn doubled_pawns(&self, color: Color) -> Vec<(usize, usize)> {
+        let pawns = self.by_kind_and_color(Kind::Pawn, color);
+        pawns
+            .iter()
+            .filter(|(pos, _)| pawns.iter().filter(|(p, _)| p.0 == pos.0).count() > 1)
+            .map(|&(pos, _)| pos)
+            .collect()
+    }
+
+    /// Returns the squares of the pawns of the given color that have no friendly pawns on the
+    /// adjacent files.
+    ///
+    pub fn isolated_pawns(&self, color: Color) -> Vec<(usize, usize)> {
+        let pawns = self.by_kind_and_color(Kind::Pawn, color);
+        pawns
+            .iter()
+            .filter(|(pos, _)| {
+                pawns
+                    .iter()
+                    .all(|(p, _)| p.0 + 1 != pos.0 && p.0 != pos.0 + 1)
+            })
+            .map(|&(pos, _)| pos)
+            .collect()
+    }
+
+    /// Returns the squares of the pawns of the given color that have no enemy pawns in front of
+    /// them, on their own file or the adjacent ones.
+    ///
+    pub fn passed_pawns(&self, color: Color) -> Vec<(usize, usize)> {
+        let enemies = self.by_kind_and_color(Kind::Pawn, color.opposite());
+        self.by_kind_and_color(Kind::Pawn, color)
+            .iter()
+            .filter(|(pos, _)| {
+                enemies.iter().all(|(p, _)| {
+                    let ahead = match color {
+                        Color::White => p.1 > pos.1,
+                        Color::Black => p.1 < pos.1,
+                    };
+                    !ahead || (p.0 as isize - pos.0 as isize).abs() > 1
+                })
+            })
+            .map(|&(pos, _)| pos)
+            .collect()
+    }
+
+    /// Returns the outposts of the given color: squares in the enemy half of the board that are
+    /// defended by a friendly pawn and can never be attacked by an enemy pawn, because there are
+    /// no enemy pawns left in front of them on the adjacent files.
+    ///
+    /// Outposts are returned whether or not a piece stands on them, in the order of the board.
+    ///
+    pub fn outpost_squares(&self, color: Color) -> Vec<(usize, usize)> {
+        let pawns = self.by_kind_and_color(Kind::Pawn, color);
+        let enemies = self.by_kind_and_color(Kind::Pawn, color.opposite());
+        let adjacent = |a: usize, b: usize| a + 1 == b || b + 1 == a;
+
+        let mut squares = Vec::new();
+        for y in 0..BOARD_SIZE {
+            let (enemy_half, behind) = match color {
+                Color::White => (y >= BOARD_SIZE / 2, y.checked_sub(1)),
+                Color::Black => (y < BOARD_SIZE / 2, Some(y + 1)),
+            };
+            if !enemy_half {
+                continue;
+            }
+            for x in 0..BOARD_SIZE {
+                let defended = pawns
+                    .iter()
+                    .any(|(p, _)| Some(p.1) == behind && adjacent(p.0, x));
+                let challengeable = enemies.iter().any(|(p, _)| {
+                    adjacent(p.0, x)
+                        && match color {
+                            Color::White => p.1 > y,
+                            Color::Black => p.1 < y,
+                        }
+                });
+                if defended && !challengeable {
+                    squares.push((x, y));
+                }
+            }
+        }
+        squares
+    }
+
+    /// Scores the pawn shelter of the king of the given color with the default weights.
+    ///
+    pub fn king_safety(&self, color: Color) -> i32 {
+        self.king_safety_with(color, &KingSafetyWeights::default())
+    }
+
+    /// Scores the pawn shelter of the king of the given color with the given weights.
+    ///
+    /// Returns 0 if the given color has no king.
+    ///
+    pub fn king_safety_with(&self, color: Color, weights: &KingSafetyWeights) -> i32 {
+        let king = match self.by_kind_and_color(Kind::King, color).first() {
+            Some(&(pos, _)) => pos,
+            None => return 0,
+        };
+        let pawns = self.by_kind(Kind::Pawn);
+        let open = self.open_files();
+        let half_open = self.half_open_files(color);
+
+        let mut score = 0;
+        for file in king.0.saturating_sub(1)..BOARD_SIZE.min(king.0 + 2) {
+            let distance = pawns
+                .iter()
+                .filter(|(pos, piece)| pos.0 == file && piece.color == color)
+                .filter_map(|(pos, _)| match color {
+                    Color::White if pos.1 > king.1 => Some(pos.1 - king.1),
+                    Color::Black if pos.1 < king.1 => Some(king.1 - pos.1),
+                    _ => None,
+                })
+                .min();
+            score += match distance {
+                Some(1) => weights.pawn_close,
+                Some(2) => weights.pawn_far,
+                _ => weights.pawn_missing,
+            };
+            if open.contains(&file) {
+                score += weights.open_file;
+            } else if half_open.contains(&file) {
+                score += weights.half_open_file;
+            }
+        }
+        score
+    }
+
+    /// Scores the position from the point of view of the given color, combining material with the
+    /// default piece-square tables.
+    ///
+    /// A positive score means `color` is better, a negative score means the opponent is better.
+    ///
+    pub fn score_material_with_position(&self, color: Color) -> i32 {
+        let other = color.opposite();
+        self.material(color) + self.positional_score(color)
+            - self.material(other)
+            - self.positional_score(other)
+    }
+
+    /// Evaluates the position in centipawns from the point of view of the given color.
+    ///
+    /// Combines material, the piece-square tables, pawn structure, king safety (outside the
+    /// endgame) and mobility. A checkmate scores `MATE_SCORE`, or `-MATE_SCORE` if `color` is the
+    /// one mated, and stalemates and other dead draws score 0.
+    ///
+    pub fn eval(&self, color: Color) -> i32 {
+        if let Ok(Some((status, winner))) = self.try_check_victory() {
+            return match status {
+                VictoryStatus::Checkmate if winner == color => MATE_SCORE,
+                VictoryStatus::Checkmate => -MATE_SCORE,
+                _ => 0,
+            };
+        }
+        if self.insufficient_material() {
+            return 0;
+        }
+        self.score_material_with_position(color) + self.eval_terms(color)
+            - self.eval_terms(color.opposite())
+    }
+
+    /// The pawn structure, king safety and mobility terms of `eval` for one color.
+    fn eval_terms(&self, color: Color) -> i32 {
+        let mut score = DOUBLED_PAWN * self.doubled_pawns(color).len() as i32
+            + ISOLATED_PAWN * self.isolated_pawns(color).len() as i32
+            + PASSED_PAWN * self.passed_pawns(color).len() as i32;
+        if !self.is_endgame() {
+            score += self.king_safety(color);
+        }
+        let mobility: usize = self
+            .by_color(color)
+            .iter()
+            .filter(|(_, piece)| piece.kind != Kind::Pawn && piece.kind != Kind::King)
+            .map(|&(pos, piece)| {
+                self.attacks_from(piece.kind, color, pos)
+                    .into_iter()
+                    .filter(|&to| self.get_from_pos(to).filter(|p| p.color == color).is_none())
+                    .count()
+            })
+            .sum();
+        score + MOBILITY * mobility as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{BLACK, WHITE};
+
+    #[test]
+    fn imbalance() {
+        let even = Game::new().imbalance();
+        assert_eq!(
+            even,
+            MaterialImbalance {
+                white_bishop_pair: true,
+                black_bishop_pair: true,
+                ..MaterialImbalance::default()
+            }
+        );
+
+        // White gave the g1 knight for the f7 and h7 pawns.
+        let game =
+            Game::from_fen("rnbqkbnr/ppppp1p1/8/8/8/8/PPPPPPPP/RNBQKB1R w KQkq - 0 1").unwrap();
+        assert_eq!(
+            game.imbalance(),
+            MaterialImbalance {
+                pawns: 2,
+                knights: -1,
+                white_bishop_pair: true,
+                black_bishop_pair: true,
+                ..MaterialImbalance::default()
+            }
+        );
+
+        // Two bishops on dark squares aren't a pair.
+        let game = Game::from_fen("4kb2/8/3b4/8/8/8/8/2B1KB2 w - - 0 1").unwrap();
+        let imbalance = game.imbalance();
+        assert!(imbalance.white_bishop_pair && !imbalance.black_bishop_pair);
+        assert_eq!(imbalance.bishops, 0);
+    }
+
+    #[test]
+    fn outposts() {
+        // The knight on d5 is backed by the e4 pawn, and black's c and e pawns have moved past it.
+        let game = Game::from_fen("4k3/p5p1/8/2pNp3/4P3/8/8/4K3 w - - 0 1").unwrap();
+        let outposts = game.outpost_squares(Color::White);
+        assert!(outposts.contains(&(3, 4)));
+        // f5 is also defended by e4, but the g7 pawn can still challenge it.
+        assert!(!outposts.contains(&(5, 4)));
+        assert!(game.outpost_squares(Color::Black).contains(&(3, 3)));
+
+        assert!(Game::new().outpost_squares(Color::White).is_empty());
+    }
+
+    #[test]
+    fn eval() {
+        let game = Game::new();
+        assert_eq!(game.eval(Color::White), 0);
+        assert_eq!(game.eval(Color::Black), 0);
+
+        // Black is missing the queen.
+        let game =
+            Game::from_fen("rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let score = game.eval(Color::White);
+        assert!(score > 850 && score < 1000, "{}", score);
+        assert_eq!(game.eval(Color::Black), -score);
+
+        let mate = Game::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+        assert_eq!(mate.eval(Color::White), MATE_SCORE);
+        assert_eq!(mate.eval(Color::Black), -MATE_SCORE);
+
+        let stalemate = Game::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(stalemate.eval(Color::White), 0);
+        let dead = Game::from_fen("7k/8/6K1/8/8/8/8/2B5 w - - 0 1").unwrap();
+        assert_eq!(dead.eval(Color::White), 0);
+    }
+
+    #[test]
+    fn start_position_is_balanced() {
+        let game = Game::new();
+        assert_eq!(game.material(Color::White), game.material(Color::Black));
+        assert_eq!(
+            game.positional_score(Color::White),
+            game.positional_score(Color::Black)
+        );
+        assert_eq!(game.score_material_with_position(Color::White), 0);
+        assert!(!game.is_endgame());
+    }
+
+    #[test]
+    fn centralized_knight_beats_rim_knight() {
+        let mut center = Game::new_empty();
+        center.set_at_pos((3, 3), Some(&WHITE[2]));
+        let mut rim = Game::new_empty();
+        rim.set_at_pos((0, 3), Some(&WHITE[2]));
+
+        assert!(center.positional_score(Color::White) > rim.positional_score(Color::White));
+
+        // The tables are mirrored for black.
+        let mut black = Game::new_empty();
+        black.set_at_pos((3, 4), Some(&BLACK[2]));
+        assert_eq!(
+            black.positional_score(Color::Black),
+            center.positional_score(Color::White)
+        );
+    }
+
+    #[test]
+    fn custom_tables() {
+        let mut game = Game::new_empty();
+        game.set_at_pos((0, 3), Some(&WHITE[2]));
+        let mut tables = PieceSquareTables::default();
+        tables.knight[4][0] = 75;
+        assert_eq!(game.positional_score_with(Color::White, &tables), 75);
+    }
+
+    #[test]
+    fn open_files() {
+        let mut game = Game::new();
+        // 1. d4 e5 2. dxe5 d6 3. exd6 Qxd6
+        for m in &[
+            ((3, 1), (3, 3)),
+            ((4, 6), (4, 4)),
+            ((3, 3), (4, 4)),
+            ((3, 6), (3, 5)),
+            ((4, 4), (3, 5)),
+            ((3, 7), (3, 5)),
+        ] {
+            game.move_pieces(&[*m]);
+        }
+        assert_eq!(game.open_files(), vec![3]);
+        assert_eq!(game.half_open_files(Color::White), vec![3]);
+        assert_eq!(game.half_open_files(Color::Black), vec![3, 4]);
+        assert!(Game::new().open_files().is_empty());
+    }
+
+    #[test]
+    fn pawn_structure() {
+        let mut game = Game::new_empty();
+        // White: a2, c4, c5, h5. Black: b7, e3, g7, h7.
+        for &pos in &[(0, 1), (2, 3), (2, 4), (7, 4)] {
+            game.set_at_pos(pos, Some(&WHITE[0]));
+        }
+        for &pos in &[(1, 6), (4, 2), (6, 6), (7, 6)] {
+            game.set_at_pos(pos, Some(&BLACK[0]));
+        }
+
+        assert_eq!(game.doubled_pawns(Color::White), vec![(2, 3), (2, 4)]);
+        assert!(game.doubled_pawns(Color::Black).is_empty());
+
+        assert_eq!(
+            game.isolated_pawns(Color::White),
+            vec![(0, 1), (2, 3), (2, 4), (7, 4)]
+        );
+        assert_eq!(game.isolated_pawns(Color::Black), vec![(1, 6), (4, 2)]);
+
+        // The b7 pawn guards a2, c4 and c5, and g7 guards h5.
+        assert!(game.passed_pawns(Color::White).is_empty());
+        game.set_at_pos((1, 6), None);
+        assert_eq!(
+            game.passed_pawns(Color::White),
+            vec![(0, 1), (2, 3), (2, 4)]
+        );
+        // h5 holds up both g7 and h7, while nothing is left in front of e3.
+        assert_eq!(game.passed_pawns(Color::Black), vec![(4, 2)]);
+    }
+
+    #[test]
+    fn king_safety() {
+        let mut castled = Game::new_empty();
+        castled.set_at_pos((6, 0), Some(&WHITE[5]));
+        for &pos in &[(5, 1), (6, 1), (7, 1)] {
+            castled.set_at_pos(pos, Some(&WHITE[0]));
+        }
+        assert_eq!(castled.king_safety(Color::White), 30);
+
+        // g4 is too far forward to shelter the king and the h-file is open.
+        let mut exposed = Game::new_empty();
+        exposed.set_at_pos((6, 0), Some(&WHITE[5]));
+        for &pos in &[(5, 1), (6, 3)] {
+            exposed.set_at_pos(pos, Some(&WHITE[0]));
+        }
+        assert_eq!(exposed.king_safety(Color::White), 10 - 20 - 20 - 25);
+
+        // The same shelter for black, mirrored.
+        let mut black = Game::new_empty();
+        black.set_at_pos((6, 7), Some(&BLACK[5]));
+        for &pos in &[(5, 6), (6, 6), (7, 5)] {
+            black.set_at_pos(pos, Some(&BLACK[0]));
+        }
+        assert_eq!(black.king_safety(Color::Black), 25);
+
+        let weights = KingSafetyWeights {
+            open_file: -100,
+            ..KingSafetyWeights::default()
+        };
+        assert_eq!(
+            exposed.king_safety_with(Color::White, &weights),
+            10 - 20 - 20 - 100
+        );
+    }
+}
@@ -0,0 +1,601 @@
+use super::{pos_to_string, string_to_pos, Color, Game, Kind, Piece, BOARD_SIZE};
+
+/// The ways a FEN string can fail to parse.
+#[derive(PartialEq, Debug, Clone)]
+pub enum FenError {
+    /// The FEN had fewer than two or more than six space separated fields.
+    FieldCount(usize),
+    /// The piece placement didn't have exactly eight ranks.
+    RankCount(usize),
+    /// The rank, counted from the eighth, didn't describe exactly eight squares.
+    RankLength(usize),
+    /// The piece placement contained a character that isn't a piece or a digit.
+    InvalidPiece(char),
+    /// The active color wasn't `w` or `b`.
+    InvalidColor(String),
+    /// The castling field wasn't `-` or a combination of `KQkq`.
+    InvalidCastling(String),
+    /// The en passant field wasn't `-` or a square on the third or sixth rank.
+    InvalidEnPassant(String),
+    /// The halfmove clock wasn't a non-negative number.
+    InvalidHalfmoveClock(String),
+    /// The fullmove number wasn't a positive number.
+    InvalidFullmoveNumber(String),
+    /// A square in a JSON position wasn't a valid square, like `e4`.
+    InvalidSquare(String),
+}
+
+impl std::fmt::Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FenError::FieldCount(n) => {
+                write!(f, "expected 2 to 6 fields, found {}", n)
+            }
+            FenError::RankCount(n) => write!(f, "expected 8 ranks, found {}", n),
+            FenError::RankLength(rank) => {
+                write!(f, "rank {} doesn't have 8 squares", BOARD_SIZE - rank)
+            }
+            FenError::InvalidPiece(c) => write!(f, "invalid piece '{}'", c),
+            FenError::InvalidColor(s) => write!(f, "invalid active color '{}'", s),
+            FenError::InvalidCastling(s) => write!(f, "invalid castling availability '{}'", s),
+            FenError::InvalidEnPassant(s) => write!(f, "invalid en passant square '{}'", s),
+            FenError::InvalidHalfmoveClock(s) => write!(f, "invalid halfmove clock '{}'", s),
+            FenError::InvalidFullmoveNumber(s) => write!(f, "invalid fullmove number '{}'", s),
+            FenError::InvalidSquare(s) => write!(f, "invalid square '{}'", s),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+impl<'a> Game<'a> {
+    /// Creates a game from a position in [Forsyth-Edwards
+    /// Notation](https://en.wikipedia.org/wiki/Forsyth%E2%80%93Edwards_Notation) (FEN).
+    ///
+    /// The fullmove number is stored as the turn, and the halfmove clock as the counter used by
+    /// the fifty and seventy-five move rules. An en passant square is turned into the double pawn
+    /// push that allowed it, so it can be captured on the next move.
+    ///
+    /// Only the piece placement and active color are required. The castling availability and en
+    /// passant square default to `-`, the halfmove clock to 0 and the fullmove number to 1, so
+    /// the four field FEN many tools emit can be loaded as is. Fields can only be left out from
+    /// the end.
+    ///
+    pub fn from_fen(fen: &str) -> Result<Game<'a>, FenError> {
+        let fields = split_fields(fen)?;
+
+        let mut game = Game::new_empty();
+        game.board = parse_placement(fields[0])?;
+
+        let color = parse_color(fields[1])?;
+        game.last_color = color.opposite();
+
+        let [white_right, white_left, black_right, black_left] = parse_castling(fields[2])?;
+        game.white_can_castle_right = white_right;
+        game.white_can_castle_left = white_left;
+        game.black_can_castle_right = black_right;
+        game.black_can_castle_left = black_left;
+
+        if let Some(push) = parse_en_passant(fields[3], color)? {
+            game.last = push;
+        }
+        game.seventy_five_move_rule = parse_halfmove_clock(fields[4])?;
+        game.turn = parse_fullmove_number(fields[5])?;
+
+        game.board_history.clear();
+        game.save_board();
+        Ok(game)
+    }
+
+    /// Turns the current position into [Forsyth-Edwards
+    /// Notation](https://en.wikipedia.org/wiki/Forsyth%E2%80%93Edwards_Notation) (FEN).
+    ///
+    /// The side to move is the opposite of the color that moved last, and the en passant square is
+    /// given after every double pawn push, whether or not a capture is possible.
+    ///
+    pub fn to_fen(&self) -> String {
+        let mut ranks = Vec::new();
+        for y in (0..BOARD_SIZE).rev() {
+            let mut rank = String::new();
+            let mut empty = 0;
+            for x in 0..BOARD_SIZE {
+                match self.board[x][y] {
+                    Some(piece) => {
+                        if empty > 0 {
+                            rank.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        rank.push(piece_to_char(piece));
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                rank.push_str(&empty.to_string());
+            }
+            ranks.push(rank);
+        }
+
+        let color = match self.side_to_move() {
+            Color::White => "w",
+            Color::Black => "b",
+        };
+
+        let mut castling = String::new();
+        for &(flag, c) in &[
+            (self.white_can_castle_right, 'K'),
+            (self.white_can_castle_left, 'Q'),
+            (self.black_can_castle_right, 'k'),
+            (self.black_can_castle_left, 'q'),
+        ] {
+            if flag {
+                castling.push(c);
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        let passant = match self.en_passant_square() {
+            Some(pos) => pos_to_string(pos).unwrap().to_lowercase(),
+            None => "-".to_string(),
+        };
+
+        format!(
+            "{} {} {} {} {} {}",
+            ranks.join("/"),
+            color,
+            castling,
+            passant,
+            self.seventy_five_move_rule,
+            self.turn
+        )
+    }
+
+    /// Returns the FEN of the position like `to_fen`, but with the side to move and the fullmove
+    /// number worked out from the number of half-moves played since the game started.
+    ///
+    /// This is for callers that know how many moves were played but don't call `next_turn`, like
+    /// the contract, where the game's own fullmove number stays at its starting value. The count
+    /// starts from the side to move and fullmove number before the first move made with
+    /// `move_pieces`, or from the current ones if no moves have been made.
+    ///
+    pub fn fen_with_ply(&self, ply_count: usize) -> String {
+        let first_number = match self.undo_stack.first() {
+            Some(undo) => undo.turn,
+            None => self.turn,
+        };
+        // Count as if the game had started with a white move.
+        let ply = match self.color_to_move_after(0) {
+            Color::White => ply_count,
+            Color::Black => ply_count + 1,
+        };
+        let color = match self.color_to_move_after(ply_count) {
+            Color::White => "w",
+            Color::Black => "b",
+        };
+
+        let fen = self.to_fen();
+        let mut fields: Vec<&str> = fen.split(' ').collect();
+        let number = (first_number as usize + ply / 2).to_string();
+        fields[1] = color;
+        fields[5] = &number;
+        fields.join(" ")
+    }
+
+    /// Sets up the double pawn push that makes the given square, like `e3`, capturable en passant
+    /// by the given color.
+    pub(crate) fn set_en_passant_square(
+        &mut self,
+        square: &str,
+        color: Color,
+    ) -> Result<(), FenError> {
+        self.last = en_passant_push(square, color)?;
+        Ok(())
+    }
+
+    /// Returns the square a pawn skipped over with a double push on the last move, if any.
+    pub(crate) fn en_passant_square(&self) -> Option<(usize, usize)> {
+        let (from, to) = self.last;
+        match self.get_from_pos(to) {
+            Some(piece)
+                if piece.kind == Kind::Pawn
+                    && from.0 == to.0
+                    && (from.1 as isize - to.1 as isize).abs() == 2 =>
+            {
+                Some((from.0, (from.1 + to.1) / 2))
+            }
+            _ => None,
+        }
+    }
+}
+
+type Board<'a> = [[Option<&'a Piece>; BOARD_SIZE]; BOARD_SIZE];
+
+/// Checks a FEN string for structural and range errors without setting up a game: the number of
+/// fields and ranks, the piece letters, the active color, the castling and en passant fields and
+/// the two clocks.
+///
+/// The position itself isn't checked, so a FEN without kings or with pawns on the first rank
+/// passes.
+///
+pub fn validate_fen(fen: &str) -> Result<(), FenError> {
+    let fields = split_fields(fen)?;
+    walk_placement(fields[0], |_, _| {})?;
+    let color = parse_color(fields[1])?;
+    parse_castling(fields[2])?;
+    parse_en_passant(fields[3], color)?;
+    parse_halfmove_clock(fields[4])?;
+    parse_fullmove_number(fields[5])?;
+    Ok(())
+}
+
+/// The values used for the optional trailing fields when a FEN leaves them out.
+const FIELD_DEFAULTS: [&str; 6] = ["", "", "-", "-", "0", "1"];
+
+/// Splits a FEN into its six fields, filling in any missing trailing fields after the active
+/// color with their defaults.
+fn split_fields(fen: &str) -> Result<Vec<&str>, FenError> {
+    let mut fields: Vec<&str> = fen.split_whitespace().collect();
+    if fields.len() < 2 || fields.len() > FIELD_DEFAULTS.len() {
+        return Err(FenError::FieldCount(fields.len()));
+    }
+    fields.extend_from_slice(&FIELD_DEFAULTS[fields.len()..]);
+    Ok(fields)
+}
+
+fn parse_placement<'a>(placement: &str) -> Result<Board<'a>, FenError> {
+    let mut board: Board<'a> = [[None; BOARD_SIZE]; BOARD_SIZE];
+    walk_placement(placement, |(x, y), piece| board[x][y] = Some(piece))?;
+    Ok(board)
+}
+
+/// Reads the piece placement field, calling `place` with every piece and its square.
+fn walk_placement<F>(placement: &str, mut place: F) -> Result<(), FenError>
+where
+    F: FnMut((usize, usize), &'static Piece),
+{
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != BOARD_SIZE {
+        return Err(FenError::RankCount(ranks.len()));
+    }
+
+    for (i, rank) in ranks.iter().enumerate() {
+        let y = BOARD_SIZE - 1 - i;
+        let mut x = 0;
+        for c in rank.chars() {
+            if let Some(n) = c.to_digit(10) {
+                if n == 0 {
+                    return Err(FenError::InvalidPiece(c));
+                }
+                x += n as usize;
+            } else {
+                let piece = piece_from_char(c).ok_or(FenError::InvalidPiece(c))?;
+                if x >= BOARD_SIZE {
+                    return Err(FenError::RankLength(i));
+                }
+                place((x, y), piece);
+                x += 1;
+            }
+            if x > BOARD_SIZE {
+                return Err(FenError::RankLength(i));
+            }
+        }
+        if x != BOARD_SIZE {
+            return Err(FenError::RankLength(i));
+        }
+    }
+    Ok(())
+}
+
+fn parse_color(field: &str) -> Result<Color, FenError> {
+    match field {
+        "w" => Ok(Color::White),
+        "b" => Ok(Color::Black),
+        other => Err(FenError::InvalidColor(other.to_string())),
+    }
+}
+
+/// Reads the castling field into the white kingside, white queenside, black kingside and black
+/// queenside rights.
+fn parse_castling(field: &str) -> Result<[bool; 4], FenError> {
+    let mut rights = [false; 4];
+    if field == "-" {
+        return Ok(rights);
+    }
+    for c in field.chars() {
+        let flag = match c {
+            'K' => &mut rights[0],
+            'Q' => &mut rights[1],
+            'k' => &mut rights[2],
+            'q' => &mut rights[3],
+            _ => return Err(FenError::InvalidCastling(field.to_string())),
+        };
+        if *flag {
+            return Err(FenError::InvalidCastling(field.to_string()));
+        }
+        *flag = true;
+    }
+    Ok(rights)
+}
+
+/// Reads the en passant field, returning the double pawn push that allowed it, if any.
+fn parse_en_passant(
+    field: &str,
+    color: Color,
+) -> Result<Option<((usize, usize), (usize, usize))>, FenError> {
+    if field == "-" {
+        return Ok(None);
+    }
+    en_passant_push(field, color).map(Some)
+}
+
+/// Returns the double pawn push that makes the given square capturable en passant by the given
+/// color.
+fn en_passant_push(
+    square: &str,
+    color: Color,
+) -> Result<((usize, usize), (usize, usize)), FenError> {
+    let pos = match string_to_pos(square) {
+        Ok(pos) => pos,
+        Err(_) => return Err(FenError::InvalidEnPassant(square.to_string())),
+    };
+    match color {
+        Color::White if pos.1 == 5 => Ok(((pos.0, 6), (pos.0, 4))),
+        Color::Black if pos.1 == 2 => Ok(((pos.0, 1), (pos.0, 3))),
+        _ => Err(FenError::InvalidEnPassant(square.to_string())),
+    }
+}
+
+fn parse_halfmove_clock(field: &str) -> Result<u32, FenError> {
+    field
+        .parse()
+        .map_err(|_| FenError::InvalidHalfmoveClock(field.to_string()))
+}
+
+fn parse_fullmove_number(field: &str) -> Result<u32, FenError> {
+    match field.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(FenError::InvalidFullmoveNumber(field.to_string())),
+    }
+}
+
+/// Returns the FEN letter for the given piece, where uppercase letters are white pieces.
+pub(crate) fn piece_to_char(piece: &Piece) -> char {
+    let c = match piece.kind {
+        Kind::Pawn => 'p',
+        Kind::Rook => 'r',
+        Kind::Knight => 'n',
+        Kind::Bishop => 'b',
+        Kind::Queen => 'q',
+        Kind::King => 'k',
+    };
+    match piece.color {
+        Color::White => c.to_ascii_uppercase(),
+        Color::Black => c,
+    }
+}
+
+/// Returns the piece for the given FEN letter, where uppercase letters are white pieces.
+pub(crate) fn piece_from_char(c: char) -> Option<&'static Piece> {
+    let color = if c.is_ascii_uppercase() {
+        Color::White
+    } else {
+        Color::Black
+    };
+    let kind = match c.to_ascii_lowercase() {
+        'p' => Kind::Pawn,
+        'r' => Kind::Rook,
+        'n' => Kind::Knight,
+        'b' => Kind::Bishop,
+        'q' => Kind::Queen,
+        'k' => Kind::King,
+        _ => return None,
+    };
+    Some(Piece::get(color, kind))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::START_FEN;
+
+    /// A small xorshift generator, so the random games are the same on every run.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+    }
+
+    /// Plays `games` random games of up to `plies` halfmoves each, calling `check` after every
+    /// move.
+    fn random_games(seed: u64, games: usize, plies: usize, check: impl Fn(&Game)) {
+        let mut rng = Rng(seed);
+        for _ in 0..games {
+            let mut game = Game::new();
+            for _ in 0..plies {
+                let color = game.side_to_move();
+                let moves = game.all_valid_moves(color);
+                if moves.is_empty() {
+                    break;
+                }
+                game.move_pieces(&moves[rng.below(moves.len())]);
+                if color == Color::Black {
+                    game.next_turn();
+                }
+                check(&game);
+            }
+        }
+    }
+
+    #[test]
+    fn random_games_round_trip() {
+        random_games(0x5eed, 12, 60, |game| {
+            let fen = game.to_fen();
+            let parsed = Game::from_fen(&fen).unwrap();
+            assert!(parsed == *game, "{}", fen);
+            assert_eq!(parsed.to_fen(), fen);
+        });
+    }
+
+    #[test]
+    fn to_fen() {
+        let mut game = Game::new();
+        assert_eq!(game.to_fen(), START_FEN);
+        game.move_pieces(&[((4, 1), (4, 3))]);
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        game.move_pieces(&[((6, 7), (5, 5))]);
+        game.next_turn();
+        game.move_pieces(&[((4, 0), (4, 1))]);
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 2 2"
+        );
+
+        let mut empty = Game::new_empty();
+        empty.clear();
+        empty.white_can_castle_right = false;
+        empty.white_can_castle_left = false;
+        empty.black_can_castle_right = false;
+        empty.black_can_castle_left = false;
+        assert_eq!(empty.to_fen(), "8/8/8/8/8/8/8/8 w - - 0 1");
+
+        // Castling rights lost by moving the kings are written as `-` and survive a round trip.
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let mut game = Game::from_fen(fen).unwrap();
+        game.play((4, 0), (4, 1), None).unwrap();
+        game.play((4, 7), (4, 6), None).unwrap();
+        let fen = game.to_fen();
+        assert_eq!(fen, "r6r/4k3/8/8/8/8/4K3/R6R w - - 2 2");
+        let parsed = Game::from_fen(&fen).unwrap();
+        assert!(parsed.same_position_as(&game));
+        assert_eq!(parsed.to_fen(), fen);
+    }
+
+    #[test]
+    fn fen_with_ply() {
+        // The moves are made without `next_turn`, so `to_fen` stays at move 1.
+        let mut game = Game::new();
+        assert_eq!(game.fen_with_ply(0), START_FEN);
+        game.move_pieces(&[((4, 1), (4, 3))]);
+        game.move_pieces(&[((4, 6), (4, 4))]);
+        assert!(game.to_fen().ends_with(" w KQkq e6 0 1"));
+        assert_eq!(
+            game.fen_with_ply(2),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2"
+        );
+        game.move_pieces(&[((6, 0), (5, 2))]);
+        assert_eq!(
+            game.fen_with_ply(3),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/8/4K3 b - - 0 30").unwrap();
+        game.move_pieces(&[((4, 7), (3, 7))]);
+        assert_eq!(game.fen_with_ply(1), "3k4/8/8/8/8/8/8/4K3 w - - 1 31");
+        game.move_pieces(&[((4, 0), (3, 0))]);
+        assert_eq!(game.fen_with_ply(2), "3k4/8/8/8/8/8/8/3K4 b - - 2 31");
+    }
+
+    #[test]
+    fn start_position() {
+        let game =
+            Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        assert_eq!(game.board, Game::new().board);
+        assert_eq!(game.last_color, Color::Black);
+        assert!(game.white_can_castle_right && game.black_can_castle_left);
+        assert_eq!(game.get_turn(), 1);
+    }
+
+    #[test]
+    fn en_passant_and_clocks() {
+        let game =
+            Game::from_fen("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w Kq f6 0 3").unwrap();
+        assert_eq!(game.last_color, Color::Black);
+        assert!(game.white_can_castle_right && !game.white_can_castle_left);
+        assert!(!game.black_can_castle_right && game.black_can_castle_left);
+        assert_eq!(game.get_turn(), 3);
+        // e5xf6 is available, d5 was pushed earlier and can't be taken.
+        let moves = game.valid_moves((4, 4));
+        assert!(moves.contains(&vec![((4, 4), (5, 4)), ((5, 4), (5, 5))]));
+        assert!(!moves.iter().any(|m| m.last().unwrap().1 == (3, 5)));
+
+        let game = Game::from_fen("8/8/8/8/8/8/8/k6K b - - 42 60").unwrap();
+        assert_eq!(game.fifty_move_counter(), 42);
+        assert_eq!(game.last_color, Color::White);
+        assert_eq!(game.by_kind(Kind::King).len(), 2);
+    }
+
+    #[test]
+    fn optional_fields() {
+        let game =
+            Game::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3").unwrap();
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        assert_eq!(game.fifty_move_counter(), 0);
+        assert_eq!(game.get_turn(), 1);
+
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/4K3 w").unwrap();
+        assert_eq!(game.to_fen(), "4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(validate_fen("4k3/8/8/8/8/8/8/4K3 w - - 7"), Ok(()));
+    }
+
+    #[test]
+    fn errors() {
+        for (fen, err) in [
+            ("8/8/8/8/8/8/8/8", FenError::FieldCount(1)),
+            ("8/8/8/8/8/8/8/8 w - - 0 1 1", FenError::FieldCount(7)),
+            ("8/8/8/8/8/8/8 w - - 0 1", FenError::RankCount(7)),
+            ("8/8/8/9/8/8/8/8 w - - 0 1", FenError::RankLength(3)),
+            ("8/8/8/ppppppppp/8/8/8/8 w - - 0 1", FenError::RankLength(3)),
+            ("8/8/8/7/8/8/8/8 w - - 0 1", FenError::RankLength(3)),
+            ("8/8/8/8/8/8/8/7x w - - 0 1", FenError::InvalidPiece('x')),
+            (
+                "8/8/8/8/8/8/8/8 white - - 0 1",
+                FenError::InvalidColor("white".to_string()),
+            ),
+            (
+                "8/8/8/8/8/8/8/8 w KK - 0 1",
+                FenError::InvalidCastling("KK".to_string()),
+            ),
+            (
+                "8/8/8/8/8/8/8/8 w - e3 0 1",
+                FenError::InvalidEnPassant("e3".to_string()),
+            ),
+            (
+                "8/8/8/8/8/8/8/8 w - - -1 1",
+                FenError::InvalidHalfmoveClock("-1".to_string()),
+            ),
+            (
+                "8/8/8/8/8/8/8/8 w - - 4294967296 1",
+                FenError::InvalidHalfmoveClock("4294967296".to_string()),
+            ),
+            (
+                "8/8/8/8/8/8/8/8 w - - 0 4294967296",
+                FenError::InvalidFullmoveNumber("4294967296".to_string()),
+            ),
+            (
+                "8/8/8/8/8/8/8/8 w - - 0 0",
+                FenError::InvalidFullmoveNumber("0".to_string()),
+            ),
+        ]
+        .iter()
+        {
+            assert_eq!(Game::from_fen(fen).err(), Some(err.clone()), "{}", fen);
+            assert_eq!(validate_fen(fen), Err(err.clone()), "{}", fen);
+        }
+
+        assert_eq!(validate_fen(START_FEN), Ok(()));
+        assert_eq!(validate_fen("8/8/8/8/8/8/8/8 b Kq e3 12 40"), Ok(()));
+    }
+}
@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+
+use super::fen::FenError;
+use super::{pos_to_string, string_to_pos, Color, Game, Kind, Piece, BOARD_SIZE};
+
+/// A piece and the square it stands on, like `e4`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PieceDto {
+    pub square: String,
+    pub color: Color,
+    pub kind: Kind,
+}
+
+/// Which castles are still available.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CastlingRights {
+    pub white_kingside: bool,
+    pub white_queenside: bool,
+    pub black_kingside: bool,
+    pub black_queenside: bool,
+}
+
+/// A position in a form that is easy to consume as JSON, holding the same information as a FEN.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PositionDto {
+    /// The pieces on the board, from A1 through H1, then A2 through H2, until H8.
+    pub pieces: Vec<PieceDto>,
+    pub side_to_move: Color,
+    pub castling: CastlingRights,
+    /// The square a pawn skipped over with a double push on the last move, like `e3`.
+    pub en_passant: Option<String>,
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+}
+
+impl<'a> Game<'a> {
+    /// Exports the position as a serializable `PositionDto`.
+    ///
+    pub fn to_json(&self) -> PositionDto {
+        let mut pieces = Vec::new();
+        for y in 0..BOARD_SIZE {
+            for x in 0..BOARD_SIZE {
+                if let Some(piece) = self.board[x][y] {
+                    pieces.push(PieceDto {
+                        square: square_name((x, y)),
+                        color: piece.color,
+                        kind: piece.kind,
+                    });
+                }
+            }
+        }
+
+        PositionDto {
+            pieces,
+            side_to_move: self.side_to_move(),
+            castling: CastlingRights {
+                white_kingside: self.white_can_castle_right,
+                white_queenside: self.white_can_castle_left,
+                black_kingside: self.black_can_castle_right,
+                black_queenside: self.black_can_castle_left,
+            },
+            en_passant: self.en_passant_square().map(square_name),
+            halfmove_clock: self.seventy_five_move_rule,
+            fullmove_number: self.turn,
+        }
+    }
+
+    /// Creates a game from a `PositionDto`, as returned from `to_json`.
+    ///
+    /// If several pieces are given for the same square, the last one is used.
+    ///
+    pub fn from_json(position: &PositionDto) -> Result<Game<'a>, FenError> {
+        let mut game = Game::new_empty();
+        for p in &position.pieces {
+            let pos =
+                string_to_pos(&p.square).map_err(|_| FenError::InvalidSquare(p.square.clone()))?;
+            game.board[pos.0][pos.1] = Some(Piece::get(p.color, p.kind));
+        }
+
+        game.last_color = position.side_to_move.opposite();
+        game.white_can_castle_right = position.castling.white_kingside;
+        game.white_can_castle_left = position.castling.white_queenside;
+        game.black_can_castle_right = position.castling.black_kingside;
+        game.black_can_castle_left = position.castling.black_queenside;
+        if let Some(square) = &position.en_passant {
+            game.set_en_passant_square(square, position.side_to_move)?;
+        }
+        game.seventy_five_move_rule = position.halfmove_clock;
+        if position.fullmove_number == 0 {
+            return Err(FenError::InvalidFullmoveNumber("0".to_string()));
+        }
+        game.turn = position.fullmove_number;
+
+        game.board_history.clear();
+        game.save_board();
+        Ok(game)
+    }
+}
+
+fn square_name(pos: (usize, usize)) -> String {
+    pos_to_string(pos).unwrap().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::{from_slice, to_vec};
+
+    #[test]
+    fn round_trip() {
+        let game = Game::from_fen("r3k2r/ppp2ppp/8/3pP3/8/8/PPP2PPP/R3K2R w Kq d6 0 12").unwrap();
+        let dto = game.to_json();
+        assert_eq!(dto.pieces.len(), 20);
+        assert_eq!(
+            dto.pieces[0],
+            PieceDto {
+                square: "a1".to_string(),
+                color: Color::White,
+                kind: Kind::Rook,
+            }
+        );
+        assert_eq!(dto.en_passant, Some("d6".to_string()));
+        assert!(dto.castling.white_kingside && !dto.castling.white_queenside);
+
+        let bytes = to_vec(&dto).unwrap();
+        let json = String::from_utf8(bytes.clone()).unwrap();
+        assert!(json.contains(r#"{"square":"e1","color":"white","kind":"king"}"#));
+        assert!(json.contains(r#""side_to_move":"white""#));
+
+        let parsed = Game::from_json(&from_slice(&bytes).unwrap()).unwrap();
+        assert!(parsed == game);
+        assert_eq!(parsed.to_fen(), game.to_fen());
+    }
+
+    #[test]
+    fn invalid_square() {
+        let mut dto = Game::new().to_json();
+        dto.pieces[0].square = "i9".to_string();
+        assert_eq!(
+            Game::from_json(&dto).err(),
+            Some(FenError::InvalidSquare("i9".to_string()))
+        );
+    }
+}
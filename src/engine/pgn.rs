@@ -0,0 +1,374 @@
+use super::{Color, Game, VictoryStatus};
+
+/// The error returned when a move in a line of movetext can't be read or isn't legal.
+#[derive(PartialEq, Debug, Clone)]
+pub struct SanError(pub String);
+
+impl std::fmt::Display for SanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "invalid or illegal move '{}'", self.0)
+    }
+}
+
+impl std::error::Error for SanError {}
+
+/// The seven tag roster written at the top of a PGN file. Unknown values are written as `?`, as
+/// the PGN standard asks.
+#[derive(PartialEq, Debug, Clone)]
+pub struct PgnTags {
+    pub event: String,
+    pub site: String,
+    /// The date in `YYYY.MM.DD` form, with `??` for unknown parts.
+    pub date: String,
+    pub round: String,
+    pub white: String,
+    pub black: String,
+    /// `1-0`, `0-1`, `1/2-1/2`, or `*` for a game that is still going.
+    pub result: String,
+}
+
+impl Default for PgnTags {
+    fn default() -> Self {
+        PgnTags {
+            event: "?".to_string(),
+            site: "?".to_string(),
+            date: "????.??.??".to_string(),
+            round: "?".to_string(),
+            white: "?".to_string(),
+            black: "?".to_string(),
+            result: "*".to_string(),
+        }
+    }
+}
+
+/// The state of a game gathered in one call for analysis tools, from `export_for_analysis`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct AnalysisExport {
+    /// The FEN of the current position.
+    pub fen: String,
+    /// The moves of `history_san`, oldest first.
+    pub san: Vec<String>,
+    /// The score after each move in `san`, from white's point of view, or nothing if no search
+    /// depth was given.
+    pub evals: Vec<i32>,
+    /// The result from `check_victory`, or None if the game is still going.
+    pub result: Option<(VictoryStatus, Color)>,
+}
+
+/// Returns the move number written before a move in PGN movetext, like `1.` before white's first
+/// move. Black's moves normally get no number, except for a continuation like `1...` when it is
+/// the first move written, or when `resume` is set, for instance after a comment.
+///
+/// Plies count from 0. `first` is the color that made the first move and `first_number` its move
+/// number, as given by the fullmove field of the FEN the game started from.
+///
+pub fn move_number_prefix(ply: usize, first: Color, first_number: u32, resume: bool) -> String {
+    // Count as if the game had started with a white move.
+    let ply = match first {
+        Color::White => ply,
+        Color::Black => ply + 1,
+    };
+    let number = first_number as usize + ply / 2;
+    if ply % 2 == 0 {
+        format!("{}.", number)
+    } else if resume || (first == Color::Black && ply == 1) {
+        format!("{}...", number)
+    } else {
+        String::new()
+    }
+}
+
+impl<'a> Game<'a> {
+    /// Returns the moves made with `move_pieces` as PGN movetext, like `1. e4 e5 2. Nf3`.
+    ///
+    /// The numbering continues from the position the moves were made from, so a game that
+    /// started with black to move begins with `1...`.
+    ///
+    pub fn pgn_movetext(&self) -> String {
+        let (first, first_number) = match self.undo_stack.first() {
+            Some(undo) => (undo.last_color.opposite(), undo.turn),
+            None => (self.side_to_move(), self.turn),
+        };
+
+        let mut s = String::new();
+        for (ply, san) in self.history_san().iter().enumerate() {
+            let prefix = move_number_prefix(ply, first, first_number, false);
+            for part in &[&prefix[..], &san[..]] {
+                if part.is_empty() {
+                    continue;
+                }
+                if !s.is_empty() {
+                    s.push(' ');
+                }
+                s.push_str(part);
+            }
+        }
+        s
+    }
+
+    /// Exports the game as PGN: the tag pairs, a blank line, and the movetext from
+    /// `pgn_movetext` ending with the result.
+    ///
+    pub fn to_pgn(&self, tags: &PgnTags) -> String {
+        let mut s = String::new();
+        for (name, value) in &[
+            ("Event", &tags.event),
+            ("Site", &tags.site),
+            ("Date", &tags.date),
+            ("Round", &tags.round),
+            ("White", &tags.white),
+            ("Black", &tags.black),
+            ("Result", &tags.result),
+        ] {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            s.push_str(&format!("[{} \"{}\"]\n", name, value));
+        }
+        s.push('\n');
+
+        let movetext = self.pgn_movetext();
+        if !movetext.is_empty() {
+            s.push_str(&movetext);
+            s.push(' ');
+        }
+        s.push_str(&tags.result);
+        s.push('\n');
+        s
+    }
+
+    /// Loads a game from the standard starting position given in PGN, like the output of
+    /// `to_pgn`. Tag pairs are skipped, and the movetext is played with `apply_san_line`.
+    ///
+    pub fn from_pgn(pgn: &str) -> Result<Game<'a>, SanError> {
+        let movetext: Vec<&str> = pgn
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('['))
+            .collect();
+        let mut game = Game::new();
+        game.apply_san_line(&movetext.join(" "))?;
+        Ok(game)
+    }
+
+    /// Pairs each move of `history_san` with the score of the position it led to, searched
+    /// `depth` plies deep with `search_eval`.
+    ///
+    /// Scores are given from white's point of view, so the result can be drawn as an evaluation
+    /// graph, and large swings between consecutive entries point to blunders.
+    ///
+    pub fn san_history_with_evals(&self, depth: u32) -> Vec<(String, i32)> {
+        let mut game = self.clone();
+        let mut result = Vec::new();
+        while let Some(undo) = game.undo_stack.last() {
+            if let Some(san) = undo.san.clone() {
+                let score = game.search_eval(depth);
+                let score = match game.side_to_move() {
+                    Color::White => score,
+                    Color::Black => -score,
+                };
+                result.push((san, score));
+            }
+            game.undo_last();
+        }
+        result.reverse();
+        result
+    }
+
+    /// Gathers the FEN, the SAN of the moves, the score after each move and the result in one
+    /// call.
+    ///
+    /// The scores come from `san_history_with_evals` searched `eval_depth` plies deep, which is
+    /// by far the most expensive part, so they are only worked out if a depth is given.
+    ///
+    pub fn export_for_analysis(&self, eval_depth: Option<u32>) -> AnalysisExport {
+        let evals = match eval_depth {
+            Some(depth) => self
+                .san_history_with_evals(depth)
+                .into_iter()
+                .map(|(_, score)| score)
+                .collect(),
+            None => Vec::new(),
+        };
+        AnalysisExport {
+            fen: self.to_fen(),
+            san: self.history_san().to_vec(),
+            evals,
+            result: self.check_victory(),
+        }
+    }
+
+    /// Plays a line of movetext, like `1. e4 e5 2. Nf3`, and returns the FEN of the resulting
+    /// position.
+    ///
+    /// Move numbers, results and check or annotation symbols are skipped, and castling can be
+    /// written with letters or zeros. Moves are played for the side to move, advancing the turn
+    /// after black moves. On an error the moves before the failing one stay played.
+    ///
+    pub fn apply_san_line(&mut self, line: &str) -> Result<String, SanError> {
+        for token in line.split_whitespace() {
+            if is_move_number(token) || ["1-0", "0-1", "1/2-1/2", "*"].contains(&token) {
+                continue;
+            }
+            let error = || SanError(token.to_string());
+            if !token.is_ascii() {
+                return Err(error());
+            }
+            let san = token
+                .trim_end_matches(|c| "+#!?".contains(c))
+                .replace('O', "0");
+
+            let color = self.side_to_move();
+            let (mv, promotion) = self.an_to_move_promote(&san, color).ok_or_else(error)?;
+            // Promote through the default, so the history gets the right SAN.
+            let default = self.default_promotion;
+            self.default_promotion = promotion.unwrap_or(default);
+            self.move_pieces(&mv);
+            self.default_promotion = default;
+            if color == Color::Black {
+                self.next_turn();
+            }
+        }
+        Ok(self.to_fen())
+    }
+}
+
+/// Whether a movetext token is a move number, like `12.` or `12...`.
+fn is_move_number(token: &str) -> bool {
+    let digits = token.trim_end_matches('.');
+    digits.len() < token.len() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{MATE_SCORE, START_FEN};
+
+    #[test]
+    fn move_number_prefix() {
+        assert_eq!(super::move_number_prefix(0, Color::White, 1, false), "1.");
+        assert_eq!(super::move_number_prefix(1, Color::White, 1, false), "");
+        assert_eq!(super::move_number_prefix(1, Color::White, 1, true), "1...");
+        assert_eq!(super::move_number_prefix(2, Color::White, 1, false), "2.");
+        assert_eq!(super::move_number_prefix(0, Color::Black, 1, false), "1...");
+        assert_eq!(super::move_number_prefix(1, Color::Black, 1, false), "2.");
+        assert_eq!(super::move_number_prefix(2, Color::Black, 1, false), "");
+        assert_eq!(
+            super::move_number_prefix(0, Color::Black, 12, false),
+            "12..."
+        );
+    }
+
+    #[test]
+    fn movetext() {
+        let game = Game::from_uci_position(
+            "fen rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1 moves e7e5 g1f3 b8c6",
+        )
+        .unwrap();
+        assert_eq!(game.pgn_movetext(), "1... e5 2. Nf3 Nc6");
+
+        let game = Game::from_uci_position("startpos moves e2e4 e7e5 g1f3").unwrap();
+        assert_eq!(game.pgn_movetext(), "1. e4 e5 2. Nf3");
+        assert_eq!(Game::new().pgn_movetext(), "");
+    }
+
+    #[test]
+    fn san_history_with_evals() {
+        let mut game = Game::new();
+        game.apply_san_line("1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7#")
+            .unwrap();
+        let evals = game.san_history_with_evals(1);
+        assert_eq!(evals.len(), 7);
+        assert_eq!(evals[0].0, "e4");
+        assert_eq!(evals[6], ("Qxf7".to_string(), MATE_SCORE));
+        // 3... Nf6 is the blunder: the mate is found one ply ahead.
+        assert_eq!(evals[5].1, MATE_SCORE - 1);
+        assert!(evals[..5].iter().all(|(_, score)| score.abs() < 150));
+    }
+
+    #[test]
+    fn export_for_analysis() {
+        let mut game = Game::new();
+        game.apply_san_line("1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7#")
+            .unwrap();
+        let export = game.export_for_analysis(Some(1));
+        assert_eq!(
+            export.fen,
+            "r1bqkb1r/pppp1Qpp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 4"
+        );
+        assert_eq!(export.san.len(), 7);
+        assert_eq!(export.san[6], "Qxf7");
+        assert_eq!(export.evals.len(), export.san.len());
+        assert_eq!(export.evals[6], MATE_SCORE);
+        assert_eq!(
+            export.result,
+            Some((VictoryStatus::Checkmate, Color::White))
+        );
+
+        let export = game.export_for_analysis(None);
+        assert!(export.evals.is_empty());
+        assert_eq!(export.san.len(), 7);
+        assert_eq!(Game::new().export_for_analysis(Some(1)).result, None);
+    }
+
+    #[test]
+    fn to_pgn() {
+        let mut game = Game::new();
+        game.apply_san_line("1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7")
+            .unwrap();
+        let tags = PgnTags {
+            white: "mario".to_string(),
+            black: "bowser \"the king\"".to_string(),
+            result: "1-0".to_string(),
+            ..PgnTags::default()
+        };
+        let pgn = game.to_pgn(&tags);
+        assert_eq!(
+            pgn,
+            "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n\
+             [White \"mario\"]\n[Black \"bowser \\\"the king\\\"\"]\n[Result \"1-0\"]\n\n\
+             1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7 1-0\n"
+        );
+
+        let parsed = Game::from_pgn(&pgn).unwrap();
+        assert!(parsed == game);
+        assert_eq!(parsed.history_san(), game.history_san());
+
+        assert!(Game::new()
+            .to_pgn(&PgnTags::default())
+            .ends_with("[Result \"*\"]\n\n*\n"));
+    }
+
+    #[test]
+    fn apply_san_line() {
+        let mut game = Game::new();
+        assert_eq!(
+            game.apply_san_line("1. e4").unwrap(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+
+        let mut game = Game::new();
+        assert_eq!(
+            game.apply_san_line("1. e4 e5 2. Nf3 Nc6 3. Bc4 Nf6 4. O-O Bc5 5. d3 d6 *")
+                .unwrap(),
+            "r1bqk2r/ppp2ppp/2np1n2/2b1p3/2B1P3/3P1N2/PPP2PPP/RNBQ1RK1 w kq - 0 6"
+        );
+
+        let mut game = Game::from_fen("8/1P4k1/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            game.apply_san_line("1. b8=N+ Kf6").unwrap(),
+            "1N6/8/5k2/8/8/8/8/4K3 w - - 1 2"
+        );
+        assert_eq!(game.history_san(), ["b8=N", "Kf6"]);
+
+        let mut game = Game::new();
+        assert_eq!(
+            game.apply_san_line("1. e4 e4"),
+            Err(SanError("e4".to_string()))
+        );
+
+        let mut game = Game::new();
+        assert_eq!(
+            game.apply_san_line("1. Nf3=Q"),
+            Err(SanError("Nf3=Q".to_string()))
+        );
+        assert_eq!(game.to_fen(), START_FEN);
+    }
+}
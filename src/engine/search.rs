@@ -0,0 +1,745 @@
+use super::{Color, Game, Kind, Move, MATE_SCORE};
+
+/// Scores further from 0 than this are mates, which are stored in the transposition table
+/// relative to the position rather than the root.
+const MATE_BOUND: i32 = MATE_SCORE - 1000;
+
+/// The settings of `search`.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct SearchOptions {
+    /// How many plies to search ahead.
+    pub depth: u32,
+    /// The number of entries in the transposition table. 0 turns the table off.
+    pub table_size: usize,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            depth: 3,
+            table_size: 1 << 14,
+        }
+    }
+}
+
+/// The outcome of `search`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct SearchResult {
+    /// The best move found, or None if there are no legal moves or the depth was 0.
+    pub best_move: Option<Move>,
+    /// The score of the position for the side to move, as with `search_eval`.
+    pub score: i32,
+    /// The number of positions visited.
+    pub nodes: u64,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Clone)]
+struct Entry {
+    hash: u64,
+    depth: u32,
+    bound: Bound,
+    score: i32,
+    best_move: Option<Move>,
+}
+
+struct Searcher {
+    table: Vec<Option<Entry>>,
+    nodes: u64,
+    best_move: Option<Move>,
+}
+
+impl Searcher {
+    fn new(table_size: usize) -> Self {
+        Searcher {
+            table: vec![None; table_size],
+            nodes: 0,
+            best_move: None,
+        }
+    }
+
+    fn probe(&self, hash: u64) -> Option<&Entry> {
+        if self.table.is_empty() {
+            return None;
+        }
+        self.table[(hash % self.table.len() as u64) as usize]
+            .as_ref()
+            .filter(|entry| entry.hash == hash)
+    }
+
+    fn store(&mut self, entry: Entry) {
+        if self.table.is_empty() {
+            return;
+        }
+        let index = (entry.hash % self.table.len() as u64) as usize;
+        self.table[index] = Some(entry);
+    }
+
+    fn negamax(
+        &mut self,
+        game: &Game,
+        color: Color,
+        depth: u32,
+        ply: i32,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        self.nodes += 1;
+        if depth == 0 {
+            return match game.eval(color) {
+                MATE_SCORE => MATE_SCORE - ply,
+                score if score == -MATE_SCORE => ply - MATE_SCORE,
+                score => score,
+            };
+        }
+
+        let hash = if self.table.is_empty() {
+            0
+        } else {
+            game.zobrist_hash()
+        };
+        let mut table_move = None;
+        if let Some(entry) = self.probe(hash) {
+            let score = from_table(entry.score, ply);
+            if ply > 0 && entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {}
+                }
+            }
+            table_move = entry.best_move.clone();
+        }
+
+        let mut moves = game.all_valid_moves(color);
+        if moves.is_empty() {
+            return if game.in_check(color) {
+                ply - MATE_SCORE
+            } else {
+                0
+            };
+        }
+        if game.insufficient_material() {
+            return 0;
+        }
+        game.order_moves(&mut moves, color);
+        if let Some(i) = table_move.and_then(|m| moves.iter().position(|x| *x == m)) {
+            let m = moves.remove(i);
+            moves.insert(0, m);
+        }
+
+        let original_alpha = alpha;
+        let mut best_score = -MATE_SCORE;
+        let mut best_move = None;
+        for (i, m) in moves.into_iter().enumerate() {
+            let mut next = game.scratch();
+            next.move_pieces(&m);
+            let other = color.opposite();
+            let score = if i == 0 {
+                -self.negamax(&next, other, depth - 1, ply + 1, -beta, -alpha)
+            } else {
+                let score = -self.negamax(&next, other, depth - 1, ply + 1, -alpha - 1, -alpha);
+                if score > alpha && score < beta {
+                    -self.negamax(&next, other, depth - 1, ply + 1, -beta, -alpha)
+                } else {
+                    score
+                }
+            };
+            if score > best_score || best_move.is_none() {
+                best_score = score;
+                best_move = Some(m);
+            }
+            if score > alpha {
+                alpha = score;
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let bound = if best_score <= original_alpha {
+            Bound::Upper
+        } else if best_score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.store(Entry {
+            hash,
+            depth,
+            bound,
+            score: to_table(best_score, ply),
+            best_move: best_move.clone(),
+        });
+        if ply == 0 {
+            self.best_move = best_move;
+        }
+        best_score
+    }
+}
+
+fn to_table(score: i32, ply: i32) -> i32 {
+    if score > MATE_BOUND {
+        score + ply
+    } else if score < -MATE_BOUND {
+        score - ply
+    } else {
+        score
+    }
+}
+
+fn from_table(score: i32, ply: i32) -> i32 {
+    if score > MATE_BOUND {
+        score - ply
+    } else if score < -MATE_BOUND {
+        score + ply
+    } else {
+        score
+    }
+}
+
+impl<'a> Game<'a> {
+    /// Returns a move that checkmates the opponent of the given color right away, or None if there
+    /// is no such move.
+    ///
+    pub fn mate_in_one(&self, color: Color) -> Option<Move> {
+        self.mate_in_n(color, 1)
+    }
+
+    /// Returns a move that lets the given color force checkmate within `n` of its own moves, no
+    /// matter how the opponent replies, or None if there is no such move.
+    ///
+    /// This is an exhaustive search, so the cost grows exponentially with `n`. It is meant for
+    /// puzzles with few pieces and small values of `n`.
+    ///
+    pub fn mate_in_n(&self, color: Color, n: u32) -> Option<Move> {
+        if n == 0 {
+            return None;
+        }
+        let other = color.opposite();
+
+        let mut moves = self.all_valid_moves(color);
+        self.order_moves(&mut moves, color);
+        for m in moves {
+            let mut game = self.scratch();
+            game.move_pieces(&m);
+
+            let replies = game.all_valid_moves(other);
+            if replies.is_empty() {
+                if game.in_check(other) {
+                    return Some(m);
+                }
+                // Stalemate.
+                continue;
+            }
+
+            if n > 1
+                && replies.iter().all(|reply| {
+                    let mut next = game.clone();
+                    next.move_pieces(reply);
+                    next.mate_in_n(color, n - 1).is_some()
+                })
+            {
+                return Some(m);
+            }
+        }
+
+        None
+    }
+
+    /// Checks whether the given color can force a repetition by giving check on every move, for
+    /// instance to save a lost position. Only lines of up to `depth` of its own moves are searched.
+    ///
+    /// This is a practical detector rather than a proof: only checking moves are tried, and a line
+    /// counts as drawn as soon as a position occurs for the second time, on the assumption that
+    /// the same checks can be repeated. Running into checkmate also counts as success.
+    ///
+    pub fn is_perpetual_check(&self, color: Color, depth: u32) -> bool {
+        if depth == 0 {
+            return false;
+        }
+        let other = color.opposite();
+
+        for m in self.all_valid_moves(color) {
+            let mut game = self.scratch();
+            game.move_pieces(&m);
+            if !game.in_check(other) {
+                continue;
+            }
+            if game.repetitions() >= 2 {
+                return true;
+            }
+
+            let forced = game.all_valid_moves(other).iter().all(|reply| {
+                let mut next = game.scratch();
+                next.move_pieces(reply);
+                next.repetitions() >= 2 || next.is_perpetual_check(color, depth - 1)
+            });
+            if forced {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Scores the position for the side to move by searching `depth` plies ahead with alpha-beta
+    /// pruning, scoring the positions at the end of each line with `eval`. A depth of 0 is just
+    /// `eval`.
+    ///
+    /// A forced mate scores `MATE_SCORE` less the number of plies it takes, so quicker mates
+    /// score higher.
+    ///
+    pub fn search_eval(&self, depth: u32) -> i32 {
+        self.search(&SearchOptions {
+            depth,
+            table_size: 0,
+        })
+        .score
+    }
+
+    /// Returns the best move for the side to move found by searching `depth` plies ahead, or
+    /// None if it has no legal moves or `depth` is 0.
+    ///
+    pub fn best_move(&self, depth: u32) -> Option<Move> {
+        self.search(&SearchOptions {
+            depth,
+            ..SearchOptions::default()
+        })
+        .best_move
+    }
+
+    /// Searches the position for the side to move with principal variation search: the first
+    /// move of each position is searched with the full window and the rest with a null window,
+    /// only searching again when one of them turns out better.
+    ///
+    /// With a transposition table, positions reached again through another move order reuse the
+    /// earlier result, and the best move found for a position is tried first the next time.
+    ///
+    pub fn search(&self, options: &SearchOptions) -> SearchResult {
+        let mut searcher = Searcher::new(options.table_size);
+        let score = searcher.negamax(
+            self,
+            self.side_to_move(),
+            options.depth,
+            0,
+            -MATE_SCORE,
+            MATE_SCORE,
+        );
+        SearchResult {
+            best_move: searcher.best_move,
+            score,
+            nodes: searcher.nodes,
+        }
+    }
+
+    /// Picks a move for the side to move by material alone, as a cheap and deterministic
+    /// opponent. Each legal move is scored by the material balance after it, less the most the
+    /// opponent can then win with a single capture. Of equally scored moves the first one in
+    /// `all_valid_moves` order is taken, so the same position always gives the same move.
+    ///
+    /// Returns None if the side to move has no legal moves.
+    ///
+    pub fn quick_best_move(&self) -> Option<Move> {
+        let color = self.side_to_move();
+        let mut best: Option<(i32, Move)> = None;
+        for m in self.all_valid_moves(color) {
+            let mut game = self.scratch();
+            game.move_pieces(&m);
+            let score = game
+                .captures_only(color.opposite())
+                .iter()
+                .map(|reply| {
+                    let mut after = game.scratch();
+                    after.move_pieces(reply);
+                    after.material_only_eval(color)
+                })
+                .fold(game.material_only_eval(color), i32::min);
+            if best.as_ref().filter(|(best, _)| *best >= score).is_none() {
+                best = Some((score, m));
+            }
+        }
+        best.map(|(_, m)| m)
+    }
+
+    /// Searches `depth` plies ahead with the null window just below `beta`, which only tells
+    /// whether the side to move can score at least `beta`. This is much cheaper than working out
+    /// the exact score.
+    ///
+    pub fn null_window_search(&self, depth: u32, beta: i32) -> bool {
+        let mut searcher = Searcher::new(0);
+        searcher.negamax(self, self.side_to_move(), depth, 0, beta - 1, beta) >= beta
+    }
+
+    /// Sorts the moves of the given color so the ones most likely to be good are tried first:
+    /// captures come before quiet moves, ordered by the value of the captured piece, most valuable
+    /// first, and then by the value of the capturing piece, least valuable first (MVV-LVA).
+    ///
+    /// Quiet moves keep their relative order.
+    ///
+    pub fn order_moves(&self, moves: &mut [Move], color: Color) {
+        moves.sort_by_key(|m| {
+            let victim = m
+                .iter()
+                .filter_map(|v| self.get_from_pos(v.1))
+                .filter(|piece| piece.color != color)
+                .map(|piece| piece.kind.value())
+                .max();
+            let attacker = match self.get_from_pos(m[0].0) {
+                // Never worth less than what it takes, as losing it loses the game.
+                Some(piece) if piece.kind == Kind::King => i32::MAX,
+                Some(piece) => piece.kind.value(),
+                None => 0,
+            };
+            match victim {
+                Some(victim) => (0, -victim, attacker),
+                None => (1, 0, 0),
+            }
+        });
+    }
+
+    /// Works out the static exchange evaluation (SEE) on a square: the material the given color
+    /// wins, in centipawns, by capturing on `target` and letting both sides keep recapturing with
+    /// their least valuable attacker for as long as it pays off.
+    ///
+    /// The first capture is always made, so a negative result means it loses material. Returns 0
+    /// if there is no opposing piece on `target` or the given color doesn't attack it. Pieces
+    /// lined up behind each other, like doubled rooks, join in as the ones in front are traded
+    /// off. Pins are ignored.
+    ///
+    pub fn see(&self, target: (usize, usize), color: Color) -> i32 {
+        let mut victim = match self.get_from_pos(target) {
+            Some(piece) if piece.color != color => piece,
+            _ => return 0,
+        };
+        let mut board = self.scratch();
+        let mut gains = Vec::new();
+        let mut side = color;
+
+        loop {
+            let attacker = board
+                .by_color(side)
+                .into_iter()
+                .filter(|&(pos, _)| board.attacked_squares(pos).contains(&target))
+                .min_by_key(|&(_, piece)| exchange_value(piece.kind));
+            let (pos, piece) = match attacker {
+                Some(attacker) => attacker,
+                None => break,
+            };
+            gains.push(exchange_value(victim.kind));
+            board.board[pos.0][pos.1] = None;
+            board.board[target.0][target.1] = Some(piece);
+            victim = piece;
+            side = side.opposite();
+        }
+
+        // Going backwards, each side only recaptures if that doesn't lose material.
+        let mut score = 0;
+        for (i, gain) in gains.iter().enumerate().rev() {
+            score = gain - score;
+            if i > 0 {
+                score = score.max(0);
+            }
+        }
+        score
+    }
+
+    /// Returns the squares of the pieces of the given color that the opponent can win material
+    /// from by capturing: pieces that are attacked and not defended, or defended but still worth
+    /// taking, as worked out by `see`. The king is never included.
+    ///
+    pub fn hanging_pieces(&self, color: Color) -> Vec<(usize, usize)> {
+        self.by_color(color)
+            .into_iter()
+            .filter(|&(pos, piece)| piece.kind != Kind::King && self.see(pos, color.opposite()) > 0)
+            .map(|(pos, _)| pos)
+            .collect()
+    }
+
+    /// Returns the squares of the opposing pieces that a move leaves hanging, as worked out by
+    /// `hanging_pieces`, and that weren't hanging before it. This shows the point of a move, like
+    /// the two pieces a fork attacks.
+    ///
+    /// The move is played on a copy of the game. Returns an empty vector if there is no piece on
+    /// the square the move starts from.
+    ///
+    pub fn threats_after(&self, m: &[((usize, usize), (usize, usize))]) -> Vec<(usize, usize)> {
+        let color = match m.first().and_then(|v| self.get_from_pos(v.0)) {
+            Some(piece) => piece.color,
+            None => return Vec::new(),
+        };
+        let before = self.hanging_pieces(color.opposite());
+        let mut game = self.scratch();
+        game.move_pieces(m);
+        game.hanging_pieces(color.opposite())
+            .into_iter()
+            .filter(|pos| !before.contains(pos))
+            .collect()
+    }
+
+    /// Returns the legal moves of the given color that capture a piece, including en passant.
+    ///
+    /// This is the move generator for a quiescence search, which only follows captures once the
+    /// normal search depth runs out.
+    ///
+    pub fn captures_only(&self, color: Color) -> Vec<Move> {
+        self.all_valid_moves(color)
+            .into_iter()
+            .filter(|m| self.is_capture(m))
+            .collect()
+    }
+
+    /// Checks whether the side to move has no captures and no checking moves available, so a
+    /// static evaluation of the position can be trusted.
+    ///
+    pub fn is_quiet(&self) -> bool {
+        let color = self.side_to_move();
+        self.all_valid_moves(color).iter().all(|m| {
+            if self.is_capture(m) {
+                return false;
+            }
+            let mut game = self.scratch();
+            game.move_pieces(m);
+            !game.in_check(color.opposite())
+        })
+    }
+
+    /// Checks whether a move lands on an opposing piece. En passant counts, since its first step
+    /// lands on the pawn being taken.
+    fn is_capture(&self, m: &[((usize, usize), (usize, usize))]) -> bool {
+        let color = match self.get_from_pos(m[0].0) {
+            Some(piece) => piece.color,
+            None => return false,
+        };
+        m.iter().any(|v| {
+            self.get_from_pos(v.1)
+                .filter(|piece| piece.color != color)
+                .is_some()
+        })
+    }
+}
+
+/// The value of a piece in an exchange. The king is worth more than anything, so it only
+/// recaptures when nothing can take it back.
+fn exchange_value(kind: Kind) -> i32 {
+    match kind {
+        Kind::King => 100_000,
+        _ => kind.value(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{BLACK, WHITE};
+
+    #[test]
+    fn back_rank_mate_in_one() {
+        let mut game = Game::new_empty();
+        game.set_at_pos((6, 0), Some(&WHITE[5]));
+        game.set_at_pos((0, 0), Some(&WHITE[1]));
+        game.set_at_pos((6, 7), Some(&BLACK[5]));
+        for x in 5..8 {
+            game.set_at_pos((x, 6), Some(&BLACK[0]));
+        }
+
+        assert_eq!(game.mate_in_one(Color::White), Some(vec![((0, 0), (0, 7))]));
+        assert_eq!(game.mate_in_one(Color::Black), None);
+
+        game.move_pieces(&[((0, 0), (0, 7))]);
+        assert!(game.is_checkmate(Color::Black));
+        assert!(!game.is_checkmate(Color::White));
+    }
+
+    #[test]
+    fn mate_in_two_needs_a_quiet_move() {
+        let mut game = Game::new_empty();
+        game.set_at_pos((2, 5), Some(&WHITE[5]));
+        game.set_at_pos((1, 1), Some(&WHITE[1]));
+        game.set_at_pos((0, 7), Some(&BLACK[5]));
+
+        assert_eq!(game.mate_in_one(Color::White), None);
+        assert!(game.mate_in_n(Color::White, 2).is_some());
+    }
+
+    #[test]
+    fn queen_perpetual() {
+        // Black is two rooks up, but Qe8+ Kh7 Qh5+ Kg8 Qe8+ repeats.
+        let game = Game::from_fen("6k1/6p1/8/8/8/6K1/4Q3/rr6 w - - 0 1").unwrap();
+        assert!(game.is_perpetual_check(Color::White, 3));
+        assert!(!game.is_perpetual_check(Color::White, 2));
+        assert!(!game.is_perpetual_check(Color::White, 0));
+
+        assert!(!Game::new().is_perpetual_check(Color::White, 2));
+    }
+
+    #[test]
+    fn order_moves() {
+        // The e4 pawn can take the queen on d5, and the b1 knight the pawn on a3.
+        let game = Game::from_fen("4k3/8/8/3q4/4P3/p7/8/1N2K3 w - - 0 1").unwrap();
+        let mut moves = game.all_valid_moves(Color::White);
+        let mut sorted = moves.clone();
+        game.order_moves(&mut moves, Color::White);
+
+        assert_eq!(moves[0], vec![((4, 3), (3, 4))]);
+        assert_eq!(moves[1], vec![((1, 0), (0, 2))]);
+        let push = moves.iter().position(|m| m == &vec![((4, 3), (4, 4))]);
+        assert!(push.unwrap() > 1);
+
+        // Only the order changes.
+        moves.sort();
+        sorted.sort();
+        assert_eq!(moves, sorted);
+    }
+
+    #[test]
+    fn see() {
+        // Rxd5 Nxd5 trades a rook for a pawn.
+        let game = Game::from_fen("4k3/8/5n2/3p4/8/8/8/3RK3 w - - 0 1").unwrap();
+        assert_eq!(
+            game.see((3, 4), Color::White),
+            Kind::Pawn.value() - Kind::Rook.value()
+        );
+
+        // An undefended pawn is simply won.
+        let game = Game::from_fen("4k3/8/8/3p4/8/8/8/3RK3 w - - 0 1").unwrap();
+        assert_eq!(game.see((3, 4), Color::White), Kind::Pawn.value());
+
+        // The second rook joins in once the first one has been traded, so white keeps the pawn.
+        let game = Game::from_fen("3rk3/8/8/3p4/8/8/3R4/3RK3 w - - 0 1").unwrap();
+        assert_eq!(game.see((3, 4), Color::White), Kind::Pawn.value());
+
+        // exd5 cxd5 Rxd5 wins a knight, with a pawn traded on each side.
+        let game = Game::from_fen("4k3/8/2p5/3n4/4P3/8/8/3RK3 w - - 0 1").unwrap();
+        assert_eq!(game.see((3, 4), Color::White), Kind::Knight.value());
+
+        // Nothing to take, or nothing to take with.
+        assert_eq!(Game::new().see((4, 3), Color::White), 0);
+        assert_eq!(Game::new().see((4, 6), Color::White), 0);
+    }
+
+    #[test]
+    fn search_eval() {
+        let game = Game::new();
+        assert_eq!(game.search_eval(0), game.eval(Color::White));
+        assert!(game.search_eval(2).abs() < 100);
+
+        // Black to move wins the rook with 1... Qxd1+.
+        let game = Game::from_fen("3q2k1/5ppp/8/8/8/8/5PPP/3R2K1 b - - 0 1").unwrap();
+        assert!(game.search_eval(2) > 300);
+
+        // Ra8 mates in one ply, and the mate is seen from both sides.
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        assert_eq!(game.search_eval(1), MATE_SCORE - 1);
+        assert_eq!(game.search_eval(3), MATE_SCORE - 1);
+    }
+
+    #[test]
+    fn transposition_table() {
+        // Nxd5 wins the queen.
+        let game = Game::from_fen("4k3/8/8/3q4/8/2N5/8/4K2R w - - 0 1").unwrap();
+        let plain = game.search(&SearchOptions {
+            depth: 4,
+            table_size: 0,
+        });
+        let table = game.search(&SearchOptions {
+            depth: 4,
+            ..SearchOptions::default()
+        });
+        assert_eq!(plain.best_move, Some(vec![((2, 2), (3, 4))]));
+        assert_eq!(table.best_move, plain.best_move);
+        assert_eq!(table.score, plain.score);
+        assert!(table.nodes < plain.nodes, "{} {}", table.nodes, plain.nodes);
+        assert_eq!(game.best_move(2), plain.best_move);
+
+        assert!(game.null_window_search(2, 500));
+        assert!(!game.null_window_search(2, 2000));
+        assert_eq!(Game::new().best_move(0), None);
+    }
+
+    #[test]
+    fn hanging_pieces() {
+        let mut game = Game::from_uci_position("startpos moves e2e4 e7e5 f1c4").unwrap();
+        assert!(game.hanging_pieces(Color::White).is_empty());
+        assert!(game.hanging_pieces(Color::Black).is_empty());
+
+        // The knight attacks e4, which nothing defends.
+        game.apply_uci("g8f6").unwrap();
+        assert_eq!(game.hanging_pieces(Color::White), vec![(4, 3)]);
+        assert!(game.hanging_pieces(Color::Black).is_empty());
+
+        // The f7 pawn is defended by the king, so taking it with the bishop loses material.
+        game.apply_uci("d1f3").unwrap();
+        assert!(game.hanging_pieces(Color::Black).is_empty());
+    }
+
+    #[test]
+    fn threats_after() {
+        // Nc7 forks the rook on a8 and the queen on e8.
+        let game = Game::from_fen("r3q2k/8/8/1N6/8/8/8/6K1 w - - 0 1").unwrap();
+        assert_eq!(
+            game.threats_after(&[((1, 4), (2, 6))]),
+            vec![(0, 7), (4, 7)]
+        );
+        assert!(game.threats_after(&[((6, 0), (6, 1))]).is_empty());
+        assert!(game.threats_after(&[((3, 3), (3, 4))]).is_empty());
+
+        // A piece that was already hanging isn't a new threat.
+        let game = Game::from_fen("r6k/3q4/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        assert_eq!(game.hanging_pieces(Color::Black), vec![(0, 7)]);
+        assert!(game.threats_after(&[((6, 0), (6, 1))]).is_empty());
+    }
+
+    #[test]
+    fn quick_best_move() {
+        let game = Game::from_fen("4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1").unwrap();
+        assert_eq!(game.quick_best_move(), Some(vec![((3, 0), (3, 4))]));
+        assert_eq!(game.material_only_eval(Color::White), -400);
+
+        // The pawn on d5 is defended, so taking it loses the queen.
+        let game = Game::from_fen("4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1").unwrap();
+        assert_ne!(game.quick_best_move(), Some(vec![((3, 0), (3, 4))]));
+
+        // Nothing to win, so the first legal move is played.
+        let game = Game::new();
+        assert_eq!(
+            game.quick_best_move(),
+            game.all_valid_moves(Color::White).into_iter().next()
+        );
+        let game = Game::from_fen("k7/8/1Q6/8/8/8/8/K7 b - - 0 1").unwrap();
+        assert_eq!(game.quick_best_move(), None);
+    }
+
+    #[test]
+    fn captures_only() {
+        let game = Game::from_fen("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            game.captures_only(Color::White),
+            vec![vec![((4, 3), (3, 4))]]
+        );
+        assert_eq!(
+            game.captures_only(Color::Black),
+            vec![vec![((3, 4), (4, 3))]]
+        );
+        assert!(!game.is_quiet());
+
+        assert!(Game::new().captures_only(Color::White).is_empty());
+        assert!(Game::new().is_quiet());
+
+        // Qe2 has no captures, but can give check on h5.
+        let game = Game::from_fen("7k/8/8/8/8/8/4Q3/K7 w - - 0 1").unwrap();
+        assert!(game.captures_only(Color::White).is_empty());
+        assert!(!game.is_quiet());
+
+        // En passant is a capture.
+        let game = Game::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        assert_eq!(game.captures_only(Color::White).len(), 1);
+    }
+}
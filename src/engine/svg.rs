@@ -0,0 +1,105 @@
+use super::{piece_glyph, Color, Game, BOARD_SIZE};
+
+/// The width and height of a square in an SVG board, in pixels.
+const SQUARE: usize = 45;
+const LIGHT: &str = "#f0d9b5";
+const DARK: &str = "#b58863";
+const HIGHLIGHT: &str = "#cdd26a";
+
+impl<'a> Game<'a> {
+    /// Draws the current position as an SVG image, with the given color at the bottom.
+    ///
+    /// The pieces are drawn as text with the unicode symbols of `board_to_string`, so the image
+    /// needs no other files. The squares of the last move are highlighted.
+    ///
+    pub fn board_to_svg(&self, perspective: Color) -> String {
+        let size = SQUARE * BOARD_SIZE;
+        let mut s = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{0}\" viewBox=\"0 0 {0} {0}\">",
+            size
+        );
+
+        let (from, to) = self.last;
+        let highlighted = |pos| from != to && (pos == from || pos == to);
+        for row in 0..BOARD_SIZE {
+            for column in 0..BOARD_SIZE {
+                let pos = match perspective {
+                    Color::White => (column, BOARD_SIZE - 1 - row),
+                    Color::Black => (BOARD_SIZE - 1 - column, row),
+                };
+                let fill = if highlighted(pos) {
+                    HIGHLIGHT
+                } else if (pos.0 + pos.1) % 2 == 0 {
+                    DARK
+                } else {
+                    LIGHT
+                };
+                let (x, y) = (column * SQUARE, row * SQUARE);
+                s.push_str(&format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{2}\" height=\"{2}\" fill=\"{3}\"/>",
+                    x, y, SQUARE, fill
+                ));
+                if let Some(piece) = self.get_from_pos(pos) {
+                    s.push_str(&format!(
+                        "<text x=\"{}\" y=\"{}\" font-size=\"{}\" text-anchor=\"middle\" \
+                         dominant-baseline=\"central\">{}</text>",
+                        x + SQUARE / 2,
+                        y + SQUARE / 2,
+                        SQUARE * 4 / 5,
+                        piece_glyph(piece, true)
+                    ));
+                }
+            }
+        }
+
+        s.push_str("</svg>");
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that every tag is closed in the right order.
+    fn well_formed(svg: &str) -> bool {
+        let mut open = Vec::new();
+        for tag in svg.split('<').skip(1) {
+            let tag = &tag[..tag.find('>').unwrap()];
+            if let Some(name) = tag.strip_prefix('/') {
+                if open.pop() != Some(name) {
+                    return false;
+                }
+            } else if !tag.ends_with('/') {
+                open.push(tag.split_whitespace().next().unwrap());
+            }
+        }
+        open.is_empty()
+    }
+
+    #[test]
+    fn board_to_svg() {
+        let mut game = Game::new();
+        let svg = game.board_to_svg(Color::White);
+        assert!(svg.starts_with("<svg ") && svg.ends_with("</svg>"));
+        assert!(well_formed(&svg));
+        assert_eq!(svg.matches("<rect ").count(), 64);
+        assert_eq!(svg.matches("<text ").count(), 32);
+        assert!(!svg.contains(HIGHLIGHT));
+        // A8 is drawn first from white's side, with the black rook on it.
+        assert!(svg.contains(&format!(
+            "<rect x=\"0\" y=\"0\" width=\"45\" height=\"45\" fill=\"{}\"/><text x=\"22\" y=\"22\" font-size=\"36\" text-anchor=\"middle\" dominant-baseline=\"central\">\u{265c}</text>",
+            LIGHT
+        )));
+
+        game.move_pieces(&[((4, 1), (4, 3))]);
+        let svg = game.board_to_svg(Color::Black);
+        assert!(well_formed(&svg));
+        assert_eq!(svg.matches(HIGHLIGHT).count(), 2);
+        // H1 is drawn first from black's side, with the white rook on it.
+        assert!(svg.contains(&format!(
+            "<rect x=\"0\" y=\"0\" width=\"45\" height=\"45\" fill=\"{}\"/><text x=\"22\" y=\"22\" font-size=\"36\" text-anchor=\"middle\" dominant-baseline=\"central\">\u{2656}</text>",
+            LIGHT
+        )));
+    }
+}
@@ -0,0 +1,184 @@
+use super::fen::{piece_from_char, FenError};
+use super::{string_to_pos, Game};
+
+/// The starting position, as used by the `startpos` form of the UCI `position` command.
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// The ways a UCI `position` command or move can fail to apply.
+#[derive(PartialEq, Debug, Clone)]
+pub enum UciError {
+    /// The command didn't start with `startpos` or `fen`.
+    MissingPosition,
+    /// Something other than `moves` followed the position.
+    UnexpectedToken(String),
+    /// The FEN given to the `fen` form was invalid.
+    Fen(FenError),
+    /// The move wasn't written in UCI long algebraic notation, like `e2e4` or `e7e8q`.
+    InvalidMove(String),
+    /// The move isn't legal in the current position.
+    IllegalMove(String),
+}
+
+impl std::fmt::Display for UciError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            UciError::MissingPosition => write!(f, "expected 'startpos' or 'fen'"),
+            UciError::UnexpectedToken(s) => write!(f, "unexpected token '{}'", s),
+            UciError::Fen(e) => write!(f, "invalid FEN: {}", e),
+            UciError::InvalidMove(s) => write!(f, "invalid move '{}'", s),
+            UciError::IllegalMove(s) => write!(f, "illegal move '{}'", s),
+        }
+    }
+}
+
+impl std::error::Error for UciError {}
+
+impl From<FenError> for UciError {
+    fn from(e: FenError) -> Self {
+        UciError::Fen(e)
+    }
+}
+
+impl<'a> Game<'a> {
+    /// Builds the position described by the arguments of a UCI `position` command, like
+    /// `startpos moves e2e4 e7e5` or `fen <FEN> moves e1g1`.
+    ///
+    /// A leading `position` keyword is accepted and ignored.
+    ///
+    pub fn from_uci_position(cmd: &str) -> Result<Game<'a>, UciError> {
+        let mut tokens = cmd.split_whitespace().peekable();
+        if tokens.peek() == Some(&"position") {
+            tokens.next();
+        }
+
+        let mut game = match tokens.next() {
+            Some("startpos") => Game::from_fen(START_FEN)?,
+            Some("fen") => {
+                // The trailing FEN fields are optional, so the FEN runs up to the move list.
+                let mut fen = Vec::new();
+                while let Some(token) = tokens.next_if(|&t| t != "moves") {
+                    fen.push(token);
+                }
+                Game::from_fen(&fen.join(" "))?
+            }
+            _ => return Err(UciError::MissingPosition),
+        };
+
+        match tokens.next() {
+            None => return Ok(game),
+            Some("moves") => {}
+            Some(other) => return Err(UciError::UnexpectedToken(other.to_string())),
+        }
+        for m in tokens {
+            game.apply_uci(m)?;
+        }
+        Ok(game)
+    }
+
+    /// Plays a move given in UCI long algebraic notation, like `e2e4`, `e1g1` or `e7e8q`.
+    ///
+    /// The move is made with `play`, so it must be legal for the side to move and the history
+    /// records the piece a pawn is promoted to. Castling is given as the king's move, and a
+    /// promotion without a piece letter uses the default promotion.
+    ///
+    pub fn apply_uci(&mut self, m: &str) -> Result<(), UciError> {
+        let invalid = || UciError::InvalidMove(m.to_string());
+        if !m.is_ascii() || (m.len() != 4 && m.len() != 5) {
+            return Err(invalid());
+        }
+        let from = string_to_pos(&m[0..2]).map_err(|_| invalid())?;
+        let to = string_to_pos(&m[2..4]).map_err(|_| invalid())?;
+        let promotion = match m[4..].chars().next() {
+            Some(c) if "nbrq".contains(c) => piece_from_char(c).map(|piece| piece.kind),
+            Some(_) => return Err(invalid()),
+            None => None,
+        };
+
+        self.play(from, to, promotion)
+            .map_err(|_| UciError::IllegalMove(m.to_string()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{Color, BLACK, WHITE};
+
+    #[test]
+    fn startpos() {
+        let game = Game::from_uci_position("startpos").unwrap();
+        assert_eq!(game.board, Game::new().board);
+
+        let game =
+            Game::from_uci_position("position startpos moves e2e4 e7e5 g1f3 b8c6 f1c4 g8f6 e1g1")
+                .unwrap();
+        assert_eq!(game.get_from_pos((6, 0)), Some(&WHITE[5]));
+        assert_eq!(game.get_from_pos((5, 0)), Some(&WHITE[1]));
+        assert_eq!(game.get_from_pos((5, 5)), Some(&BLACK[2]));
+        assert_eq!(game.last_color, Color::White);
+        assert_eq!(game.get_turn(), 4);
+    }
+
+    #[test]
+    fn fen() {
+        let game = Game::from_uci_position("fen 4k3/1P6/8/8/8/8/8/4K3 w - - 0 1 moves b7b8n e8f7")
+            .unwrap();
+        assert_eq!(game.get_from_pos((1, 7)), Some(&WHITE[2]));
+        assert_eq!(game.get_from_pos((5, 6)), Some(&BLACK[5]));
+
+        // The history records the piece the pawn became.
+        let mut game = Game::from_fen("7k/4P3/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        game.apply_uci("e7e8n").unwrap();
+        assert_eq!(game.history_san(), ["e8=N"]);
+        assert_eq!(game.get_from_pos((4, 7)), Some(&WHITE[2]));
+
+        let game =
+            Game::from_uci_position("fen 4k3/1P6/8/8/8/8/8/4K3 w - - 0 1 moves b7b8").unwrap();
+        assert_eq!(game.get_from_pos((1, 7)), Some(&WHITE[4]));
+
+        // A FEN without the clocks ends at the move list.
+        let game = Game::from_uci_position(
+            "position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - moves e2e4 e7e5",
+        )
+        .unwrap();
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2"
+        );
+        let game = Game::from_uci_position("fen 4k3/8/8/8/8/8/8/4K3 b").unwrap();
+        assert_eq!(game.to_fen(), "4k3/8/8/8/8/8/8/4K3 b - - 0 1");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Game::from_uci_position("moves e2e4").err(),
+            Some(UciError::MissingPosition)
+        );
+        assert_eq!(
+            Game::from_uci_position("fen 8/8/8 w - - 0 1").err(),
+            Some(UciError::Fen(FenError::RankCount(3)))
+        );
+        assert_eq!(
+            Game::from_uci_position("fen 8/8/8/8/8/8/8/8 w - - 0 1 e2e4").err(),
+            Some(UciError::Fen(FenError::FieldCount(7)))
+        );
+        assert_eq!(
+            Game::from_uci_position("startpos e2e4").err(),
+            Some(UciError::UnexpectedToken("e2e4".to_string()))
+        );
+        assert_eq!(
+            Game::from_uci_position("startpos moves e2e5").err(),
+            Some(UciError::IllegalMove("e2e5".to_string()))
+        );
+        assert_eq!(
+            Game::from_uci_position("startpos moves e7e5").err(),
+            Some(UciError::IllegalMove("e7e5".to_string()))
+        );
+        assert_eq!(
+            Game::from_uci_position("startpos moves e2e4x").err(),
+            Some(UciError::InvalidMove("e2e4x".to_string()))
+        );
+    }
+}
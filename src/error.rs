@@ -3,6 +3,12 @@ use thiserror::Error;
 
 use cw_controllers::{AdminError, HookError};
 
+use crate::engine::{FenError, MissingKing};
+
+#[derive(Error, Debug, PartialEq)]
+#[error("Coordinate ({0}, {1}) is off the board")]
+pub struct InvalidCoord(pub u8, pub u8);
+
 #[derive(Error, Debug, PartialEq)]
 pub enum ContractError {
     #[error("{0}")]
@@ -20,6 +26,84 @@ pub enum ContractError {
     #[error("Blacklisted address used")]
     Blacklisted {},
 
+    #[error("One-step admin update is disabled, use ProposeAdmin")]
+    OneStepAdminDisabled {},
+
+    #[error("No admin transfer is pending")]
+    NoPendingAdmin {},
+
+    #[error("No finished match to rematch")]
+    NoFinishedMatch {},
+
+    #[error("These players already have a match running")]
+    MatchInProgress {},
+
+    #[error("Move was made for an earlier or later position")]
+    StaleMove {},
+
+    #[error("Illegal move")]
+    IllegalMove {},
+
+    #[error("Move is malformed")]
+    InvalidMove {},
+
+    #[error("It is not your turn")]
+    NotYourTurn {},
+
+    #[error("The match is already over")]
+    GameOver {},
+
+    #[error("No match found between these players")]
+    GameNotFound {},
+
+    #[error("A player can't play against themselves")]
+    SelfPlay {},
+
+    #[error("A draw can't be claimed in this position")]
+    DrawNotClaimable {},
+
+    #[error("{0}")]
+    InvalidCoord(#[from] InvalidCoord),
+
+    #[error("Invalid starting position: {0}")]
+    InvalidFen(#[from] FenError),
+
+    #[error("Invalid starting position: {0}")]
+    MissingKing(#[from] MissingKing),
+
     #[error("Unexplained")]
     Unexplained {},
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages() {
+        for (err, message) in [
+            (
+                ContractError::MatchInProgress {},
+                "These players already have a match running",
+            ),
+            (ContractError::InvalidMove {}, "Move is malformed"),
+            (ContractError::NotYourTurn {}, "It is not your turn"),
+            (ContractError::GameOver {}, "The match is already over"),
+            (
+                ContractError::GameNotFound {},
+                "No match found between these players",
+            ),
+            (
+                ContractError::SelfPlay {},
+                "A player can't play against themselves",
+            ),
+        ] {
+            assert_eq!(err.to_string(), message);
+        }
+        assert_ne!(ContractError::GameOver {}, ContractError::GameNotFound {});
+        assert_ne!(
+            ContractError::NotYourTurn {},
+            ContractError::Unauthorized {}
+        );
+    }
+}
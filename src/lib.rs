@@ -7,4 +7,4 @@ pub mod state;
 
 extern crate log;
 
-pub use crate::error::ContractError;
+pub use crate::error::{ContractError, InvalidCoord};
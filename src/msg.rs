@@ -1,16 +1,52 @@
 use crate::state::ChessMove;
+use cosmwasm_std::Addr;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq)]
 pub struct InstantiateMsg {
     pub admin: Option<String>,
+    /// Allows the admin to be replaced in one step with `UpdateAdmin`. Defaults to true.
+    pub one_step_admin_update: Option<bool>,
+    /// The FEN every match starts from. Defaults to the standard starting position. The host makes
+    /// the first move, so the host plays black if the FEN has black to move.
+    pub start_fen: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq)]
 pub enum QueryMsg {
     GetAdmin {},
-    CheckMatch { host: String, opponent: String },
+    CheckMatch {
+        host: String,
+        opponent: String,
+    },
+    /// Returns which draws the side to move could claim in a running match, as a
+    /// `DrawClaimsResponse`.
+    DrawClaims {
+        host: String,
+        opponent: String,
+    },
+}
+
+/// Returned as the data of `PlayMove` and `PlayMoves`, telling whether the moves ended the match.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct MoveResponse {
+    pub game_over: bool,
+    /// How the match ended, like "checkmate", if it is over.
+    pub result: Option<String>,
+    /// The player who won, if the match ended in a checkmate.
+    pub winner: Option<Addr>,
+}
+
+/// The draws that can be claimed in the current position of a match.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct DrawClaimsResponse {
+    /// Fifty moves by each side without a capture or a pawn move.
+    pub fifty_move: bool,
+    /// The same position for the third time.
+    pub threefold_repetition: bool,
+    /// Neither side has the material left to checkmate.
+    pub insufficient_material: bool,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq)]
@@ -19,13 +55,52 @@ pub enum ExecuteMsg {
     UpdateAdmin {
         admin: Option<String>,
     },
+    ProposeAdmin {
+        new_admin: String,
+    },
+    AcceptAdmin {},
     PlayMove {
         host: String,
         opponent: String,
         your_move: ChessMove,
+        /// The number of moves already played in the match. A move sent with any other number is
+        /// rejected, so a resubmitted message can't be played twice.
+        ply: u32,
+    },
+    /// Plays several moves in a row, for both sides. Every move is checked like `PlayMove`, and
+    /// the moves for the other player have to be agreed to by them first with `AgreeMoves`.
+    /// Either all moves are played or none are.
+    PlayMoves {
+        host: String,
+        opponent: String,
+        moves: Vec<ChessMove>,
+        /// The number of moves already played in the match, as in `PlayMove`.
+        ply: u32,
+    },
+    /// Agrees to a run of moves, like a series of forced recaptures, so the other player can play
+    /// it with `PlayMoves`. The batch has to hold exactly these moves, starting at the same ply.
+    /// A new agreement replaces the last one.
+    AgreeMoves {
+        host: String,
+        opponent: String,
+        moves: Vec<ChessMove>,
+        /// The number of moves already played in the match, as in `PlayMove`.
+        ply: u32,
     },
     StartMatch {
         opponent: String,
         first_move: ChessMove,
     },
+    /// Starts a new match against the opponent of a finished match, with the colors swapped. The
+    /// finished match is removed, so the next rematch swaps the colors of this one. Fails while
+    /// the two players have a match running.
+    Rematch {
+        previous_opponent: String,
+    },
+    /// Ends a match as a draw when neither side has the material left to checkmate. Either
+    /// player can claim it.
+    ClaimInsufficientMaterialDraw {
+        host: String,
+        opponent: String,
+    },
 }
@@ -1,14 +1,136 @@
 use cosmwasm_std::Addr;
 use cw_controllers::Admin;
-use cw_storage_plus::Map;
+use cw_storage_plus::{Item, Map};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::engine::{Kind, BOARD_SIZE};
+use crate::error::InvalidCoord;
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Copy, JsonSchema)]
 pub struct ChessMove {
     pub original: (u8, u8),
     pub new: (u8, u8),
+    /// The piece a pawn reaching the last rank turns into. Defaults to a queen.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub promotion: Option<Promotion>,
+}
+
+/// The pieces a pawn can be promoted to.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Copy, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Promotion {
+    Queen,
+    Rook,
+    Bishop,
+    Knight,
+}
+
+impl Promotion {
+    pub fn kind(self) -> Kind {
+        match self {
+            Promotion::Queen => Kind::Queen,
+            Promotion::Rook => Kind::Rook,
+            Promotion::Bishop => Kind::Bishop,
+            Promotion::Knight => Kind::Knight,
+        }
+    }
+}
+
+impl ChessMove {
+    /// Creates a move, checking that both coordinates lie on the board.
+    pub fn new(original: (u8, u8), new: (u8, u8)) -> Result<ChessMove, InvalidCoord> {
+        let chess_move = ChessMove {
+            original,
+            new,
+            promotion: None,
+        };
+        chess_move.validate()?;
+        Ok(chess_move)
+    }
+
+    /// Checks that both coordinates lie on the board, for moves that didn't come from `new`.
+    pub fn validate(&self) -> Result<(), InvalidCoord> {
+        for &(x, y) in &[self.original, self.new] {
+            if x as usize >= BOARD_SIZE || y as usize >= BOARD_SIZE {
+                return Err(InvalidCoord(x, y));
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the piece a promoting pawn turns into.
+    pub fn promote_to(mut self, promotion: Promotion) -> ChessMove {
+        self.promotion = Some(promotion);
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FinishedMatch {
+    pub moves: Vec<ChessMove>,
+    pub result: String,
+    pub winner: Option<Addr>,
+}
+
+/// A run of moves one player of a match agreed to, so the other player can play it with
+/// `PlayMoves`, including the moves that aren't theirs.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AgreedMoves {
+    pub player: Addr,
+    /// The number of moves played in the match when the run starts.
+    pub ply: u32,
+    pub moves: Vec<ChessMove>,
 }
 
 pub const ADMIN: Admin = Admin::new("admin");
+pub const PENDING_ADMIN: Item<Addr> = Item::new("pending_admin");
+pub const ONE_STEP_ADMIN: Item<bool> = Item::new("one_step_admin");
+pub const START_POSITION: Item<String> = Item::new("start_fen");
 pub const MATCHS: Map<(&Addr, &Addr), Vec<ChessMove>> = Map::new("match");
+pub const FINISHED: Map<(&Addr, &Addr), FinishedMatch> = Map::new("finished");
+pub const AGREED: Map<(&Addr, &Addr), AgreedMoves> = Map::new("agreed");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::{from_slice, to_vec};
+
+    #[test]
+    fn chess_move_round_trip() {
+        let mov = ChessMove::new((4, 1), (4, 3)).unwrap();
+        let bytes = to_vec(&mov).unwrap();
+        assert_eq!(bytes, br#"{"original":[4,1],"new":[4,3]}"#.to_vec());
+        assert_eq!(from_slice::<ChessMove>(&bytes).unwrap(), mov);
+
+        let finished = FinishedMatch {
+            moves: vec![mov],
+            result: "Checkmate".to_string(),
+            winner: Some(Addr::unchecked("host")),
+        };
+        let bytes = to_vec(&finished).unwrap();
+        assert_eq!(from_slice::<FinishedMatch>(&bytes).unwrap(), finished);
+
+        let promotion = ChessMove::new((1, 6), (1, 7))
+            .unwrap()
+            .promote_to(Promotion::Knight);
+        let bytes = to_vec(&promotion).unwrap();
+        assert_eq!(
+            bytes,
+            br#"{"original":[1,6],"new":[1,7],"promotion":"knight"}"#.to_vec()
+        );
+        assert_eq!(from_slice::<ChessMove>(&bytes).unwrap(), promotion);
+    }
+
+    #[test]
+    fn chess_move_rejects_off_board_coordinates() {
+        assert_eq!(ChessMove::new((8, 0), (0, 0)), Err(InvalidCoord(8, 0)));
+        assert_eq!(ChessMove::new((0, 0), (3, 8)), Err(InvalidCoord(3, 8)));
+        assert!(ChessMove::new((7, 7), (0, 0)).is_ok());
+
+        // Moves read from a message skip `new`, so they are checked with `validate`.
+        let mov: ChessMove = from_slice(br#"{"original":[4,1],"new":[4,9]}"#).unwrap();
+        assert_eq!(mov.validate(), Err(InvalidCoord(4, 9)));
+        assert_eq!(ChessMove::new((4, 1), (4, 3)).unwrap().validate(), Ok(()));
+    }
+}